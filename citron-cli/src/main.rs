@@ -1,13 +1,59 @@
 // use chesty_core::{explore_line, hash, Board, Position};
 
-use citron_core::{analysis::explore_line, move_gen::Move, Board, Position};
+use citron_core::{
+    analysis::{explore_line, SearchLimit},
+    book::{BookSelection, OpeningBook},
+    datagen::{DataGenerator, TrainingPosition},
+    epd::{EpdError, EpdPosition},
+    move_gen::Move,
+    pgn::{GameResult, Pgn},
+    piece::PieceKind,
+    tablebase::Tablebases,
+    tournament::{Adjudication, EngineConfig, Match, MatchScore, Sprt, SprtVerdict},
+    tuning::{load_positions, Tuner},
+    Board, BoardRenderer, EvalParams, PlayableTeam,
+};
 
-use clap::{App, Arg, SubCommand};
+#[cfg(feature = "nn_evaluation")]
+use citron_core::nnue::Network;
+
+use clap::{App, Arg, ArgMatches, SubCommand};
+
+use std::{
+    fmt::Write as _,
+    fs::File,
+    io::{BufReader, BufWriter, Write},
+    num::NonZeroUsize,
+    str::FromStr,
+    thread,
+    time::{Duration, Instant},
+};
 
 fn main() {
-    let matches = App::new("chesty-cli")
+    let app = App::new("chesty-cli")
         .version("0.1")
         .author("Elliot W")
+        .arg(
+            Arg::with_name("params")
+                .long("params")
+                .takes_value(true)
+                .global(true)
+                .help("A file of evaluation parameters to use, as written by tune"),
+        )
+        .arg(
+            Arg::with_name("syzygy")
+                .long("syzygy")
+                .takes_value(true)
+                .global(true)
+                .help("A directory of Syzygy tablebases to probe"),
+        )
+        .arg(
+            Arg::with_name("syzygy-probe-limit")
+                .long("syzygy-probe-limit")
+                .takes_value(true)
+                .global(true)
+                .help("The most pieces a position can have to be probed in the search (default of the largest tables)"),
+        )
         .subcommand(
             SubCommand::with_name("analyse")
                 .help("Gets the best move for a given FEN")
@@ -27,12 +73,67 @@ fn main() {
                     Arg::with_name("explore")
                         .short("e")
                         .help("Whether the whole line should be explored"),
+                )
+                .arg(
+                    Arg::with_name("book")
+                        .long("book")
+                        .takes_value(true)
+                        .help("A Polyglot opening book to play from before searching"),
+                )
+                .arg(
+                    Arg::with_name("book-selection")
+                        .long("book-selection")
+                        .takes_value(true)
+                        .possible_values(&["best", "weighted"])
+                        .help("How book moves are chosen (default of weighted)"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("eval")
+                .help("Prints a breakdown of the static evaluation of a given FEN")
+                .about("Prints a breakdown of the static evaluation of a given FEN")
+                .arg(
+                    Arg::with_name("fen")
+                        .takes_value(true)
+                        .required(true)
+                        .help("The input FEN"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("epd")
+                .help("Runs a suite of EPD test positions, reporting which are solved")
+                .about("Runs a suite of EPD test positions, reporting which are solved")
+                .arg(
+                    Arg::with_name("file")
+                        .takes_value(true)
+                        .required(true)
+                        .help("A file with an EPD position on each line"),
+                )
+                .arg(
+                    Arg::with_name("depth")
+                        .short("d")
+                        .long("depth")
+                        .takes_value(true)
+                        .conflicts_with("movetime")
+                        .help("The depth ply to search each position to (default of 8)"),
+                )
+                .arg(
+                    Arg::with_name("movetime")
+                        .short("m")
+                        .long("movetime")
+                        .takes_value(true)
+                        .help("The milliseconds to search each position for, instead of a depth"),
+                )
+                .arg(
+                    Arg::with_name("json")
+                        .long("json")
+                        .help("Whether the results are written as JSON"),
                 ),
         )
         .subcommand(
             SubCommand::with_name("play")
-                .help("Plays the game from a given FEN")
-                .about("Plays the game from a given FEN")
+                .help("Plays a game against the engine from a given FEN")
+                .about("Plays a game against the engine from a given FEN")
                 .arg(
                     Arg::with_name("fen")
                         .takes_value(true)
@@ -45,9 +146,322 @@ fn main() {
                         .long("depth")
                         .takes_value(true)
                         .help("The desired depth ply of the analysis (default of 8)"),
+                )
+                .arg(
+                    Arg::with_name("colour")
+                        .short("c")
+                        .long("colour")
+                        .takes_value(true)
+                        .possible_values(&["white", "black"])
+                        .help("The colour you play (default of white)"),
+                )
+                .arg(
+                    Arg::with_name("pgn")
+                        .long("pgn")
+                        .takes_value(true)
+                        .help("The file the game is saved to on exit, if any"),
+                )
+                .arg(
+                    Arg::with_name("book")
+                        .long("book")
+                        .takes_value(true)
+                        .help("A Polyglot opening book to play from before searching"),
+                )
+                .arg(
+                    Arg::with_name("book-selection")
+                        .long("book-selection")
+                        .takes_value(true)
+                        .possible_values(&["best", "weighted"])
+                        .help("How book moves are chosen (default of weighted)"),
                 ),
         )
-        .get_matches();
+        .subcommand(
+            SubCommand::with_name("match")
+                .help("Plays two configurations of the engine against each other")
+                .about("Plays two configurations of the engine against each other")
+                .arg(
+                    Arg::with_name("openings")
+                        .takes_value(true)
+                        .required(true)
+                        .help("A file with a FEN on each line, each played twice with colours reversed"),
+                )
+                .arg(
+                    Arg::with_name("depth1")
+                        .long("depth1")
+                        .takes_value(true)
+                        .conflicts_with_all(&["nodes1", "movetime1"])
+                        .help("The depth ply the first engine searches to (default of 6)"),
+                )
+                .arg(
+                    Arg::with_name("nodes1")
+                        .long("nodes1")
+                        .takes_value(true)
+                        .conflicts_with("movetime1")
+                        .help("The number of nodes the first engine searches for, instead of a depth"),
+                )
+                .arg(
+                    Arg::with_name("movetime1")
+                        .long("movetime1")
+                        .takes_value(true)
+                        .help("The milliseconds the first engine searches for, instead of a depth"),
+                )
+                .arg(
+                    Arg::with_name("values1")
+                        .long("values1")
+                        .takes_value(true)
+                        .help("The first engine's pawn, knight, bishop, rook and queen values, such as 100,300,300,500,900"),
+                )
+                .arg(
+                    Arg::with_name("depth2")
+                        .long("depth2")
+                        .takes_value(true)
+                        .conflicts_with_all(&["nodes2", "movetime2"])
+                        .help("The depth ply the second engine searches to (default of 6)"),
+                )
+                .arg(
+                    Arg::with_name("nodes2")
+                        .long("nodes2")
+                        .takes_value(true)
+                        .conflicts_with("movetime2")
+                        .help("The number of nodes the second engine searches for, instead of a depth"),
+                )
+                .arg(
+                    Arg::with_name("movetime2")
+                        .long("movetime2")
+                        .takes_value(true)
+                        .help("The milliseconds the second engine searches for, instead of a depth"),
+                )
+                .arg(
+                    Arg::with_name("values2")
+                        .long("values2")
+                        .takes_value(true)
+                        .help("The second engine's pawn, knight, bishop, rook and queen values, such as 100,300,300,500,900"),
+                )
+                .arg(
+                    Arg::with_name("games")
+                        .short("g")
+                        .long("games")
+                        .takes_value(true)
+                        .help("The most games to play (default of two for each opening)"),
+                )
+                .arg(
+                    Arg::with_name("threads")
+                        .short("t")
+                        .long("threads")
+                        .takes_value(true)
+                        .help("The number of games to play at once (default of the number of cores)"),
+                )
+                .arg(
+                    Arg::with_name("pgn")
+                        .long("pgn")
+                        .takes_value(true)
+                        .help("The file the games are written to (default of match.pgn)"),
+                )
+                .arg(
+                    Arg::with_name("max-plies")
+                        .long("max-plies")
+                        .takes_value(true)
+                        .help("The plies after which a game is drawn (default of 400)"),
+                )
+                .arg(
+                    Arg::with_name("adjudicate-score")
+                        .long("adjudicate-score")
+                        .takes_value(true)
+                        .help("The score in centipawns both engines must agree on for a game to be won (default of 1000)"),
+                )
+                .arg(
+                    Arg::with_name("adjudicate-plies")
+                        .long("adjudicate-plies")
+                        .takes_value(true)
+                        .help("The plies in a row the score must be agreed on for (default of 6)"),
+                )
+                .arg(
+                    Arg::with_name("elo0")
+                        .long("elo0")
+                        .takes_value(true)
+                        .help("The Elo difference of the SPRT's null hypothesis (default of 0)"),
+                )
+                .arg(
+                    Arg::with_name("elo1")
+                        .long("elo1")
+                        .takes_value(true)
+                        .help("The Elo difference of the SPRT's alternative hypothesis (default of 5)"),
+                )
+                .arg(
+                    Arg::with_name("alpha")
+                        .long("alpha")
+                        .takes_value(true)
+                        .help("The SPRT's false positive rate (default of 0.05)"),
+                )
+                .arg(
+                    Arg::with_name("beta")
+                        .long("beta")
+                        .takes_value(true)
+                        .help("The SPRT's false negative rate (default of 0.05)"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("tune")
+                .help("Tunes the evaluation parameters against labelled positions")
+                .about("Tunes the evaluation parameters against labelled positions")
+                .arg(
+                    Arg::with_name("positions")
+                        .takes_value(true)
+                        .required(true)
+                        .help("A file with a FEN and its game result on each line"),
+                )
+                .arg(
+                    Arg::with_name("output")
+                        .short("o")
+                        .long("output")
+                        .takes_value(true)
+                        .help(
+                            "The file the tuned parameters are written to (default of params.txt)",
+                        ),
+                )
+                .arg(
+                    Arg::with_name("iterations")
+                        .short("i")
+                        .long("iterations")
+                        .takes_value(true)
+                        .help("The most passes over the parameters to make (default of 100)"),
+                )
+                .arg(
+                    Arg::with_name("threads")
+                        .short("t")
+                        .long("threads")
+                        .takes_value(true)
+                        .help("The number of threads to evaluate positions with (default of 1)"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("datagen")
+                .help("Generates training data for the network from self-play games")
+                .about("Generates training data for the network from self-play games")
+                .arg(
+                    Arg::with_name("output")
+                        .short("o")
+                        .long("output")
+                        .takes_value(true)
+                        .help("The file positions are appended to (default of data.bin)"),
+                )
+                .arg(
+                    Arg::with_name("games")
+                        .short("g")
+                        .long("games")
+                        .takes_value(true)
+                        .help("The number of games to play (default of 100)"),
+                )
+                .arg(
+                    Arg::with_name("seed")
+                        .short("s")
+                        .long("seed")
+                        .takes_value(true)
+                        .help("The seed for the random openings (default of 0)"),
+                )
+                .arg(
+                    Arg::with_name("depth")
+                        .short("d")
+                        .long("depth")
+                        .takes_value(true)
+                        .conflicts_with("nodes")
+                        .help("The depth ply to search each move to (default of 6)"),
+                )
+                .arg(
+                    Arg::with_name("nodes")
+                        .short("n")
+                        .long("nodes")
+                        .takes_value(true)
+                        .help("The number of nodes to search each move for, instead of a depth"),
+                )
+                .arg(
+                    Arg::with_name("random-plies")
+                        .short("r")
+                        .long("random-plies")
+                        .takes_value(true)
+                        .help("The number of random moves each game opens with (default of 8)"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("convert")
+                .help("Converts training data written by datagen to text")
+                .about("Converts training data written by datagen to text")
+                .arg(
+                    Arg::with_name("input")
+                        .takes_value(true)
+                        .required(true)
+                        .help("The training data file"),
+                )
+                .arg(
+                    Arg::with_name("output")
+                        .short("o")
+                        .long("output")
+                        .takes_value(true)
+                        .help("The file the text is written to (default of stdout)"),
+                ),
+        );
+
+    #[cfg(feature = "nn_evaluation")]
+    let app = app.arg(
+        Arg::with_name("network")
+            .long("network")
+            .takes_value(true)
+            .global(true)
+            .help("A neural network file to evaluate with, instead of the hand written evaluation"),
+    );
+
+    let matches = app.get_matches();
+
+    let params = match matches.value_of("params") {
+        Some(path) => match EvalParams::load(path) {
+            Ok(params) => params,
+            Err(e) => {
+                eprintln!("Couldn't load parameters from {}: {}", path, e);
+                return;
+            }
+        },
+        None => EvalParams::default(),
+    };
+
+    // Boards keep their piece-square score up to date as pieces move, so
+    // the parameters must be installed before any are created
+    params.clone().install();
+
+    // Likewise for the network's accumulators
+    #[cfg(feature = "nn_evaluation")]
+    if let Some(path) = matches.value_of("network") {
+        match Network::load(path) {
+            Ok(network) => {
+                network.install();
+            }
+            Err(e) => {
+                eprintln!("Couldn't load the network from {}: {}", path, e);
+                return;
+            }
+        }
+    }
+
+    if let Some(directory) = matches.value_of("syzygy") {
+        match Tablebases::open(directory) {
+            Ok(mut tablebases) => {
+                if let Some(limit) = matches.value_of("syzygy-probe-limit") {
+                    match limit.parse() {
+                        Ok(limit) => tablebases.set_probe_limit(limit),
+                        Err(_) => {
+                            eprintln!("Invalid probe limit {}", limit);
+                            return;
+                        }
+                    }
+                }
+
+                tablebases.install();
+            }
+            Err(e) => {
+                eprintln!("Couldn't open the tablebases in {}: {}", directory, e);
+                return;
+            }
+        }
+    }
 
     match matches.subcommand() {
         ("analyse", Some(t)) => {
@@ -60,6 +474,18 @@ fn main() {
             let fen = t.value_of("fen").unwrap();
             let board = Board::from_fen(fen).unwrap();
 
+            let book = match load_book(t) {
+                Ok(book) => book,
+                Err(()) => return,
+            };
+
+            if let Some(book_move) = book.and_then(|(book, selection)| {
+                book.choose(&board, selection, &mut rand::thread_rng())
+            }) {
+                println!("Book move in position: {}", book_move);
+                return;
+            }
+
             let table = board.iterative_deepening_ply(depth);
 
             if t.is_present("explore") {
@@ -67,14 +493,85 @@ fn main() {
             } else {
                 let best = table.get(&board.hash()).unwrap();
 
-                // let (from, to) = best.best_move;
                 println!(
-                    "Best move in position: {:?} {}",
+                    "Best move in position: {} {}",
                     best.best_move,
                     best.evaluation.into_inner() as f64 / 100.
                 );
             }
         }
+        ("eval", Some(t)) => {
+            let fen = t.value_of("fen").unwrap();
+            let board = Board::from_fen(fen).unwrap();
+
+            println!("{}", board.evaluation_trace());
+        }
+        ("epd", Some(t)) => {
+            let path = t.value_of("file").unwrap();
+            let limit = match t.value_of("movetime").and_then(|time| time.parse().ok()) {
+                Some(time) => SearchLimit::Time(Duration::from_millis(time)),
+                None => SearchLimit::Depth(
+                    t.value_of("depth")
+                        .and_then(|depth| depth.parse().ok())
+                        .unwrap_or(8),
+                ),
+            };
+            let json = t.is_present("json");
+
+            let suite = match std::fs::read_to_string(path) {
+                Ok(suite) => suite,
+                Err(e) => {
+                    eprintln!("Couldn't read {}: {}", path, e);
+                    return;
+                }
+            };
+
+            let start = Instant::now();
+            let mut results = Vec::new();
+            let mut errors = Vec::new();
+
+            for (number, line) in suite.lines().enumerate() {
+                if line.trim().is_empty() {
+                    continue;
+                }
+
+                let epd = match EpdPosition::parse(line) {
+                    Ok(epd) => epd,
+                    Err(e) => {
+                        if !json {
+                            println!("Line {}: {}", number + 1, e);
+                        }
+
+                        errors.push((number + 1, e));
+                        continue;
+                    }
+                };
+
+                let result = EpdResult::search(epd, number + 1, limit);
+
+                if !json {
+                    println!("{}", result);
+                }
+
+                results.push(result);
+            }
+
+            let elapsed = start.elapsed();
+            let solved = results.iter().filter(|result| result.solved).count();
+
+            if json {
+                println!("{}", epd_json(&results, &errors, elapsed));
+            } else {
+                println!(
+                    "Solved {}/{} in {:.2}s, {} failed, {} unreadable",
+                    solved,
+                    results.len(),
+                    elapsed.as_secs_f64(),
+                    results.len() - solved,
+                    errors.len()
+                );
+            }
+        }
         ("play", Some(t)) => {
             let depth = if let Some(depth) = t.value_of("depth") {
                 depth.parse().unwrap_or(8)
@@ -85,61 +582,802 @@ fn main() {
             let fen = t
                 .value_of("fen")
                 .unwrap_or("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w - - 0 0");
-            let mut board = Board::from_fen(fen).unwrap();
+            let starting_board = if let Some(board) = Board::from_fen(fen) {
+                board
+            } else {
+                eprintln!("Invalid FEN {}", fen);
+                return;
+            };
 
-            println!("{} {}", board.material, board.absolute_material);
+            let human = match t.value_of("colour") {
+                Some("black") => PlayableTeam::Black,
+                _ => PlayableTeam::White,
+            };
+
+            let book = match load_book(t) {
+                Ok(book) => book,
+                Err(()) => return,
+            };
+            let mut rng = rand::thread_rng();
+
+            let mut board = starting_board.clone();
+            let mut history: Vec<PlayedMove> = Vec::new();
+            let mut flipped = human == PlayableTeam::Black;
+            let mut result = None;
+
+            println!("Enter moves such as e2e4 or Nf3, or help for the commands");
 
             loop {
-                let eval = board.iterative_deepening_ply(depth);
-                let best = eval.get(&board.hash()).unwrap();
+                if board.legal_moves().is_empty() {
+                    result = Some(if board.in_check(board.to_play()) {
+                        println!("Checkmate, {} wins", !board.to_play());
+                        GameResult::win_for(!board.to_play())
+                    } else {
+                        println!("Stalemate");
+                        GameResult::Draw
+                    });
+                    break;
+                }
+
+                let (played_move, annotation) = if board.to_play() == human {
+                    print_board(&board, flipped, &history);
+
+                    match read_command(&board) {
+                        Command::Move(played_move) => (played_move, Annotation::None),
+                        Command::Undo => {
+                            if history.is_empty() {
+                                println!("There's nothing to take back");
+                            }
+
+                            // Take back the engine's reply along with the
+                            // human's last move
+                            while let Some(played) = history.pop() {
+                                board = played.board;
+
+                                if board.to_play() == human {
+                                    break;
+                                }
+                            }
+                            continue;
+                        }
+                        Command::Hint => {
+                            let table = board.iterative_deepening_ply(depth);
+
+                            if let Some(best) = table.get(&board.hash()) {
+                                println!(
+                                    "Try {} ({:+.2})",
+                                    board.san(&best.best_move),
+                                    f64::from(best.evaluation.into_inner()) / 100.
+                                );
+                            }
+                            continue;
+                        }
+                        Command::Fen => {
+                            println!("{}", board.to_fen());
+                            continue;
+                        }
+                        Command::Flip => {
+                            flipped = !flipped;
+                            continue;
+                        }
+                        Command::Resign => {
+                            println!("{} resigns", human);
+                            result = Some(GameResult::win_for(!human));
+                            break;
+                        }
+                        Command::Quit => break,
+                    }
+                } else {
+                    let book_move = book
+                        .as_ref()
+                        .and_then(|(book, selection)| book.choose(&board, *selection, &mut rng));
+
+                    if let Some(book_move) = book_move {
+                        (book_move, Annotation::Book)
+                    } else {
+                        let table = board.iterative_deepening_ply(depth);
+                        let best = if let Some(best) = table.get(&board.hash()) {
+                            best
+                        } else {
+                            break;
+                        };
+
+                        (
+                            best.best_move.clone(),
+                            Annotation::Evaluation(best.evaluation.into_inner()),
+                        )
+                    }
+                };
+
+                match annotation {
+                    Annotation::Book => println!("citron plays {} (book)", board.san(&played_move)),
+                    Annotation::Evaluation(evaluation) => println!(
+                        "citron plays {} ({:+.2})",
+                        board.san(&played_move),
+                        f64::from(evaluation) / 100.
+                    ),
+                    Annotation::None => {}
+                }
+
+                let king_taken = played_move.captured_piece_kind() == PieceKind::King;
+                let next = board.make_move(&played_move).unwrap();
+
+                history.push(PlayedMove {
+                    board: std::mem::replace(&mut board, next),
+                    played_move,
+                    annotation,
+                });
+
+                if king_taken {
+                    println!("The king has been taken, {} wins", !board.to_play());
+                    result = Some(GameResult::win_for(!board.to_play()));
+                    break;
+                }
+            }
+
+            print_board(&board, flipped, &history);
+
+            let mut pgn = Pgn::from_board(&starting_board);
+            let (white, black) = match human {
+                PlayableTeam::White => ("Human", "citron"),
+                PlayableTeam::Black => ("citron", "Human"),
+            };
+            pgn.set_tag("Event", "citron-cli play");
+            pgn.set_tag("White", white);
+            pgn.set_tag("Black", black);
+
+            for played in &history {
+                pgn.add_move(&played.board, &played.played_move);
+
+                match played.annotation {
+                    Annotation::Book => pgn.add_comment("book"),
+                    Annotation::Evaluation(evaluation) => pgn.add_evaluation(evaluation, depth),
+                    Annotation::None => {}
+                }
+            }
+
+            if let Some(result) = result {
+                pgn.set_result(result);
+            }
+
+            if let Some(path) = t.value_of("pgn") {
+                match std::fs::write(path, pgn.to_string()) {
+                    Ok(()) => println!("Game saved to {}", path),
+                    Err(e) => eprintln!("Couldn't save the game to {}: {}", path, e),
+                }
+            }
+        }
+        ("match", Some(t)) => {
+            let path = t.value_of("openings").unwrap();
+            let file = match std::fs::read_to_string(path) {
+                Ok(file) => file,
+                Err(e) => {
+                    eprintln!("Couldn't read {}: {}", path, e);
+                    return;
+                }
+            };
+
+            let mut openings = Vec::new();
+
+            for (number, line) in file.lines().enumerate() {
+                if line.trim().is_empty() {
+                    continue;
+                }
+
+                // EPD, which leaves out the move counters, is read as well
+                match Board::from_fen(line.trim())
+                    .or_else(|| EpdPosition::parse(line).ok().map(|epd| epd.board))
+                {
+                    Some(board) => openings.push(board),
+                    None => {
+                        eprintln!("Invalid FEN on line {}: {}", number + 1, line);
+                        return;
+                    }
+                }
+            }
+
+            let engines = match (engine_config(t, 1, &params), engine_config(t, 2, &params)) {
+                (Ok(first), Ok(second)) => [first, second],
+                (Err(e), _) | (_, Err(e)) => {
+                    eprintln!("{}", e);
+                    return;
+                }
+            };
+
+            let games = t
+                .value_of("games")
+                .and_then(|games| games.parse().ok())
+                .unwrap_or(2 * openings.len().max(1));
+            let threads = t
+                .value_of("threads")
+                .and_then(|threads| threads.parse().ok())
+                .unwrap_or_else(|| thread::available_parallelism().map_or(1, NonZeroUsize::get));
+
+            let default = Adjudication::default();
+            let adjudication = Adjudication {
+                max_plies: parse_or(t, "max-plies", default.max_plies),
+                score: parse_or(t, "adjudicate-score", default.score),
+                score_plies: parse_or(t, "adjudicate-plies", default.score_plies),
+            };
+
+            let default = Sprt::default();
+            let sprt = Sprt {
+                elo0: parse_or(t, "elo0", default.elo0),
+                elo1: parse_or(t, "elo1", default.elo1),
+                alpha: parse_or(t, "alpha", default.alpha),
+                beta: parse_or(t, "beta", default.beta),
+            };
+            let (lower, upper) = sprt.bounds();
+
+            let output = t.value_of("pgn").unwrap_or("match.pgn");
+            let mut writer = match File::create(output) {
+                Ok(file) => BufWriter::new(file),
+                Err(e) => {
+                    eprintln!("Couldn't create {}: {}", output, e);
+                    return;
+                }
+            };
+
+            println!(
+                "{} against {}, {} games on {} threads",
+                engines[0].name, engines[1].name, games, threads
+            );
+
+            let start = Instant::now();
+            let chess_match = Match::new(engines, openings, adjudication, threads);
+
+            let score = chess_match.play(games, |game, score| {
+                // Games are written as they finish, so a match can be
+                // stopped at any time
+                if let Err(e) = writeln!(writer, "{}", game.to_pgn(chess_match.engines()))
+                    .and_then(|()| writer.flush())
+                {
+                    eprintln!("Couldn't write to {}: {}", output, e);
+                }
+
                 println!(
-                    "{:?} {}",
-                    best.best_move,
-                    best.evaluation.into_inner() as f64 / 100.
+                    "Game {}: {} ({}), {}, {}, LLR {:.2} ({:.2}, {:.2})",
+                    game.round,
+                    game.result,
+                    game.termination,
+                    score,
+                    elo_text(score),
+                    sprt.llr(score),
+                    lower,
+                    upper
                 );
 
-                board = board.make_move(&best.best_move).unwrap();
+                sprt.verdict(score) == SprtVerdict::Continue
+            });
 
-                println!("{}", board);
+            println!(
+                "{} against {}: {} [{:.3}] in {} games, {:.1}s",
+                chess_match.engines()[0].name,
+                chess_match.engines()[1].name,
+                score,
+                score.score(),
+                score.games(),
+                start.elapsed().as_secs_f64()
+            );
+            println!("{}", elo_text(&score));
+            println!(
+                "SPRT ({:.1}, {:.1}): LLR {:.2} ({:.2}, {:.2}), {}",
+                sprt.elo0,
+                sprt.elo1,
+                sprt.llr(&score),
+                lower,
+                upper,
+                match sprt.verdict(&score) {
+                    SprtVerdict::Stronger => "H1 accepted, the first engine is stronger",
+                    SprtVerdict::NotStronger => "H0 accepted, the first engine isn't stronger",
+                    SprtVerdict::Continue => "undecided",
+                }
+            );
+        }
+        ("tune", Some(t)) => {
+            let path = t.value_of("positions").unwrap();
+            let output = t.value_of("output").unwrap_or("params.txt");
+            let iterations = t
+                .value_of("iterations")
+                .and_then(|iterations| iterations.parse().ok())
+                .unwrap_or(100);
+            let threads = t
+                .value_of("threads")
+                .and_then(|threads| threads.parse().ok())
+                .unwrap_or(1);
 
-                let (from, to) = get_positions();
+            let positions = match load_positions(path) {
+                Ok(positions) => positions,
+                Err(e) => {
+                    eprintln!("Couldn't read positions from {}: {}", path, e);
+                    return;
+                }
+            };
 
-                let played_move = Move::new(
-                    from,
-                    to,
-                    board.kind_at(board.to_play(), from),
-                    board.kind_at(!board.to_play(), to),
-                );
+            println!("Loaded {} positions", positions.len());
 
-                board = board.make_move(&played_move).unwrap();
+            let mut tuner = Tuner::new(positions, threads);
+            let k = tuner.optimise_k(&params);
 
-                println!("{:?}", board);
+            println!("K: {:.3}, error: {:.6}", k, tuner.error(&params));
+
+            let tuned = tuner.tune(params, iterations, |iteration, error, params| {
+                println!("Iteration {}: error {:.6}", iteration, error);
+
+                // Save after every pass, so a long run can be stopped at any time
+                if let Err(e) = params.save(output) {
+                    eprintln!("Couldn't save parameters to {}: {}", output, e);
+                }
+            });
+
+            match tuned.save(output) {
+                Ok(()) => println!("Parameters saved to {}", output),
+                Err(e) => eprintln!("Couldn't save parameters to {}: {}", output, e),
+            }
+        }
+        ("datagen", Some(t)) => {
+            let output = t.value_of("output").unwrap_or("data.bin");
+            let games = t
+                .value_of("games")
+                .and_then(|games| games.parse().ok())
+                .unwrap_or(100);
+            let seed = t
+                .value_of("seed")
+                .and_then(|seed| seed.parse().ok())
+                .unwrap_or(0);
+            let random_plies = t
+                .value_of("random-plies")
+                .and_then(|plies| plies.parse().ok())
+                .unwrap_or(8);
+            let limit = match t.value_of("nodes").and_then(|nodes| nodes.parse().ok()) {
+                Some(nodes) => SearchLimit::Nodes(nodes),
+                None => SearchLimit::Depth(
+                    t.value_of("depth")
+                        .and_then(|depth| depth.parse().ok())
+                        .unwrap_or(6),
+                ),
+            };
+
+            let file = std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(output);
+            let mut writer = match file {
+                Ok(file) => BufWriter::new(file),
+                Err(e) => {
+                    eprintln!("Couldn't open {}: {}", output, e);
+                    return;
+                }
+            };
+
+            let mut generator = DataGenerator::new(seed, limit, random_plies);
+            let mut total = 0;
+
+            for game in 1..=games {
+                let positions = generator.play_game();
+
+                for position in &positions {
+                    if let Err(e) = position.write_to(&mut writer) {
+                        eprintln!("Couldn't write to {}: {}", output, e);
+                        return;
+                    }
+                }
+
+                total += positions.len();
+
+                if let Some(position) = positions.first() {
+                    println!(
+                        "Game {}: {}, {} positions ({} in total)",
+                        game,
+                        position.result,
+                        positions.len(),
+                        total
+                    );
+                } else {
+                    println!("Game {}: no quiet positions", game);
+                }
+            }
+
+            if let Err(e) = writer.flush() {
+                eprintln!("Couldn't write to {}: {}", output, e);
+            }
+        }
+        ("convert", Some(t)) => {
+            let input = t.value_of("input").unwrap();
+            let mut reader = match File::open(input) {
+                Ok(file) => BufReader::new(file),
+                Err(e) => {
+                    eprintln!("Couldn't open {}: {}", input, e);
+                    return;
+                }
+            };
+            let mut writer: Box<dyn Write> = match t.value_of("output") {
+                Some(output) => match File::create(output) {
+                    Ok(file) => Box::new(BufWriter::new(file)),
+                    Err(e) => {
+                        eprintln!("Couldn't create {}: {}", output, e);
+                        return;
+                    }
+                },
+                None => Box::new(BufWriter::new(std::io::stdout())),
+            };
+
+            loop {
+                match TrainingPosition::read_from(&mut reader) {
+                    Ok(Some(position)) => {
+                        if let Err(e) = writeln!(writer, "{}", position) {
+                            eprintln!("{}", e);
+                            return;
+                        }
+                    }
+                    Ok(None) => break,
+                    Err(e) => {
+                        eprintln!("Couldn't read {}: {}", input, e);
+                        return;
+                    }
+                }
+            }
+
+            if let Err(e) = writer.flush() {
+                eprintln!("{}", e);
             }
         }
         _ => panic!(),
     }
 }
 
-fn get_positions() -> (Position, Position) {
+/// Loads the opening book given by `--book`, if there is one, with how its
+/// moves should be chosen. Prints the error if the book can't be read
+fn load_book(matches: &ArgMatches) -> Result<Option<(OpeningBook, BookSelection)>, ()> {
+    let path = if let Some(path) = matches.value_of("book") {
+        path
+    } else {
+        return Ok(None);
+    };
+
+    let selection = match matches.value_of("book-selection") {
+        Some("best") => BookSelection::Best,
+        _ => BookSelection::Weighted,
+    };
+
+    match OpeningBook::load(path) {
+        Ok(book) => Ok(Some((book, selection))),
+        Err(e) => {
+            eprintln!("Couldn't load the opening book {}: {}", path, e);
+            Err(())
+        }
+    }
+}
+
+/// A move played in `play`, with the board it was played from so it can
+/// be taken back
+struct PlayedMove {
+    board: Board,
+    played_move: Move,
+    annotation: Annotation,
+}
+
+/// What's recorded in the PGN alongside a move
+#[derive(Clone, Copy)]
+enum Annotation {
+    None,
+    Book,
+    Evaluation(i16),
+}
+
+/// Something entered by the human in `play`
+enum Command {
+    Move(Move),
+    Undo,
+    Hint,
+    Fen,
+    Flip,
+    Resign,
+    Quit,
+}
+
+/// Reads a move, in either UCI notation such as `e2e4` or SAN such as
+/// `Nf3`, or a command from stdin, asking again until it's one of the
+/// legal moves in the position. The input ending is taken as `quit`
+fn read_command(board: &Board) -> Command {
     let mut buf = String::new();
 
     loop {
+        print!("{} to play> ", board.to_play());
+        let _ = std::io::stdout().flush();
+
         buf.clear();
 
-        while let Err(e) = std::io::stdin().read_line(&mut buf) {
-            eprintln!("{}", e);
+        match std::io::stdin().read_line(&mut buf) {
+            Ok(0) => return Command::Quit,
+            Ok(_) => {}
+            Err(e) => {
+                eprintln!("{}", e);
+                continue;
+            }
+        }
+
+        let input = buf.trim();
+
+        match input {
+            "" => continue,
+            "undo" => return Command::Undo,
+            "hint" => return Command::Hint,
+            "fen" => return Command::Fen,
+            "flip" => return Command::Flip,
+            "resign" => return Command::Resign,
+            "quit" => return Command::Quit,
+            "help" => {
+                println!("Enter a move such as e2e4 or Nf3, or one of:");
+                println!("  undo    take back your last move");
+                println!("  hint    suggest a move");
+                println!("  fen     print the position's FEN");
+                println!("  flip    turn the board around");
+                println!("  resign  give up the game");
+                println!("  quit    stop without a result");
+                continue;
+            }
+            _ => {}
         }
 
-        let mut chars = buf.split(' ');
+        let uci: String = input.split_whitespace().collect();
 
-        if let Some(from) = chars.next() {
-            if let Some(from) = Position::from_uci(from) {
-                if let Some(to) = chars.next() {
-                    if let Some(to) = Position::from_uci(to) {
-                        return (from, to);
-                    }
-                }
+        match board
+            .parse_uci_move(&uci)
+            .or_else(|_| board.parse_san(input))
+        {
+            Ok(played_move) => return Command::Move(played_move),
+            Err(e) => eprintln!("{}: {}", e, input),
+        }
+    }
+}
+
+/// Prints the board from black's side if it's `flipped`, highlighting the
+/// last move played
+fn print_board(board: &Board, flipped: bool, history: &[PlayedMove]) {
+    let renderer = BoardRenderer {
+        flipped,
+        last_move: history.last().map(|played| played.played_move.from_to()),
+        ..BoardRenderer::default()
+    };
+
+    print!("{}", renderer.render(board));
+}
+
+/// Parses the value of argument `name`, or returns `default` if it isn't
+/// given or can't be parsed
+fn parse_or<T: FromStr>(matches: &ArgMatches, name: &str, default: T) -> T {
+    matches
+        .value_of(name)
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(default)
+}
+
+/// Reads the configuration of engine `number` of a match, 1 or 2, from its
+/// arguments. Piece values replace those in `params`
+fn engine_config(
+    matches: &ArgMatches,
+    number: usize,
+    params: &EvalParams,
+) -> Result<EngineConfig, String> {
+    let argument = |name: &str| matches.value_of(format!("{}{}", name, number));
+    let invalid = |name: &str, value: &str| format!("Invalid --{}{} {}", name, number, value);
+
+    let (limit, mut name) = if let Some(nodes) = argument("nodes") {
+        let nodes = nodes.parse().map_err(|_| invalid("nodes", nodes))?;
+
+        (SearchLimit::Nodes(nodes), format!("citron {} nodes", nodes))
+    } else if let Some(time) = argument("movetime") {
+        let time = time.parse().map_err(|_| invalid("movetime", time))?;
+
+        (
+            SearchLimit::Time(Duration::from_millis(time)),
+            format!("citron {}ms", time),
+        )
+    } else {
+        let depth = match argument("depth") {
+            Some(depth) => depth.parse().map_err(|_| invalid("depth", depth))?,
+            None => 6,
+        };
+
+        (SearchLimit::Depth(depth), format!("citron depth {}", depth))
+    };
+
+    let params = if let Some(values) = argument("values") {
+        let parsed: Vec<i16> = values
+            .split(',')
+            .map(|value| value.trim().parse())
+            .collect::<Result<_, _>>()
+            .map_err(|_| invalid("values", values))?;
+
+        if parsed.len() != 5 {
+            return Err(invalid("values", values));
+        }
+
+        let mut params = params.clone();
+
+        for (kind, value) in [
+            PieceKind::Pawn,
+            PieceKind::Knight,
+            PieceKind::Bishop,
+            PieceKind::Rook,
+            PieceKind::Queen,
+        ]
+        .into_iter()
+        .zip(parsed)
+        {
+            params.piece_values[kind as usize] = value;
+        }
+
+        name.push_str(&format!(" values {}", values));
+
+        // Each engine's parameters live for the rest of the match
+        Some(&*Box::leak(Box::new(params)))
+    } else {
+        None
+    };
+
+    Ok(EngineConfig {
+        name,
+        limit,
+        params,
+    })
+}
+
+fn elo_text(score: &MatchScore) -> String {
+    match score.elo() {
+        Some((elo, margin)) => format!("Elo {:+.1} +/- {:.1}", elo, margin),
+        None => String::from("Elo unknown"),
+    }
+}
+
+/// How the engine did on a position from an EPD suite
+struct EpdResult {
+    epd: EpdPosition,
+    /// The line of the suite the position is on
+    line: usize,
+    /// The move the search chose, in SAN, if it found one
+    found: Option<String>,
+    evaluation: i16,
+    solved: bool,
+    elapsed: Duration,
+}
+
+impl EpdResult {
+    fn search(epd: EpdPosition, line: usize, limit: SearchLimit) -> Self {
+        let start = Instant::now();
+        let table = epd.board.search(limit);
+        let elapsed = start.elapsed();
+
+        let (found, evaluation, solved) = match table.get(&epd.board.hash()) {
+            Some(best) => {
+                let evaluation = best.evaluation.into_inner();
+
+                (
+                    Some(epd.board.san(&best.best_move)),
+                    evaluation,
+                    epd.is_solved(&best.best_move, evaluation),
+                )
+            }
+            None => (None, 0, false),
+        };
+
+        Self {
+            epd,
+            line,
+            found,
+            evaluation,
+            solved,
+            elapsed,
+        }
+    }
+    /// The position's `id`, or its line in the suite if it doesn't have one
+    fn name(&self) -> String {
+        self.epd
+            .id
+            .clone()
+            .unwrap_or_else(|| format!("line {}", self.line))
+    }
+    fn moves(&self, moves: &[Move]) -> Vec<String> {
+        moves.iter().map(|m| self.epd.board.san(m)).collect()
+    }
+}
+
+impl std::fmt::Display for EpdResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}: {} {} ({:+.2}, {}ms)",
+            self.name(),
+            if self.solved { "solved" } else { "failed" },
+            self.found.as_deref().unwrap_or("with no move"),
+            f64::from(self.evaluation) / 100.,
+            self.elapsed.as_millis()
+        )?;
+
+        if !self.solved {
+            if !self.epd.best_moves.is_empty() {
+                write!(f, ", best {}", self.moves(&self.epd.best_moves).join(" "))?;
+            }
+
+            if !self.epd.avoid_moves.is_empty() {
+                write!(f, ", avoid {}", self.moves(&self.epd.avoid_moves).join(" "))?;
+            }
+
+            if let Some(mate) = self.epd.mate {
+                write!(f, ", mate in {}", mate)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Writes `text` as a quoted JSON string
+fn json_string(text: &str) -> String {
+    let mut json = String::from('"');
+
+    for c in text.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            c if c.is_control() => {
+                let _ = write!(json, "\\u{:04x}", u32::from(c));
             }
+            c => json.push(c),
         }
     }
+
+    json.push('"');
+    json
+}
+
+fn json_list(items: &[String]) -> String {
+    let items: Vec<_> = items.iter().map(|item| json_string(item)).collect();
+
+    format!("[{}]", items.join(", "))
+}
+
+/// Writes the results of an EPD suite as a JSON object, with the totals
+/// and an entry for each position and each line that couldn't be read
+fn epd_json(results: &[EpdResult], errors: &[(usize, EpdError)], elapsed: Duration) -> String {
+    let positions: Vec<_> = results
+        .iter()
+        .map(|result| {
+            format!(
+                "{{\"id\": {}, \"line\": {}, \"fen\": {}, \"solved\": {}, \"move\": {}, \"best_moves\": {}, \"avoid_moves\": {}, \"mate\": {}, \"evaluation\": {}, \"time_ms\": {}}}",
+                result.epd.id.as_deref().map_or("null".to_string(), json_string),
+                result.line,
+                json_string(&result.epd.board.to_fen()),
+                result.solved,
+                result.found.as_deref().map_or("null".to_string(), json_string),
+                json_list(&result.moves(&result.epd.best_moves)),
+                json_list(&result.moves(&result.epd.avoid_moves)),
+                result.epd.mate.map_or("null".to_string(), |mate| mate.to_string()),
+                result.evaluation,
+                result.elapsed.as_millis()
+            )
+        })
+        .collect();
+    let errors: Vec<_> = errors
+        .iter()
+        .map(|(line, e)| {
+            format!(
+                "{{\"line\": {}, \"error\": {}}}",
+                line,
+                json_string(&e.to_string())
+            )
+        })
+        .collect();
+    let solved = results.iter().filter(|result| result.solved).count();
+
+    format!(
+        "{{\"solved\": {}, \"failed\": {}, \"total\": {}, \"time_ms\": {}, \"positions\": [{}], \"errors\": [{}]}}",
+        solved,
+        results.len() - solved,
+        results.len(),
+        elapsed.as_millis(),
+        positions.join(", "),
+        errors.join(", ")
+    )
 }
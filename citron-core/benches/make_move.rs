@@ -0,0 +1,59 @@
+use citron_core::{Board, MoveGen};
+use criterion::{criterion_group, criterion_main, Criterion};
+
+const FEN: &str = "r2q1rk1/1p3p1p/1b4p1/pPp2b2/3pn1P1/P2Q4/B1P1NP1P/R1B2RK1 b - - 0 30";
+
+/// Counts the nodes in the move tree, cloning the board at every move
+fn perft_clone(board: &Board, depth: u8) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+
+    MoveGen::new(board)
+        .into_inner()
+        .iter()
+        .map(|possible_move| perft_clone(&board.make_move(possible_move).unwrap(), depth - 1))
+        .sum()
+}
+
+/// Counts the nodes in the move tree, making and unmaking moves in place
+fn perft_in_place(board: &mut Board, depth: u8) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+
+    let mut nodes = 0;
+
+    for possible_move in MoveGen::new(board).into_inner() {
+        let undo = board.make_move_mut(&possible_move);
+        nodes += perft_in_place(board, depth - 1);
+        board.unmake_move(&possible_move, undo);
+    }
+
+    nodes
+}
+
+pub fn criterion_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("make_move");
+
+    let board = Board::from_fen(FEN).unwrap();
+
+    group.sample_size(10);
+
+    group.bench_function("clone perft 3", |b| b.iter(|| perft_clone(&board, 3)));
+
+    group.bench_function("in place perft 3", |b| {
+        let mut board = board.clone();
+
+        b.iter(|| perft_in_place(&mut board, 3));
+    });
+
+    group.bench_function("search ply 6", |b| {
+        b.iter(|| board.iterative_deepening_ply(6));
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
@@ -0,0 +1,99 @@
+//! Move generation throughput, and slider attack lookups on their own.
+//!
+//! To compare the `pext` lookups against magic multiplication, save a
+//! baseline with the default build and compare against it:
+//!
+//! ```text
+//! cargo bench --bench movegen -- --save-baseline magic
+//! RUSTFLAGS="-C target-cpu=native" cargo bench --bench movegen --features pext -- --baseline magic
+//! ```
+//!
+//! The `sliders` group also runs the magic lookup in the same build, so a
+//! `pext` build shows both side by side.
+
+use citron_core::{
+    magic::{bishop_attacks, bishop_attacks_magic, rook_attacks, rook_attacks_magic},
+    Board, MoveGen, Position,
+};
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+
+const POSITIONS: [(&str, &str); 3] = [
+    (
+        "opening",
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+    ),
+    (
+        "middle game",
+        "r2q1rk1/1p3p1p/1b4p1/pPp2b2/3pn1P1/P2Q4/B1P1NP1P/R1B2RK1 b - - 0 30",
+    ),
+    ("end game", "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1"),
+];
+
+/// Counts the nodes in the move tree, making and unmaking moves in place
+fn perft(board: &mut Board, depth: u8) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+
+    let mut nodes = 0;
+
+    for possible_move in MoveGen::new(board).into_inner() {
+        let undo = board.make_move_mut(&possible_move);
+        nodes += perft(board, depth - 1);
+        board.unmake_move(&possible_move, undo);
+    }
+
+    nodes
+}
+
+fn movegen(c: &mut Criterion) {
+    let mut group = c.benchmark_group("movegen");
+
+    group.sample_size(20);
+
+    for (name, fen) in POSITIONS {
+        let mut board = Board::from_fen(fen).unwrap();
+
+        // Reported as nodes per second
+        group.throughput(Throughput::Elements(perft(&mut board, 3)));
+        group.bench_function(format!("perft 3 {}", name), |b| {
+            b.iter(|| perft(&mut board, 3));
+        });
+    }
+
+    group.finish();
+}
+
+fn sliders(c: &mut Criterion) {
+    let mut group = c.benchmark_group("sliders");
+
+    let blockers: Vec<_> = POSITIONS
+        .iter()
+        .map(|(_, fen)| Board::from_fen(fen).unwrap().occupied().0)
+        .collect();
+    let lookups = |attacks: fn(Position, u64) -> u64| {
+        let mut all = 0;
+
+        for &blockers in &blockers {
+            for position in Position::positions() {
+                all ^= attacks(position, black_box(blockers));
+            }
+        }
+
+        all
+    };
+
+    group.throughput(Throughput::Elements(64 * blockers.len() as u64));
+
+    group.bench_function("rook", |b| b.iter(|| lookups(rook_attacks)));
+    group.bench_function("rook magic", |b| b.iter(|| lookups(rook_attacks_magic)));
+    group.bench_function("bishop", |b| b.iter(|| lookups(bishop_attacks)));
+    group.bench_function("bishop magic", |b| {
+        b.iter(|| lookups(bishop_attacks_magic));
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, movegen, sliders);
+criterion_main!(benches);
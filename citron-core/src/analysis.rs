@@ -1,11 +1,16 @@
-use std::collections::hash_map::Entry;
+use std::{
+    cell::Cell,
+    collections::hash_map::Entry,
+    time::{Duration, Instant},
+};
 
 use crate::{
     killer::KillerMoves,
     move_ordering::move_ordering,
     piece::{PieceKind, KING_VALUE},
+    tablebase::{piece_count, tablebases},
     transposition_table::{TranspositionEntry, TranspositionTable},
-    Board, MoveGen,
+    Board, MoveGen, PlayableTeam,
 };
 
 const ASPIRATION_WINDOW: i16 = 25;
@@ -14,6 +19,57 @@ const INF: i16 = std::i16::MAX;
 const MULTICUT_M: usize = 5;
 const MULTICUT_C: usize = 2;
 
+/// The deepest a node limited search will go, however few nodes it takes
+const MAXIMUM_DEPTH: u8 = 64;
+
+/// The number of nodes searched between checks of the clock in a time
+/// limited search
+const TIME_CHECK_INTERVAL: u64 = 1024;
+
+thread_local! {
+    /// The number of nodes searched on this thread, used to stop node
+    /// limited searches
+    static NODES: Cell<u64> = const { Cell::new(0) };
+    /// When the time limited search on this thread has to stop, if there
+    /// is one
+    static DEADLINE: Cell<Option<Instant>> = const { Cell::new(None) };
+    /// Whether the search on this thread ran out of time partway through
+    /// an iteration, which is then abandoned
+    static ABORTED: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Whether the search on this thread has run out of time. The clock is
+/// only read every [`TIME_CHECK_INTERVAL`] nodes
+fn out_of_time() -> bool {
+    if ABORTED.with(Cell::get) {
+        return true;
+    }
+
+    if NODES.with(Cell::get) % TIME_CHECK_INTERVAL == 0 {
+        if let Some(deadline) = DEADLINE.with(Cell::get) {
+            if Instant::now() >= deadline {
+                ABORTED.with(|aborted| aborted.set(true));
+
+                return true;
+            }
+        }
+    }
+
+    false
+}
+
+/// How long a search goes on for
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SearchLimit {
+    /// Search to a fixed depth, in ply
+    Depth(u8),
+    /// Search until an iteration finishes having searched this many nodes
+    Nodes(u64),
+    /// Search for this much time, abandoning the iteration underway when
+    /// it runs out
+    Time(Duration),
+}
+
 #[derive(Debug, Clone, Copy)]
 pub enum Node {
     PvNode(i16),
@@ -33,18 +89,13 @@ impl Node {
 pub fn explore_line(mut starting_board: Board, transposition_table: &TranspositionTable) {
     for _ in 0..10 {
         if let Some(best) = transposition_table.get(&starting_board.hash) {
-            let (from, to) = best.best_move.from_to();
             println!(
-                "Best move in position: ({}) ({}) {:?}",
-                from, to, best.evaluation
+                "Best move in position: {} {:?}",
+                best.best_move, best.evaluation
             );
 
             starting_board = starting_board.make_move(&best.best_move).unwrap();
 
-            let (from, to) = best.best_move.from_to();
-            let (fx, fy) = from.to_uci();
-            let (tx, ty) = to.to_uci();
-            println!("{fx}{fy} {tx}{ty}");
             println!("{}", starting_board);
         } else {
             break;
@@ -59,23 +110,83 @@ impl Board {
     }
     #[must_use]
     pub fn iterative_deepening_ply(&self, depth: u8) -> TranspositionTable {
+        self.deepen(SearchLimit::Depth(depth))
+    }
+    /// Searches one ply deeper at a time until an iteration finishes having
+    /// searched at least `nodes` nodes. The limit is only checked between
+    /// iterations, so the search usually goes over it
+    #[must_use]
+    pub fn iterative_deepening_nodes(&self, nodes: u64) -> TranspositionTable {
+        self.deepen(SearchLimit::Nodes(nodes))
+    }
+    /// Searches one ply deeper at a time until `time` has passed. The
+    /// iteration underway then is abandoned, and the best move from the
+    /// last finished one is kept
+    #[must_use]
+    pub fn iterative_deepening_time(&self, time: Duration) -> TranspositionTable {
+        self.deepen(SearchLimit::Time(time))
+    }
+    /// Searches the board until `limit` is reached
+    #[must_use]
+    pub fn search(&self, limit: SearchLimit) -> TranspositionTable {
+        self.deepen(limit)
+    }
+    fn deepen(&self, limit: SearchLimit) -> TranspositionTable {
+        let depth = match limit {
+            SearchLimit::Depth(depth) => depth,
+            SearchLimit::Nodes(_) | SearchLimit::Time(_) => MAXIMUM_DEPTH,
+        };
+
+        // With a tablebase move there's nothing left to search
+        if let Some((best_move, wdl)) =
+            tablebases().and_then(|tablebases| tablebases.root_move(self))
+        {
+            let mut transposition_table = TranspositionTable::new();
+
+            transposition_table.insert(
+                self.hash,
+                TranspositionEntry::new(depth, Node::PvNode(wdl.score(0)), best_move),
+            );
+
+            return transposition_table;
+        }
+
         let mut beta = INF;
         let mut alpha = -INF;
 
+        let mut board = self.clone();
         let mut transposition_table = TranspositionTable::new();
         let mut killer_table = Vec::with_capacity(depth as usize);
 
         killer_table.resize_with(depth as usize, KillerMoves::default);
 
+        let start = NODES.with(Cell::get);
+        let start_time = Instant::now();
+
+        ABORTED.with(|aborted| aborted.set(false));
+
         for depth in 0..=depth {
+            // The first iteration with a move always finishes, so there's a
+            // move to play however tight the limit is
+            DEADLINE.with(|deadline| {
+                deadline.set(match limit {
+                    SearchLimit::Time(time) if depth > 1 => Some(start_time + time),
+                    _ => None,
+                });
+            });
+
             for i in 0.. {
-                let eval = self.evaluate_private(
+                let eval = board.evaluate_private(
                     depth,
                     0,
                     alpha,
                     beta,
                     (&mut transposition_table, killer_table.as_mut_slice()),
                 );
+                if ABORTED.with(Cell::get) {
+                    break;
+                }
+
                 if eval <= alpha {
                     alpha -= ASPIRATION_WINDOW << (2 * i);
                 } else if eval >= beta {
@@ -86,20 +197,54 @@ impl Board {
                     break;
                 }
             }
+
+            let finished = match limit {
+                SearchLimit::Depth(_) => false,
+                SearchLimit::Nodes(nodes) => NODES.with(Cell::get) - start >= nodes,
+                SearchLimit::Time(time) => start_time.elapsed() >= time,
+            };
+
+            if depth > 0 && (finished || ABORTED.with(Cell::get)) {
+                break;
+            }
         }
 
+        DEADLINE.with(|deadline| deadline.set(None));
+
         transposition_table
     }
     fn evaluate_private(
-        &self,
+        &mut self,
         depth: u8,
         ply: u8,
         mut alpha: i16,
         beta: i16,
         (transposition_table, killer_table): (&mut TranspositionTable, &mut [KillerMoves]),
     ) -> i16 {
+        NODES.with(|nodes| nodes.set(nodes.get() + 1));
+
+        // The score is thrown away along with the rest of the iteration
+        if out_of_time() {
+            return 0;
+        }
+
+        if ply > 0 {
+            if let Some(tablebases) = tablebases() {
+                if piece_count(self) <= tablebases.probe_limit() {
+                    if let Some(wdl) = tablebases.probe_wdl(self) {
+                        return wdl.score(ply);
+                    }
+                }
+            }
+        }
+
         if depth == 0 {
-            return self.static_evaluation();
+            // The static evaluation is from white's perspective, and the
+            // search scores from the side to play's
+            return match self.to_play {
+                PlayableTeam::White => self.static_evaluation(),
+                PlayableTeam::Black => -self.static_evaluation(),
+            };
         }
 
         if let Some(t) = transposition_table.get(&self.hash) {
@@ -127,9 +272,9 @@ impl Board {
             let mut c = 0;
 
             if let Err(multi_cut) = moves.iter().take(MULTICUT_M).try_for_each(|possible_move| {
-                let possible_board = self.make_move(possible_move).unwrap();
+                let undo = self.make_move_mut(possible_move);
 
-                let eval = -possible_board.evaluate_private(
+                let eval = -self.evaluate_private(
                     depth - 3,
                     ply + 1,
                     -beta,
@@ -137,6 +282,12 @@ impl Board {
                     (transposition_table, killer_table),
                 );
 
+                self.unmake_move(possible_move, undo);
+
+                if ABORTED.with(Cell::get) {
+                    return Err(0);
+                }
+
                 if eval >= beta {
                     c += 1;
                     if c == MULTICUT_C {
@@ -170,10 +321,10 @@ impl Board {
                         return Err((KING_VALUE, possible_move));
                     }
 
-                    let possible_board = self.make_move(&possible_move).unwrap();
+                    let undo = self.make_move_mut(&possible_move);
 
                     let score = if index > 3 && depth >= 3 && best_move.is_none() {
-                        let eval = -possible_board.evaluate_private(
+                        let eval = -self.evaluate_private(
                             depth - 3,
                             ply + 1,
                             -beta,
@@ -181,7 +332,7 @@ impl Board {
                             (transposition_table, killer_table),
                         );
                         if eval > alpha {
-                            -possible_board.evaluate_private(
+                            -self.evaluate_private(
                                 depth - 1,
                                 ply + 1,
                                 -beta,
@@ -192,7 +343,7 @@ impl Board {
                             eval
                         }
                     } else if pv_search {
-                        -possible_board.evaluate_private(
+                        -self.evaluate_private(
                             depth - 1,
                             ply + 1,
                             -beta,
@@ -200,7 +351,7 @@ impl Board {
                             (transposition_table, killer_table),
                         )
                     } else {
-                        let score = -possible_board.evaluate_private(
+                        let score = -self.evaluate_private(
                             depth - 1,
                             ply + 1,
                             -(alpha + 1),
@@ -209,7 +360,7 @@ impl Board {
                         );
 
                         if score > alpha {
-                            -possible_board.evaluate_private(
+                            -self.evaluate_private(
                                 depth - 1,
                                 ply + 1,
                                 -beta,
@@ -221,6 +372,12 @@ impl Board {
                         }
                     };
 
+                    self.unmake_move(&possible_move, undo);
+
+                    if ABORTED.with(Cell::get) {
+                        return Err((0, possible_move));
+                    }
+
                     if score > alpha {
                         if score >= beta {
                             if possible_move.captured_piece_kind() == PieceKind::None {
@@ -238,6 +395,10 @@ impl Board {
                     Ok(())
                 })
         {
+            if ABORTED.with(Cell::get) {
+                return 0;
+            }
+
             let transposition_entry =
                 TranspositionEntry::new(depth, Node::CutNode(beta_cutoff), possible_move);
 
@@ -312,46 +473,42 @@ fn good_test() {
 }
 
 #[test]
-fn simple_tactical_puzzle_1() {
-    use crate::Position;
-
-    let board = Board::from_fen("5nk1/7p/2Q2Pp1/1p1rp1P1/p2P2q1/1PN5/P1K5/5R2 b - - 0 1").unwrap();
-
-    let table = board.iterative_deepening_ply(10);
-
-    let best = table.get(&board.hash()).unwrap();
-    let (from, to) = best.best_move.from_to();
-
-    assert_eq!(Position::new(6, 3), from);
-    assert_eq!(Position::new(6, 1), to);
-}
-
-#[test]
-fn simple_tactical_puzzle_2() {
-    use crate::Position;
-
-    let board = Board::from_fen("5bk1/5pp1/r4n1p/4p3/3nP3/6NP/1BB2PP1/R5K1 b - - 0 1").unwrap();
-
-    let table = board.iterative_deepening_ply(10);
-
-    let best = table.get(&board.hash()).unwrap();
-    let (from, to) = best.best_move.from_to();
-
-    assert_eq!(Position::new(0, 5), from);
-    assert_eq!(Position::new(0, 0), to);
+fn scores_are_from_the_side_to_play() {
+    // Black is a queen up, so whoever is to play, the score at every depth
+    // should favour black. Leaves with black to play used to be scored from
+    // white's perspective
+    for (fen, sign) in [
+        ("3qk3/8/8/8/8/8/8/4K3 b - - 0 1", 1),
+        ("3qk3/8/8/8/8/8/8/4K3 w - - 0 1", -1),
+    ] {
+        let board = Board::from_fen(fen).unwrap();
+
+        for depth in 1..=4 {
+            let table = board.iterative_deepening_ply(depth);
+            let score = table.get(&board.hash()).unwrap().evaluation.into_inner();
+
+            assert_eq!(score.signum(), sign, "{} at depth {}", fen, depth);
+        }
+    }
 }
 
 #[test]
-fn simple_tactical_puzzle_3() {
-    use crate::Position;
+fn time_limit_stops_the_iteration() {
+    let board =
+        Board::from_fen("r2q1rk1/1p3p1p/1b4p1/pPp2b2/3pn1P1/P2Q4/B1P1NP1P/R1B2RK1 b - - 0 30")
+            .unwrap();
 
-    let board = Board::from_fen("4r1k1/2Q2pp1/7p/8/5q2/7P/5PP1/2R3K1 b - - 1 1").unwrap();
+    let start = Instant::now();
+    let table = board.iterative_deepening_time(Duration::from_millis(100));
 
-    let table = board.iterative_deepening_ply(10);
+    // Iterations get far longer than this, so the last one must have been
+    // cut short rather than left to finish
+    assert!(start.elapsed() < Duration::from_millis(500));
 
     let best = table.get(&board.hash()).unwrap();
-    let (from, to) = best.best_move.from_to();
 
-    assert_eq!(Position::new(4, 7), from);
-    assert_eq!(Position::new(4, 0), to);
+    assert!(board
+        .legal_moves()
+        .iter()
+        .any(|possible_move| possible_move.to_uci() == best.best_move.to_uci()));
 }
@@ -0,0 +1,228 @@
+//! Questions about which squares pieces attack, such as whether a square
+//! is attacked, which pieces give check or are pinned, and which are left
+//! hanging. Everything is looked up in the magic attack tables, without
+//! generating moves.
+
+use crate::{
+    magic,
+    piece::PieceKind,
+    Bitboard, Board, PlayableTeam, Position,
+};
+
+/// Returns the squares strictly between two squares on the same rank, file
+/// or diagonal, or none if they don't share one
+fn between(from: Position, to: Position) -> u64 {
+    let (from_bitmap, to_bitmap) = (from.to_bitmap(), to.to_bitmap());
+
+    // Each piece blocks the other, so the rays only meet between them
+    if magic::rook_attacks(from, 0) & to_bitmap != 0 {
+        magic::rook_attacks(from, to_bitmap) & magic::rook_attacks(to, from_bitmap)
+    } else if magic::bishop_attacks(from, 0) & to_bitmap != 0 {
+        magic::bishop_attacks(from, to_bitmap) & magic::bishop_attacks(to, from_bitmap)
+    } else {
+        0
+    }
+}
+
+impl Board {
+    /// Returns the squares the piece on `position` attacks, or none if the
+    /// square is empty. Pawns attack the squares diagonally in front of
+    /// them, not the square they can push to
+    #[must_use]
+    pub fn attacks_from(&self, position: Position) -> Bitboard {
+        let piece = self.piece_at(position);
+
+        match PlayableTeam::try_from(piece.team()) {
+            Ok(team) => Bitboard(Self::get_attacks_for_square(
+                team,
+                piece.kind(),
+                position,
+                self.get_occupied(),
+            )),
+            Err(()) => Bitboard::EMPTY,
+        }
+    }
+    /// Returns every square attacked by at least one of `team`'s pieces
+    #[must_use]
+    pub fn attacked_squares(&self, team: PlayableTeam) -> Bitboard {
+        let blockers = self.get_occupied();
+        let mut attacked =
+            magic::all_pawn_attacks(self.pieces[team as usize][PieceKind::Pawn as usize], team);
+
+        for kind in PieceKind::kinds_no_pawn() {
+            for position in self.pieces(team, kind) {
+                attacked |= Self::get_attacks_for_square(team, kind, position, blockers);
+            }
+        }
+
+        Bitboard(attacked)
+    }
+    /// Returns `by`'s pieces that attack `position`
+    #[must_use]
+    pub fn attackers(&self, position: Position, by: PlayableTeam) -> Bitboard {
+        let blockers = self.get_occupied();
+        let pieces = &self.pieces[by as usize];
+
+        let rooks = pieces[PieceKind::Rook as usize] | pieces[PieceKind::Queen as usize];
+        let bishops = pieces[PieceKind::Bishop as usize] | pieces[PieceKind::Queen as usize];
+
+        // A piece attacks a square if the same piece on that square would
+        // attack it back, except pawns, which attack the other way
+        Bitboard(
+            (magic::pawn_attacks(position, !by) & pieces[PieceKind::Pawn as usize])
+                | (magic::knight_attacks(position) & pieces[PieceKind::Knight as usize])
+                | (magic::king_attacks(position) & pieces[PieceKind::King as usize])
+                | (magic::rook_attacks(position, blockers) & rooks)
+                | (magic::bishop_attacks(position, blockers) & bishops),
+        )
+    }
+    /// Returns true if any of `by`'s pieces attack `position`
+    #[must_use]
+    pub fn is_square_attacked(&self, position: Position, by: PlayableTeam) -> bool {
+        !self.attackers(position, by).is_empty()
+    }
+    /// Returns the pieces giving check to the side to play
+    #[must_use]
+    pub fn checkers(&self) -> Bitboard {
+        self.king_position(self.to_play)
+            .map_or(Bitboard::EMPTY, |king| self.attackers(king, !self.to_play))
+    }
+    /// Returns `team`'s pieces that stand alone between their king and an
+    /// enemy rook, bishop or queen, so moving them off that line would put
+    /// the king in check
+    #[must_use]
+    pub fn pinned_pieces(&self, team: PlayableTeam) -> Bitboard {
+        let king = match self.king_position(team) {
+            Some(king) => king,
+            None => return Bitboard::EMPTY,
+        };
+
+        let enemy = &self.pieces[!team as usize];
+        let rooks = enemy[PieceKind::Rook as usize] | enemy[PieceKind::Queen as usize];
+        let bishops = enemy[PieceKind::Bishop as usize] | enemy[PieceKind::Queen as usize];
+
+        // The sliders that would attack the king if nothing were in the way
+        let snipers =
+            (magic::rook_attacks(king, 0) & rooks) | (magic::bishop_attacks(king, 0) & bishops);
+
+        let mut pinned = Bitboard::EMPTY;
+
+        for sniper in Bitboard(snipers) {
+            let blockers = between(king, sniper) & self.get_occupied();
+
+            if blockers.count_ones() == 1 && blockers & self.all_pieces[team as usize] != 0 {
+                pinned |= Bitboard(blockers);
+            }
+        }
+
+        pinned
+    }
+    /// Returns `team`'s pieces, other than the king, that the other side
+    /// could win by capturing: those attacked and not defended, and those
+    /// attacked by a piece worth less than them
+    #[must_use]
+    pub fn hanging_pieces(&self, team: PlayableTeam) -> Bitboard {
+        let mut hanging = Bitboard::EMPTY;
+
+        for kind in PieceKind::kinds_no_king() {
+            for position in self.pieces(team, kind) {
+                let cheapest_attacker = self
+                    .attackers(position, !team)
+                    .map(|attacker| self.piece_at(attacker).kind().value())
+                    .min();
+
+                if let Some(value) = cheapest_attacker {
+                    if value < kind.value() || !self.is_square_attacked(position, team) {
+                        hanging.insert(position);
+                    }
+                }
+            }
+        }
+
+        hanging
+    }
+}
+
+#[cfg(test)]
+fn squares(board: Bitboard) -> Vec<String> {
+    board
+        .map(|position| {
+            let (x, y) = position.to_uci();
+            format!("{}{}", x, y)
+        })
+        .collect()
+}
+
+#[test]
+fn attack_maps() {
+    let board = Board::from_fen("4k3/8/8/3p4/4P3/8/8/R3K1N1 w - - 0 1").unwrap();
+    let square = |uci| Position::from_uci(uci).unwrap();
+
+    assert_eq!(squares(board.attacks_from(square("e4"))), ["d5", "f5"]);
+    assert_eq!(squares(board.attacks_from(square("d5"))), ["c4", "e4"]);
+    assert_eq!(
+        squares(board.attacks_from(square("g1"))),
+        ["e2", "f3", "h3"]
+    );
+    assert_eq!(
+        squares(board.attacks_from(square("a1"))),
+        ["b1", "c1", "d1", "e1", "a2", "a3", "a4", "a5", "a6", "a7", "a8"]
+    );
+    assert!(board.attacks_from(square("e5")).is_empty());
+
+    let attacked = board.attacked_squares(PlayableTeam::White);
+
+    assert!(attacked.contains(square("d5")));
+    assert!(attacked.contains(square("a8")));
+    assert!(!attacked.contains(square("e5")));
+
+    assert!(board.is_square_attacked(square("e4"), PlayableTeam::Black));
+    assert!(!board.is_square_attacked(square("e5"), PlayableTeam::White));
+    assert_eq!(
+        squares(board.attackers(square("d1"), PlayableTeam::White)),
+        ["a1", "e1"]
+    );
+}
+
+#[test]
+fn checks_and_pins() {
+    // Both rooks give check
+    let board = Board::from_fen("4k3/8/8/8/8/4r3/3N4/r3K3 w - - 0 1").unwrap();
+
+    assert_eq!(squares(board.checkers()), ["a1", "e3"]);
+    assert!(board.in_check(PlayableTeam::White));
+    assert!(board.pinned_pieces(PlayableTeam::White).is_empty());
+
+    // The knight stands alone between the king and the rook on a1
+    let board = Board::from_fen("4k3/8/8/8/8/8/8/r2NK2r w - - 0 1").unwrap();
+
+    assert_eq!(squares(board.checkers()), ["h1"]);
+    assert_eq!(squares(board.pinned_pieces(PlayableTeam::White)), ["d1"]);
+    assert!(board.pinned_pieces(PlayableTeam::Black).is_empty());
+
+    // Likewise on a diagonal, unless another piece is in the way
+    let board = Board::from_fen("4k3/8/1b6/8/8/8/5N2/6K1 w - - 0 1").unwrap();
+
+    assert_eq!(squares(board.pinned_pieces(PlayableTeam::White)), ["f2"]);
+
+    let board = Board::from_fen("4k3/8/1b6/8/3p4/8/5N2/6K1 w - - 0 1").unwrap();
+
+    assert!(board.pinned_pieces(PlayableTeam::White).is_empty());
+    assert!(board.checkers().is_empty());
+}
+
+#[test]
+fn hanging_pieces() {
+    // The knight is defended by the queen but attacked by a pawn, and the
+    // rook is attacked by the bishop and undefended. The bishop is only
+    // attacked by the queen, and defended by the king
+    let board = Board::from_fen("8/1b4Q1/k7/8/1p6/2N5/8/4K2R w - - 0 1").unwrap();
+
+    assert_eq!(
+        squares(board.hanging_pieces(PlayableTeam::White)),
+        ["h1", "c3"]
+    );
+    assert!(board.hanging_pieces(PlayableTeam::Black).is_empty());
+
+    assert!(Board::new().hanging_pieces(PlayableTeam::White).is_empty());
+}
@@ -0,0 +1,335 @@
+//! A set of squares stored as the bits of a `u64`, with bit `y * 8 + x`
+//! set for each square in it, so a1 is the least significant bit and h8 the
+//! most significant.
+
+use core::{
+    fmt::{self, Display},
+    ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign, BitXor, BitXorAssign, Not},
+};
+
+use crate::{
+    magic::{MASK_FILE, MASK_RANK},
+    Position,
+};
+
+/// A set of squares. Iterating over it yields each square, from a1 to h8
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Bitboard(pub u64);
+
+/// One of the eight directions a bitboard can be shifted in. North is
+/// towards the eighth rank and east towards the h file
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    North,
+    South,
+    East,
+    West,
+    NorthEast,
+    NorthWest,
+    SouthEast,
+    SouthWest,
+}
+
+impl Direction {
+    #[must_use]
+    pub const fn directions() -> [Self; 8] {
+        [
+            Self::North,
+            Self::South,
+            Self::East,
+            Self::West,
+            Self::NorthEast,
+            Self::NorthWest,
+            Self::SouthEast,
+            Self::SouthWest,
+        ]
+    }
+}
+
+impl Bitboard {
+    pub const EMPTY: Self = Self(0);
+    pub const FULL: Self = Self(u64::MAX);
+
+    /// Returns the squares on the file `x`, where 0 is the a file
+    #[must_use]
+    pub const fn file(x: u8) -> Self {
+        Self(MASK_FILE[x as usize])
+    }
+    /// Returns the squares on the rank `y`, where 0 is the first rank
+    #[must_use]
+    pub const fn rank(y: u8) -> Self {
+        Self(MASK_RANK[y as usize])
+    }
+    #[must_use]
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+    #[must_use]
+    pub const fn contains(self, position: Position) -> bool {
+        self.0 & position.to_bitmap() != 0
+    }
+    pub const fn insert(&mut self, position: Position) {
+        self.0 |= position.to_bitmap();
+    }
+    pub const fn remove(&mut self, position: Position) {
+        self.0 &= !position.to_bitmap();
+    }
+    /// Returns the number of squares in the set
+    #[must_use]
+    pub const fn popcount(self) -> u32 {
+        self.0.count_ones()
+    }
+    /// Returns the lowest square in the set, closest to a1
+    #[must_use]
+    pub const fn lsb(self) -> Option<Position> {
+        if self.0 == 0 {
+            None
+        } else {
+            Some(Position::from_u8(self.0.trailing_zeros() as u8))
+        }
+    }
+    /// Returns the highest square in the set, closest to h8
+    #[must_use]
+    pub const fn msb(self) -> Option<Position> {
+        if self.0 == 0 {
+            None
+        } else {
+            Some(Position::from_u8(63 - self.0.leading_zeros() as u8))
+        }
+    }
+    /// Moves every square one step in `direction`. Squares that would move
+    /// off the board are dropped rather than wrapping around to the other
+    /// side
+    #[must_use]
+    pub const fn shift(self, direction: Direction) -> Self {
+        let a_file = MASK_FILE[0];
+        let h_file = MASK_FILE[7];
+
+        Self(match direction {
+            Direction::North => self.0 << 8,
+            Direction::South => self.0 >> 8,
+            Direction::East => (self.0 << 1) & !a_file,
+            Direction::West => (self.0 >> 1) & !h_file,
+            Direction::NorthEast => (self.0 << 9) & !a_file,
+            Direction::NorthWest => (self.0 << 7) & !h_file,
+            Direction::SouthEast => (self.0 >> 7) & !a_file,
+            Direction::SouthWest => (self.0 >> 9) & !h_file,
+        })
+    }
+}
+
+impl Iterator for Bitboard {
+    type Item = Position;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let position = self.lsb()?;
+
+        self.0 &= self.0 - 1;
+
+        Some(position)
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let count = self.popcount() as usize;
+
+        (count, Some(count))
+    }
+}
+
+impl ExactSizeIterator for Bitboard {}
+
+impl FromIterator<Position> for Bitboard {
+    fn from_iter<I: IntoIterator<Item = Position>>(iter: I) -> Self {
+        let mut bitboard = Self::EMPTY;
+
+        for position in iter {
+            bitboard.insert(position);
+        }
+
+        bitboard
+    }
+}
+
+impl From<u64> for Bitboard {
+    fn from(bits: u64) -> Self {
+        Self(bits)
+    }
+}
+
+impl From<Bitboard> for u64 {
+    fn from(bitboard: Bitboard) -> Self {
+        bitboard.0
+    }
+}
+
+impl From<Position> for Bitboard {
+    fn from(position: Position) -> Self {
+        Self(position.to_bitmap())
+    }
+}
+
+impl BitAnd for Bitboard {
+    type Output = Self;
+
+    fn bitand(self, rhs: Self) -> Self::Output {
+        Self(self.0 & rhs.0)
+    }
+}
+
+impl BitOr for Bitboard {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self::Output {
+        Self(self.0 | rhs.0)
+    }
+}
+
+impl BitXor for Bitboard {
+    type Output = Self;
+
+    fn bitxor(self, rhs: Self) -> Self::Output {
+        Self(self.0 ^ rhs.0)
+    }
+}
+
+impl Not for Bitboard {
+    type Output = Self;
+
+    fn not(self) -> Self::Output {
+        Self(!self.0)
+    }
+}
+
+impl BitAndAssign for Bitboard {
+    fn bitand_assign(&mut self, rhs: Self) {
+        self.0 &= rhs.0;
+    }
+}
+
+impl BitOrAssign for Bitboard {
+    fn bitor_assign(&mut self, rhs: Self) {
+        self.0 |= rhs.0;
+    }
+}
+
+impl BitXorAssign for Bitboard {
+    fn bitxor_assign(&mut self, rhs: Self) {
+        self.0 ^= rhs.0;
+    }
+}
+
+impl Display for Bitboard {
+    /// Draws the board from white's side, with an `X` on each square in the
+    /// set
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for y in (0..8).rev() {
+            write!(f, "{} ", y + 1)?;
+
+            for x in 0..8 {
+                let square = if self.contains(Position::new(x, y)) {
+                    'X'
+                } else {
+                    '.'
+                };
+
+                write!(f, " {}", square)?;
+            }
+
+            writeln!(f)?;
+        }
+
+        write!(f, "   a b c d e f g h")
+    }
+}
+
+#[test]
+fn bitboard_iteration() {
+    let square = |uci| Position::from_uci(uci).unwrap();
+
+    let bitboard: Bitboard = ["h8", "a1", "e4"].into_iter().map(square).collect();
+
+    assert_eq!(bitboard.popcount(), 3);
+    assert_eq!(bitboard.len(), 3);
+    assert_eq!(bitboard.lsb(), Some(square("a1")));
+    assert_eq!(bitboard.msb(), Some(square("h8")));
+    assert_eq!(
+        bitboard.collect::<Vec<_>>(),
+        vec![square("a1"), square("e4"), square("h8")]
+    );
+
+    assert_eq!(Bitboard::EMPTY.lsb(), None);
+    assert_eq!(Bitboard::EMPTY.msb(), None);
+    assert_eq!(Bitboard::EMPTY.count(), 0);
+    assert_eq!(Bitboard::FULL.count(), 64);
+}
+
+#[test]
+fn bitboard_shifts() {
+    let square = |uci| Bitboard::from(Position::from_uci(uci).unwrap());
+
+    assert_eq!(square("e4").shift(Direction::North), square("e5"));
+    assert_eq!(square("e4").shift(Direction::SouthWest), square("d3"));
+    assert_eq!(square("e4").shift(Direction::NorthEast), square("f5"));
+
+    // Squares on the edge fall off rather than wrapping around
+    for direction in [Direction::East, Direction::NorthEast, Direction::SouthEast] {
+        assert_eq!(Bitboard::file(7).shift(direction), Bitboard::EMPTY);
+    }
+
+    for direction in [Direction::West, Direction::NorthWest, Direction::SouthWest] {
+        assert_eq!(Bitboard::file(0).shift(direction), Bitboard::EMPTY);
+    }
+
+    assert_eq!(Bitboard::rank(7).shift(Direction::North), Bitboard::EMPTY);
+    assert_eq!(Bitboard::rank(0).shift(Direction::South), Bitboard::EMPTY);
+
+    // Every square, shifted each way, keeps all but the edge it moved off
+    for direction in Direction::directions() {
+        assert!(Bitboard::FULL.shift(direction).popcount() >= 49);
+    }
+}
+
+#[test]
+fn bitboard_operations() {
+    let a_file = Bitboard::file(0);
+    let first_rank = Bitboard::rank(0);
+
+    assert_eq!(
+        (a_file & first_rank).collect::<Vec<_>>(),
+        vec![Position::new(0, 0)]
+    );
+    assert_eq!((a_file | first_rank).popcount(), 15);
+    assert_eq!((a_file ^ first_rank).popcount(), 14);
+    assert_eq!((!a_file).popcount(), 56);
+
+    let display = Bitboard::file(4).to_string();
+
+    assert_eq!(display.lines().count(), 9);
+    assert_eq!(display.lines().next(), Some("8  . . . . X . . ."));
+}
+
+#[test]
+fn board_bitboards() {
+    use crate::{piece::PieceKind, Board, PlayableTeam};
+
+    let board = Board::new();
+
+    assert_eq!(
+        board.pieces(PlayableTeam::White, PieceKind::Pawn),
+        Bitboard::rank(1)
+    );
+    assert_eq!(
+        board.pieces(PlayableTeam::Black, PieceKind::None),
+        Bitboard::EMPTY
+    );
+    assert_eq!(
+        board
+            .pieces(PlayableTeam::Black, PieceKind::King)
+            .collect::<Vec<_>>(),
+        vec![Position::new(4, 7)]
+    );
+    assert_eq!(
+        board.team_occupancy(PlayableTeam::Black),
+        Bitboard::rank(6) | Bitboard::rank(7)
+    );
+    assert_eq!(board.occupied().popcount(), 32);
+}
@@ -0,0 +1,260 @@
+//! Reads opening books in the Polyglot `.bin` format.
+//!
+//! A book is a list of 16 byte entries, sorted by key, each holding a
+//! position's [`polyglot_key`], a move, the move's weight and a learning
+//! value, all big endian.
+
+use std::{
+    io::{self, Read},
+    path::Path,
+};
+
+use rand::Rng;
+
+use crate::{move_gen::Move, Board};
+
+mod keys;
+
+pub use keys::polyglot_key;
+
+/// The size of a single entry in a Polyglot book
+const ENTRY_SIZE: usize = 16;
+
+/// How a move is chosen when a book has several for a position
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BookSelection {
+    /// Always play the move with the highest weight
+    Best,
+    /// Play a random move, with a chance proportional to its weight
+    Weighted,
+}
+
+/// A single entry in a Polyglot book
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BookEntry {
+    pub key: u64,
+    /// The move, packed as Polyglot stores it
+    pub raw_move: u16,
+    pub weight: u16,
+    pub learn: u32,
+}
+
+impl BookEntry {
+    /// The entry's move in UCI notation. Polyglot writes castling as the
+    /// king taking its own rook, such as `e1h1`, which is left as is
+    #[must_use]
+    pub fn uci(&self) -> String {
+        let square = |file: u16, rank: u16| {
+            format!(
+                "{}{}",
+                char::from(b'a' + file as u8),
+                char::from(b'1' + rank as u8)
+            )
+        };
+        let promotion = match (self.raw_move >> 12) & 7 {
+            1 => "n",
+            2 => "b",
+            3 => "r",
+            4 => "q",
+            _ => "",
+        };
+
+        format!(
+            "{}{}{}",
+            square((self.raw_move >> 6) & 7, (self.raw_move >> 9) & 7),
+            square(self.raw_move & 7, (self.raw_move >> 3) & 7),
+            promotion
+        )
+    }
+}
+
+/// A Polyglot opening book, held in memory
+#[derive(Clone, Debug, Default)]
+pub struct OpeningBook {
+    entries: Vec<BookEntry>,
+}
+
+impl OpeningBook {
+    /// Reads every entry of a book
+    pub fn from_reader(mut reader: impl Read) -> io::Result<Self> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+
+        if bytes.len() % ENTRY_SIZE != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Not a Polyglot book, its size isn't a multiple of 16 bytes",
+            ));
+        }
+
+        let mut entries: Vec<BookEntry> = bytes
+            .chunks_exact(ENTRY_SIZE)
+            .map(|entry| BookEntry {
+                key: u64::from_be_bytes(entry[0..8].try_into().unwrap()),
+                raw_move: u16::from_be_bytes([entry[8], entry[9]]),
+                weight: u16::from_be_bytes([entry[10], entry[11]]),
+                learn: u32::from_be_bytes(entry[12..16].try_into().unwrap()),
+            })
+            .collect();
+
+        // Books should already be sorted, but lookups rely on it
+        entries.sort_by_key(|entry| entry.key);
+
+        Ok(Self { entries })
+    }
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::from_reader(io::BufReader::new(std::fs::File::open(path)?))
+    }
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+    /// Every entry for the position on `board`
+    #[must_use]
+    pub fn entries(&self, board: &Board) -> &[BookEntry] {
+        let key = polyglot_key(board);
+        let start = self.entries.partition_point(|entry| entry.key < key);
+        let end = self.entries.partition_point(|entry| entry.key <= key);
+
+        &self.entries[start..end]
+    }
+    /// The book's moves for the position on `board` with their weights,
+    /// skipping any the engine can't play, such as castling
+    #[must_use]
+    pub fn moves(&self, board: &Board) -> Vec<(Move, u16)> {
+        self.entries(board)
+            .iter()
+            .filter_map(|entry| Some((board.parse_uci_move(&entry.uci()).ok()?, entry.weight)))
+            .collect()
+    }
+    /// Chooses one of the book's moves for the position on `board`, or
+    /// `None` if it has none
+    pub fn choose(
+        &self,
+        board: &Board,
+        selection: BookSelection,
+        rng: &mut impl Rng,
+    ) -> Option<Move> {
+        let moves = self.moves(board);
+
+        match selection {
+            BookSelection::Best => moves
+                .into_iter()
+                // The first of equally weighted moves is preferred
+                .rev()
+                .max_by_key(|(_, weight)| *weight)
+                .map(|(book_move, _)| book_move),
+            BookSelection::Weighted => {
+                let total: u32 = moves.iter().map(|(_, weight)| u32::from(*weight)).sum();
+
+                if total == 0 {
+                    return moves.into_iter().next().map(|(book_move, _)| book_move);
+                }
+
+                let mut choice = rng.gen_range(0..total);
+
+                moves.into_iter().find_map(|(book_move, weight)| {
+                    if choice < u32::from(weight) {
+                        Some(book_move)
+                    } else {
+                        choice -= u32::from(weight);
+                        None
+                    }
+                })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+fn test_book() -> OpeningBook {
+    OpeningBook::from_reader(
+        &include_bytes!(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/fixtures/small.bin"
+        ))[..],
+    )
+    .unwrap()
+}
+
+#[test]
+fn book_lookup() {
+    let book = test_book();
+    let board = Board::new();
+
+    let moves: Vec<_> = book
+        .moves(&board)
+        .into_iter()
+        .map(|(book_move, weight)| (book_move.to_uci(), weight))
+        .collect();
+
+    assert_eq!(
+        moves,
+        [
+            ("e2e4".to_string(), 20),
+            ("d2d4".to_string(), 10),
+            ("g1f3".to_string(), 5)
+        ]
+    );
+
+    let board = board
+        .make_move(&board.parse_uci_move("e2e4").unwrap())
+        .unwrap();
+
+    assert_eq!(book.moves(&board).len(), 2);
+
+    // Castling can't be played, so is skipped
+    let board = Board::from_fen("r1bqk1nr/pppp1ppp/2n5/2b1p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w - - 4 4")
+        .unwrap();
+
+    assert_eq!(book.entries(&board).len(), 2);
+    assert_eq!(book.entries(&board)[0].uci(), "e1h1");
+    assert_eq!(book.moves(&board).len(), 1);
+
+    // Positions out of the book have no moves
+    let board = Board::from_fen("4k3/8/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+
+    assert!(book.entries(&board).is_empty());
+}
+
+#[test]
+fn book_selection() {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    let book = test_book();
+    let board = Board::new();
+    let mut rng = StdRng::seed_from_u64(0);
+
+    assert_eq!(
+        book.choose(&board, BookSelection::Best, &mut rng)
+            .unwrap()
+            .to_uci(),
+        "e2e4"
+    );
+
+    let mut counts = [0; 3];
+
+    for _ in 0..350 {
+        let book_move = book
+            .choose(&board, BookSelection::Weighted, &mut rng)
+            .unwrap();
+
+        counts[["e2e4", "d2d4", "g1f3"]
+            .iter()
+            .position(|uci| *uci == book_move.to_uci())
+            .unwrap()] += 1;
+    }
+
+    // Each move is chosen roughly in proportion to its weight
+    assert!(counts[0] > counts[1] && counts[1] > counts[2] && counts[2] > 0);
+
+    let board = Board::from_fen("4k3/8/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+
+    assert!(book
+        .choose(&board, BookSelection::Weighted, &mut rng)
+        .is_none());
+}
@@ -0,0 +1,331 @@
+//! Editing boards square by square, keeping the hashes, material and
+//! everything else derived from the pieces up to date, and checking the
+//! result is a position that could be played.
+
+use core::fmt::{self, Display};
+
+use crate::{
+    piece::{Piece, PieceKind},
+    transposition_table::ZOBRIST_KEYS,
+    Board, PlayableTeam, Position,
+};
+
+/// Every square on the first and last ranks
+const BACK_RANKS: u64 = 0xff00_0000_0000_00ff;
+
+/// The reason a board isn't a valid position
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BoardError {
+    /// A side doesn't have exactly one king
+    KingCount(PlayableTeam),
+    /// A side has more than eight pawns
+    TooManyPawns(PlayableTeam),
+    /// A side has more than sixteen pieces
+    TooManyPieces(PlayableTeam),
+    /// A pawn is on the first or last rank
+    PawnOnBackRank(Position),
+    /// The side that isn't to play is in check, so its king could be taken
+    OpponentInCheck,
+}
+
+impl Display for BoardError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::KingCount(team) => write!(f, "{} must have exactly one king", team),
+            Self::TooManyPawns(team) => write!(f, "{} has more than eight pawns", team),
+            Self::TooManyPieces(team) => write!(f, "{} has more than sixteen pieces", team),
+            Self::PawnOnBackRank(position) => write!(f, "Pawn on the back rank at {}", position),
+            Self::OpponentInCheck => write!(f, "The side not to play is in check"),
+        }
+    }
+}
+
+impl Board {
+    /// Creates a board with no pieces on it, with white to play
+    #[must_use]
+    pub const fn empty() -> Self {
+        let mut board = Self::EMPTY_BOARD;
+        board.turn = 1;
+        board
+    }
+    /// Puts `piece` on `position`, replacing whatever was there. Setting
+    /// [`Piece::Empty`] clears the square. The board isn't checked to be a
+    /// valid position, see [`Board::validate`]
+    pub fn set_piece(&mut self, position: Position, piece: Piece) {
+        self.clear_square(position);
+
+        let team = match PlayableTeam::try_from(piece.team()) {
+            Ok(team) => team,
+            Err(()) => return,
+        };
+        let kind = piece.kind();
+
+        if kind != PieceKind::King {
+            self.material += Self::material_sign(team) * kind.value();
+            self.absolute_material += kind.value();
+        }
+
+        self.add_piece(piece, position);
+        self.update_king_positions();
+
+        #[cfg(feature = "nn_evaluation")]
+        self.refresh_accumulator();
+    }
+    /// Removes the piece on `position`, returning it, or [`Piece::Empty`]
+    /// if there wasn't one
+    pub fn clear_square(&mut self, position: Position) -> Piece {
+        let piece = self.piece_at(position);
+
+        let team = match PlayableTeam::try_from(piece.team()) {
+            Ok(team) => team,
+            Err(()) => return piece,
+        };
+        let kind = piece.kind();
+
+        if kind != PieceKind::King {
+            self.material -= Self::material_sign(team) * kind.value();
+            self.absolute_material -= kind.value();
+        }
+
+        self.remove_piece(piece, position);
+        self.update_king_positions();
+
+        #[cfg(feature = "nn_evaluation")]
+        self.refresh_accumulator();
+
+        piece
+    }
+    /// Recomputes each side's king position from its king bitboard, which
+    /// is a1 when the king is missing, as it is for an empty board
+    fn update_king_positions(&mut self) {
+        let king = |team: PlayableTeam| match self.pieces[team as usize][PieceKind::King as usize] {
+            0 => Position::new(0, 0),
+            kings => Position::from_bitmap(kings),
+        };
+
+        self.king_positions = (king(PlayableTeam::White), king(PlayableTeam::Black));
+    }
+    /// Sets the side to play
+    pub fn set_side_to_move(&mut self, team: PlayableTeam) {
+        if self.to_play != team {
+            self.to_play = team;
+            self.hash ^= ZOBRIST_KEYS.1;
+        }
+    }
+    /// Sets the turn, as written at the end of a FEN
+    pub fn set_turn(&mut self, turn: u16) {
+        self.turn = turn;
+    }
+    const fn material_sign(team: PlayableTeam) -> i16 {
+        match team {
+            PlayableTeam::White => 1,
+            PlayableTeam::Black => -1,
+        }
+    }
+    /// Checks the board is a position that could be played: each side has
+    /// one king, no more than sixteen pieces and eight pawns, no pawns are
+    /// on the back ranks, and the side not to play isn't in check
+    ///
+    /// # Errors
+    ///
+    /// Returns the first [`BoardError`] found
+    pub fn validate(&self) -> Result<(), BoardError> {
+        for team in PlayableTeam::teams() {
+            let pieces = &self.pieces[team as usize];
+
+            if pieces[PieceKind::King as usize].count_ones() != 1 {
+                return Err(BoardError::KingCount(team));
+            }
+
+            if pieces[PieceKind::Pawn as usize].count_ones() > 8 {
+                return Err(BoardError::TooManyPawns(team));
+            }
+
+            if self.all_pieces[team as usize].count_ones() > 16 {
+                return Err(BoardError::TooManyPieces(team));
+            }
+
+            let misplaced = pieces[PieceKind::Pawn as usize] & BACK_RANKS;
+
+            if misplaced != 0 {
+                return Err(BoardError::PawnOnBackRank(Position::from_u8(
+                    misplaced.trailing_zeros() as u8,
+                )));
+            }
+        }
+
+        if self.in_check(!self.to_play) {
+            return Err(BoardError::OpponentInCheck);
+        }
+
+        Ok(())
+    }
+}
+
+/// Builds a [`Board`] piece by piece, checking it's a valid position once
+/// it's built. It starts empty, with white to play on turn 1
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BoardBuilder {
+    board: Board,
+}
+
+impl Default for BoardBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl From<Board> for BoardBuilder {
+    /// Starts building from an existing board
+    fn from(board: Board) -> Self {
+        Self { board }
+    }
+}
+
+impl BoardBuilder {
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            board: Board::empty(),
+        }
+    }
+    /// Puts `piece` on `position`, replacing whatever was there
+    pub fn piece(&mut self, position: Position, piece: Piece) -> &mut Self {
+        self.board.set_piece(position, piece);
+        self
+    }
+    /// Removes the piece on `position`, if there is one
+    pub fn clear(&mut self, position: Position) -> &mut Self {
+        self.board.clear_square(position);
+        self
+    }
+    pub fn to_play(&mut self, team: PlayableTeam) -> &mut Self {
+        self.board.set_side_to_move(team);
+        self
+    }
+    pub fn turn(&mut self, turn: u16) -> &mut Self {
+        self.board.set_turn(turn);
+        self
+    }
+    /// Returns the board built so far, without checking it's valid
+    #[must_use]
+    pub const fn board(&self) -> &Board {
+        &self.board
+    }
+    /// Returns the board, if it's a valid position
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`BoardError`] if it isn't, see [`Board::validate`]
+    pub fn build(&self) -> Result<Board, BoardError> {
+        self.board.validate()?;
+
+        Ok(self.board.clone())
+    }
+}
+
+#[test]
+fn build_initial_position() {
+    let initial = Board::new();
+    let mut builder = BoardBuilder::new();
+
+    for y in 0..8 {
+        for x in 0..8 {
+            let position = Position::new(x, y);
+            builder.piece(position, initial.piece_at(position));
+        }
+    }
+
+    let board = builder.turn(0).build().unwrap();
+
+    assert_eq!(board, initial);
+    assert_eq!(board.hash(), crate::hash(&board));
+}
+
+#[test]
+fn edit_board() {
+    let square = |uci| Position::from_uci(uci).unwrap();
+
+    let mut board = Board::new();
+
+    // Replacing a piece, and clearing one, keeps everything up to date
+    board.set_piece(square("d1"), Piece::WhiteKnight);
+    assert_eq!(board.clear_square(square("d8")), Piece::BlackQueen);
+    assert_eq!(board.clear_square(square("d4")), Piece::Empty);
+    board.set_side_to_move(PlayableTeam::Black);
+    board.set_turn(12);
+
+    let fen = "rnb1kbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBNKBNR b - - 0 12";
+
+    assert_eq!(board.to_fen(), fen);
+    assert_eq!(board, Board::from_fen(fen).unwrap());
+
+    // Moving the king
+    board.clear_square(square("e1"));
+    board.set_piece(square("e3"), Piece::WhiteKing);
+
+    assert_eq!(board.king_position(PlayableTeam::White), Some(square("e3")));
+    assert_eq!(
+        board,
+        Board::from_fen("rnb1kbnr/pppppppp/8/8/8/4K3/PPPPPPPP/RNBN1BNR b - - 0 12").unwrap()
+    );
+
+    // Removing a second king leaves the first one's position
+    let mut board = Board::empty();
+    board.set_piece(square("e1"), Piece::WhiteKing);
+    board.set_piece(square("a1"), Piece::WhiteKing);
+    board.set_piece(square("e8"), Piece::BlackKing);
+    board.clear_square(square("a1"));
+
+    assert_eq!(board.king_position(PlayableTeam::White), Some(square("e1")));
+    assert_eq!(
+        board,
+        Board::from_fen("4k3/8/8/8/8/8/8/4K3 w - - 0 1").unwrap()
+    );
+}
+
+#[test]
+fn invalid_boards() {
+    use PlayableTeam::{Black, White};
+
+    let square = |uci| Position::from_uci(uci).unwrap();
+
+    let mut builder = BoardBuilder::new();
+    builder.piece(square("e1"), Piece::WhiteKing);
+
+    assert_eq!(builder.build(), Err(BoardError::KingCount(Black)));
+
+    builder
+        .piece(square("e8"), Piece::BlackKing)
+        .piece(square("a1"), Piece::WhiteKing);
+
+    assert_eq!(builder.build(), Err(BoardError::KingCount(White)));
+
+    builder
+        .clear(square("a1"))
+        .piece(square("a8"), Piece::WhitePawn);
+
+    assert_eq!(
+        builder.build(),
+        Err(BoardError::PawnOnBackRank(square("a8")))
+    );
+
+    builder.piece(square("a8"), Piece::WhiteRook);
+
+    assert_eq!(builder.build(), Err(BoardError::OpponentInCheck));
+    assert!(builder.to_play(Black).build().is_ok());
+
+    let mut builder = BoardBuilder::from(Board::new());
+
+    for x in 0..8 {
+        builder.piece(Position::new(x, 2), Piece::WhitePawn);
+    }
+
+    assert_eq!(builder.build(), Err(BoardError::TooManyPawns(White)));
+
+    for x in 0..8 {
+        builder.piece(Position::new(x, 2), Piece::WhiteKnight);
+    }
+
+    assert_eq!(builder.build(), Err(BoardError::TooManyPieces(White)));
+}
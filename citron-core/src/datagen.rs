@@ -0,0 +1,372 @@
+//! Generates training data for the network from self-play.
+//!
+//! Each game starts with a few random moves, so that games differ, then
+//! both sides play the best move found by a depth or node limited search.
+//! Quiet positions are recorded with their search score, and labelled with
+//! the game's result once it's over. Given the same seed, the same games
+//! are always played.
+//!
+//! # File format
+//!
+//! Positions are written one after another as fixed size records of
+//! [`PACKED_SIZE`] bytes, with no header, so files can be concatenated.
+//! Every number is little endian:
+//!
+//! | Bytes    | Field                                                   |
+//! |----------|---------------------------------------------------------|
+//! | `0..8`   | Occupancy, a `u64` with bit `n` set for each occupied square, counting from a1 to h8 |
+//! | `8..24`  | Each occupied square's [`Piece`], as a 4 bit number, in the same order as the occupancy, low nibble first |
+//! | `24`     | The side to move, 0 for white and 1 for black          |
+//! | `25..27` | The turn, a `u16`                                       |
+//! | `27..29` | The search score in centipawns, an `i16` from white's perspective |
+//! | `29`     | The result, 0 for a black win, 1 for a draw, 2 for a white win and 3 if unknown |
+
+use std::io::{self, Read, Write};
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{
+    analysis::SearchLimit,
+    magic::pop_lsb,
+    move_gen::Move,
+    pgn::GameResult,
+    piece::{Piece, PieceKind},
+    transposition_table::ZOBRIST_KEYS,
+    Board, PlayableTeam, Position, Team,
+};
+
+/// The size of a single position in the binary format
+pub const PACKED_SIZE: usize = 30;
+
+/// Games that reach this many plies are adjudicated as draws
+const MAXIMUM_PLIES: usize = 400;
+/// Once a search scores a position at least this far in either side's
+/// favour, the game is adjudicated as a win for that side
+const ADJUDICATION_SCORE: i16 = 2000;
+
+/// Every piece, indexed by the number it's stored as
+const PIECES: [Piece; 12] = [
+    Piece::WhitePawn,
+    Piece::WhiteKnight,
+    Piece::WhiteBishop,
+    Piece::WhiteRook,
+    Piece::WhiteQueen,
+    Piece::WhiteKing,
+    Piece::BlackPawn,
+    Piece::BlackKnight,
+    Piece::BlackBishop,
+    Piece::BlackRook,
+    Piece::BlackQueen,
+    Piece::BlackKing,
+];
+
+/// A quiet position from a self-play game
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TrainingPosition {
+    pub board: Board,
+    /// The search score in centipawns, from white's perspective
+    pub score: i16,
+    /// The result of the game the position was taken from
+    pub result: GameResult,
+}
+
+impl TrainingPosition {
+    /// Packs the position into the format described in the [module
+    /// documentation](self)
+    #[must_use]
+    pub fn pack(&self) -> [u8; PACKED_SIZE] {
+        let mut packed = [0; PACKED_SIZE];
+        let mut occupancy = self.board.all_pieces[0] | self.board.all_pieces[1];
+
+        packed[0..8].copy_from_slice(&occupancy.to_le_bytes());
+
+        let mut index = 0;
+
+        while occupancy != 0 {
+            let position = Position::from_u8(pop_lsb(&mut occupancy) as u8);
+
+            packed[8 + index / 2] |= (self.board.piece_at(position) as u8) << (4 * (index % 2));
+            index += 1;
+        }
+
+        packed[24] = self.board.to_play as u8;
+        packed[25..27].copy_from_slice(&self.board.turn.to_le_bytes());
+        packed[27..29].copy_from_slice(&self.score.to_le_bytes());
+        packed[29] = match self.result {
+            GameResult::BlackWins => 0,
+            GameResult::Draw => 1,
+            GameResult::WhiteWins => 2,
+            GameResult::Unknown => 3,
+        };
+
+        packed
+    }
+    /// Unpacks a position packed by [`TrainingPosition::pack`], returning
+    /// `None` if it isn't a valid position
+    #[must_use]
+    pub fn unpack(packed: &[u8; PACKED_SIZE]) -> Option<Self> {
+        let mut occupancy = u64::from_le_bytes(packed[0..8].try_into().ok()?);
+
+        if occupancy.count_ones() > 32 {
+            return None;
+        }
+
+        let mut board = Board::EMPTY_BOARD;
+        let mut kings = [0; 2];
+        let mut index = 0;
+
+        while occupancy != 0 {
+            let position = Position::from_u8(pop_lsb(&mut occupancy) as u8);
+            let piece = *PIECES.get(usize::from(
+                (packed[8 + index / 2] >> (4 * (index % 2))) & 0xf,
+            ))?;
+
+            if piece.kind() == PieceKind::King {
+                if piece.team() == Team::White {
+                    board.king_positions.0 = position;
+                } else {
+                    board.king_positions.1 = position;
+                }
+                kings[piece.team() as usize] += 1;
+            }
+
+            board.add_piece(piece, position);
+            index += 1;
+        }
+
+        if kings != [1, 1] {
+            return None;
+        }
+
+        board.to_play = match packed[24] {
+            0 => PlayableTeam::White,
+            1 => {
+                board.hash ^= ZOBRIST_KEYS.1;
+                PlayableTeam::Black
+            }
+            _ => return None,
+        };
+        board.turn = u16::from_le_bytes([packed[25], packed[26]]);
+        board.calculate_material();
+
+        #[cfg(feature = "nn_evaluation")]
+        board.refresh_accumulator();
+
+        Some(Self {
+            board,
+            score: i16::from_le_bytes([packed[27], packed[28]]),
+            result: match packed[29] {
+                0 => GameResult::BlackWins,
+                1 => GameResult::Draw,
+                2 => GameResult::WhiteWins,
+                3 => GameResult::Unknown,
+                _ => return None,
+            },
+        })
+    }
+    pub fn write_to(&self, mut writer: impl Write) -> io::Result<()> {
+        writer.write_all(&self.pack())
+    }
+    /// Reads the next position, returning `None` at the end of the input
+    pub fn read_from(mut reader: impl Read) -> io::Result<Option<Self>> {
+        let mut packed = [0; PACKED_SIZE];
+
+        match reader.read_exact(&mut packed) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e),
+        }
+
+        Self::unpack(&packed)
+            .map(Some)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Invalid training position"))
+    }
+}
+
+/// Writes the position as `<fen> | <score> | <result>`, with the result as
+/// 1.0, 0.5 or 0.0 from white's perspective, which
+/// [`LabelledPosition::parse`](crate::tuning::LabelledPosition::parse)
+/// can read
+impl core::fmt::Display for TrainingPosition {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let result = match self.result {
+            GameResult::WhiteWins => "1.0",
+            GameResult::Draw => "0.5",
+            GameResult::BlackWins => "0.0",
+            GameResult::Unknown => "*",
+        };
+
+        write!(f, "{} | {} | {}", self.board.to_fen(), self.score, result)
+    }
+}
+
+/// Plays self-play games, collecting [`TrainingPosition`]s
+pub struct DataGenerator {
+    rng: StdRng,
+    limit: SearchLimit,
+    random_plies: usize,
+}
+
+impl DataGenerator {
+    /// Creates a generator whose games open with `random_plies` random
+    /// moves, chosen with a random number generator seeded by `seed`
+    #[must_use]
+    pub fn new(seed: u64, limit: SearchLimit, random_plies: usize) -> Self {
+        Self {
+            rng: StdRng::seed_from_u64(seed),
+            limit,
+            random_plies,
+        }
+    }
+    /// Plays a game to the end, returning the quiet positions from it.
+    /// Positions in check, or where the best move is a capture or a
+    /// promotion, aren't quiet. Games where the search chooses an illegal
+    /// move are abandoned, and return no positions
+    pub fn play_game(&mut self) -> Vec<TrainingPosition> {
+        let mut board = self.random_opening();
+        let mut history = vec![board.hash()];
+        let mut positions = Vec::new();
+
+        let result = loop {
+            let to_play = board.to_play();
+            let in_check = board.in_check(to_play);
+
+            let legal_moves = board.legal_moves();
+
+            if legal_moves.is_empty() {
+                break if in_check {
+                    GameResult::win_for(!to_play)
+                } else {
+                    GameResult::Draw
+                };
+            }
+
+            if history.len() >= MAXIMUM_PLIES || board.absolute_material == 0 {
+                break GameResult::Draw;
+            }
+
+            let table = board.search(self.limit);
+            let best = match table.get(&board.hash()) {
+                Some(best) => best,
+                None => break GameResult::Draw,
+            };
+            // The search scores positions from the side to move's perspective
+            let score = best.evaluation.into_inner();
+
+            if score.abs() >= ADJUDICATION_SCORE {
+                break GameResult::win_for(if score > 0 { to_play } else { !to_play });
+            }
+
+            // A search too shallow to see its king being taken can choose
+            // an illegal move, which would leave the game meaningless
+            if !legal_moves
+                .iter()
+                .any(|legal_move| legal_move.from_to() == best.best_move.from_to())
+            {
+                return Vec::new();
+            }
+
+            if !in_check && !Self::is_noisy(&best.best_move) {
+                positions.push(TrainingPosition {
+                    board: board.clone(),
+                    score: match to_play {
+                        PlayableTeam::White => score,
+                        PlayableTeam::Black => -score,
+                    },
+                    result: GameResult::Unknown,
+                });
+            }
+
+            board = board.make_move(&best.best_move).unwrap();
+
+            // Threefold repetition
+            if history.iter().filter(|&&hash| hash == board.hash()).count() >= 2 {
+                break GameResult::Draw;
+            }
+
+            history.push(board.hash());
+        };
+
+        for position in &mut positions {
+            position.result = result;
+        }
+
+        positions
+    }
+    /// Plays random legal moves from the initial position, starting again
+    /// if the game ends before they've all been played
+    fn random_opening(&mut self) -> Board {
+        'opening: loop {
+            let mut board = Board::new();
+
+            for _ in 0..self.random_plies {
+                let moves = board.legal_moves();
+
+                if moves.is_empty() {
+                    continue 'opening;
+                }
+
+                board = board
+                    .make_move(&moves[self.rng.gen_range(0..moves.len())])
+                    .unwrap();
+            }
+
+            return board;
+        }
+    }
+    fn is_noisy(best_move: &Move) -> bool {
+        best_move.captured_piece_kind() != PieceKind::None || best_move.flags().is_promotion()
+    }
+}
+
+#[test]
+fn pack_round_trip() {
+    for fen in [
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w - - 0 1",
+        "r2q1rk1/1p3p1p/1b4p1/pPp2b2/3pn1P1/P2Q4/B1P1NP1P/R1B2RK1 b - - 0 30",
+        "3k4/8/8/8/8/8/8/KQ6 w - - 0 1",
+    ] {
+        let position = TrainingPosition {
+            board: Board::from_fen(fen).unwrap(),
+            score: -123,
+            result: GameResult::BlackWins,
+        };
+        let mut buffer = Vec::new();
+
+        position.write_to(&mut buffer).unwrap();
+        assert_eq!(buffer.len(), PACKED_SIZE);
+
+        let mut reader = buffer.as_slice();
+        let read = TrainingPosition::read_from(&mut reader).unwrap().unwrap();
+
+        assert_eq!(read, position, "{}", fen);
+        assert_eq!(read.board.hash(), crate::hash(&read.board));
+        assert!(TrainingPosition::read_from(&mut reader).unwrap().is_none());
+    }
+
+    // The text format can be read back for tuning
+    let position = TrainingPosition {
+        board: Board::new(),
+        score: 20,
+        result: GameResult::Draw,
+    };
+    let labelled = crate::tuning::LabelledPosition::parse(&position.to_string()).unwrap();
+
+    assert_eq!(labelled.result, 0.5);
+    assert_eq!(labelled.board.to_fen(), position.board.to_fen());
+}
+
+#[test]
+fn generation_is_deterministic() {
+    let play = |seed| DataGenerator::new(seed, SearchLimit::Depth(2), 8).play_game();
+    let positions = play(7);
+
+    assert!(!positions.is_empty());
+    assert_eq!(positions, play(7));
+
+    for position in &positions {
+        assert!(!position.board.in_check(position.board.to_play()));
+        assert_eq!(position.result, positions[0].result);
+        assert_ne!(position.result, GameResult::Unknown);
+    }
+}
@@ -0,0 +1,310 @@
+//! Reads test positions in Extended Position Description (EPD) format, as
+//! used by tactical suites such as WAC, ECM and STS.
+//!
+//! Each line is the first four fields of a FEN, followed by operations of
+//! an opcode and its operands, each ending in a semicolon:
+//!
+//! ```text
+//! 2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - bm Qg6; id "WAC.001";
+//! ```
+//!
+//! The opcodes understood are `bm` (best moves), `am` (moves to avoid),
+//! `dm` (direct mate in a number of moves), `id` and `c0` (a comment).
+//! Others are ignored. Moves are given in SAN.
+
+use core::fmt::{self, Display};
+
+use crate::{
+    move_gen::{Move, MoveParseError},
+    piece::KING_VALUE,
+    Board,
+};
+
+/// A test position read from a line of EPD
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EpdPosition {
+    pub board: Board,
+    /// The position's name, from `id`
+    pub id: Option<String>,
+    /// Any of these moves solves the position, from `bm`
+    pub best_moves: Vec<Move>,
+    /// None of these moves may be played, from `am`
+    pub avoid_moves: Vec<Move>,
+    /// The number of moves the side to play can mate in, from `dm`
+    pub mate: Option<u8>,
+    /// From `c0`
+    pub comment: Option<String>,
+}
+
+/// The error returned when a line of EPD can't be read
+#[derive(Debug, PartialEq, Eq)]
+pub enum EpdError {
+    /// The position fields don't make up a valid board
+    InvalidPosition,
+    /// A quoted operand is missing its closing quote
+    UnterminatedString,
+    /// An opcode is missing its operands, or one can't be read
+    InvalidOperand(String),
+    /// A move in `bm` or `am` isn't legal in the position
+    InvalidMove(String, MoveParseError),
+}
+
+impl Display for EpdError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidPosition => write!(f, "Invalid position"),
+            Self::UnterminatedString => write!(f, "Unterminated string"),
+            Self::InvalidOperand(opcode) => write!(f, "Invalid operand for {}", opcode),
+            Self::InvalidMove(san, e) => write!(f, "Invalid move {}: {}", san, e),
+        }
+    }
+}
+
+/// Splits the operations of a line into their opcode and operands. Quoted
+/// operands may contain spaces and semicolons
+fn operations(text: &str) -> Result<Vec<(String, Vec<String>)>, EpdError> {
+    let mut operations = Vec::new();
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            ';' => {
+                if !tokens.is_empty() {
+                    let opcode = tokens.remove(0);
+                    operations.push((opcode, std::mem::take(&mut tokens)));
+                }
+            }
+            '"' => {
+                let mut token = String::new();
+
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some(c) => token.push(c),
+                        None => return Err(EpdError::UnterminatedString),
+                    }
+                }
+
+                tokens.push(token);
+            }
+            c if c.is_whitespace() => {}
+            c => {
+                let mut token = String::from(c);
+
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || c == ';' {
+                        break;
+                    }
+
+                    token.push(c);
+                    chars.next();
+                }
+
+                tokens.push(token);
+            }
+        }
+    }
+
+    // The last operation's semicolon is sometimes left off
+    if !tokens.is_empty() {
+        let opcode = tokens.remove(0);
+        operations.push((opcode, tokens));
+    }
+
+    Ok(operations)
+}
+
+impl EpdPosition {
+    /// Reads a position from a line of EPD
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`EpdError`] if the position is invalid, or if an
+    /// operation the position is tested by can't be read
+    pub fn parse(line: &str) -> Result<Self, EpdError> {
+        let line = line.trim();
+        let mut fields = line.splitn(5, ' ');
+        let position: Vec<_> = fields.by_ref().take(4).collect();
+
+        if position.len() != 4 {
+            return Err(EpdError::InvalidPosition);
+        }
+
+        // EPD leaves out the move counters
+        let board = Board::from_fen(&format!("{} 0 1", position.join(" ")))
+            .ok_or(EpdError::InvalidPosition)?;
+
+        let mut epd = Self {
+            board,
+            id: None,
+            best_moves: Vec::new(),
+            avoid_moves: Vec::new(),
+            mate: None,
+            comment: None,
+        };
+
+        for (opcode, operands) in operations(fields.next().unwrap_or(""))? {
+            let moves = || {
+                operands
+                    .iter()
+                    .map(|san| {
+                        epd.board
+                            .parse_san(san)
+                            .map_err(|e| EpdError::InvalidMove(san.clone(), e))
+                    })
+                    .collect::<Result<Vec<_>, _>>()
+            };
+            let single = || {
+                operands
+                    .first()
+                    .cloned()
+                    .ok_or_else(|| EpdError::InvalidOperand(opcode.clone()))
+            };
+
+            match opcode.as_str() {
+                "bm" => epd.best_moves = moves()?,
+                "am" => epd.avoid_moves = moves()?,
+                "dm" => {
+                    epd.mate = Some(
+                        single()?
+                            .parse()
+                            .map_err(|_| EpdError::InvalidOperand(opcode.clone()))?,
+                    );
+                }
+                "id" => epd.id = Some(single()?),
+                "c0" => epd.comment = Some(single()?),
+                _ => {}
+            }
+        }
+
+        Ok(epd)
+    }
+    /// Whether a search that found `best_move`, scored `evaluation` from
+    /// the perspective of the side to play, solves the position. Every
+    /// `bm`, `am` and `dm` operation given must be satisfied.
+    ///
+    /// The search doesn't score mates by their distance, so `dm` only
+    /// checks that a mate was found, not that it's the shortest one
+    #[must_use]
+    pub fn is_solved(&self, best_move: &Move, evaluation: i16) -> bool {
+        // Moves from the search carry their ordering value, so they're
+        // compared by their squares and promotion alone
+        let uci = best_move.to_uci();
+        let played = |moves: &[Move]| moves.iter().any(|m| m.to_uci() == uci);
+
+        (self.best_moves.is_empty() || played(&self.best_moves))
+            && !played(&self.avoid_moves)
+            && (self.mate.is_none() || evaluation >= KING_VALUE)
+    }
+}
+
+#[test]
+fn parse_epd() {
+    let epd = EpdPosition::parse(
+        r#"2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - bm Qg6; id "WAC.001"; c0 "Qg6, fxg6; mate";"#,
+    )
+    .unwrap();
+
+    assert_eq!(epd.id.as_deref(), Some("WAC.001"));
+    assert_eq!(epd.comment.as_deref(), Some("Qg6, fxg6; mate"));
+    assert_eq!(
+        epd.best_moves,
+        vec![epd.board.parse_uci_move("g3g6").unwrap()]
+    );
+    assert!(epd.avoid_moves.is_empty());
+    assert_eq!(epd.mate, None);
+
+    let epd = EpdPosition::parse("4k3/8/8/8/8/8/1r6/R3K3 w - - am Ra8 Ra2; dm 3; acd 10").unwrap();
+
+    assert_eq!(epd.avoid_moves.len(), 2);
+    assert_eq!(epd.mate, Some(3));
+    assert_eq!(epd.id, None);
+}
+
+#[test]
+fn invalid_epd() {
+    assert_eq!(
+        EpdPosition::parse("4k3/8/8/8/8/8/8/X3K3 w - - bm Qg6;"),
+        Err(EpdError::InvalidPosition)
+    );
+    assert_eq!(
+        EpdPosition::parse("4k3/8/8/8/8/8/8/R3K3 w -"),
+        Err(EpdError::InvalidPosition)
+    );
+    assert_eq!(
+        EpdPosition::parse(r#"4k3/8/8/8/8/8/8/R3K3 w - - id "unterminated;"#),
+        Err(EpdError::UnterminatedString)
+    );
+    assert_eq!(
+        EpdPosition::parse("4k3/8/8/8/8/8/8/R3K3 w - - dm;"),
+        Err(EpdError::InvalidOperand("dm".to_string()))
+    );
+    assert_eq!(
+        EpdPosition::parse("4k3/8/8/8/8/8/8/R3K3 w - - bm Rb2;"),
+        Err(EpdError::InvalidMove(
+            "Rb2".to_string(),
+            MoveParseError::IllegalMove
+        ))
+    );
+}
+
+#[test]
+fn solved_epd() {
+    let epd = EpdPosition::parse("4k3/8/8/8/8/8/8/R3K3 w - - bm Ra8 Ra7; am Ra2;").unwrap();
+    let parse = |san| epd.board.parse_san(san).unwrap();
+
+    assert!(epd.is_solved(&parse("Ra8"), 0));
+    assert!(epd.is_solved(&parse("Ra7"), 0));
+    assert!(!epd.is_solved(&parse("Ra2"), 0));
+    assert!(!epd.is_solved(&parse("Kd2"), 0));
+
+    let epd = EpdPosition::parse("6k1/5ppp/8/8/8/8/8/R5K1 w - - dm 1;").unwrap();
+    let mate = epd.board.parse_san("Ra8").unwrap();
+
+    assert!(epd.is_solved(&mate, KING_VALUE));
+    assert!(!epd.is_solved(&mate, 100));
+}
+
+#[cfg(test)]
+fn unsolved_positions(suite: &str) -> Vec<String> {
+    use crate::analysis::SearchLimit;
+
+    let mut unsolved = Vec::new();
+
+    for line in suite.lines() {
+        let epd = EpdPosition::parse(line).unwrap();
+
+        assert_eq!(epd.best_moves.len(), 1, "{}", line);
+        assert!(epd.id.is_some(), "{}", line);
+
+        let table = epd.board.search(SearchLimit::Depth(10));
+        let solved = table.get(&epd.board.hash()).map_or(false, |best| {
+            epd.is_solved(&best.best_move, best.evaluation.into_inner())
+        });
+
+        if !solved {
+            unsolved.extend(epd.id);
+        }
+    }
+
+    unsolved
+}
+
+#[test]
+fn tactics_suite() {
+    let unsolved = unsolved_positions(include_str!("../tests/fixtures/tactics.epd"));
+
+    assert!(unsolved.is_empty(), "Unsolved: {:?}", unsolved);
+}
+
+/// Positions the search can't solve yet. citron.001 needs Qg2+, but even
+/// at 14 ply the search prefers a rook move. Move them into the main suite
+/// once they're solved
+#[test]
+#[ignore = "the search doesn't find these yet"]
+fn unsolved_tactics_suite() {
+    let unsolved = unsolved_positions(include_str!("../tests/fixtures/tactics_unsolved.epd"));
+
+    assert!(unsolved.is_empty(), "Unsolved: {:?}", unsolved);
+}
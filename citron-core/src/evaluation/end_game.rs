@@ -1,8 +1,211 @@
-use crate::Board;
+use super::{
+    pawns::{front_span, relative_rank},
+    EvalParams,
+};
+use crate::{magic::pop_lsb, piece::PieceKind, Board, PlayableTeam, Position};
 
 impl Board {
     #[must_use]
-    pub const fn end_game_evaluation(&self) -> i16 {
+    pub fn end_game_evaluation(&self, params: &EvalParams) -> i16 {
+        PlayableTeam::teams()
+            .into_iter()
+            .map(|team| {
+                let score = self.king_activity(params, team)
+                    + self.free_passed_pawns(params, team)
+                    + self.unstoppable_pawns(params, team)
+                    + self.mop_up(params, team);
+
+                match team {
+                    PlayableTeam::White => score,
+                    PlayableTeam::Black => -score,
+                }
+            })
+            .sum()
+    }
+    /// The score for `team`'s king being central, and close to the pawns
+    pub(crate) fn king_activity(&self, params: &EvalParams, team: PlayableTeam) -> i16 {
+        self.king_position(team).map_or(0, |king| {
+            let mut pawns =
+                self.pieces[0][PieceKind::Pawn as usize] | self.pieces[1][PieceKind::Pawn as usize];
+
+            let mut closest_pawn = 0;
+
+            if pawns != 0 {
+                closest_pawn = u8::MAX;
+
+                while pawns != 0 {
+                    let pawn = Position::from_u8(pop_lsb(&mut pawns) as u8);
+                    closest_pawn = closest_pawn.min(king.distance(&pawn));
+                }
+            }
+
+            -(i16::from(king.centre_distance()) * params.king_centre_weight
+                + i16::from(closest_pawn) * params.king_pawn_distance_weight)
+        })
+    }
+    /// The bonus for `team`'s passed pawns with nothing in front of them,
+    /// growing as they advance
+    pub(crate) fn free_passed_pawns(&self, params: &EvalParams, team: PlayableTeam) -> i16 {
+        let mut passed = self.passed_pawn_bitmap(team);
+        let mut bonus = 0;
+
+        while passed != 0 {
+            let pawn = Position::from_u8(pop_lsb(&mut passed) as u8);
+
+            if front_span(team, pawn) & self.get_occupied() == 0 {
+                bonus += params.free_passed_pawn_bonus[relative_rank(team, pawn) as usize];
+            }
+        }
+
+        bonus
+    }
+    /// The bonus for `team` having a passed pawn that can't be caught by the
+    /// enemy king, by the rule of the square, when the enemy has nothing but
+    /// pawns left to stop it with
+    pub(crate) fn unstoppable_pawns(&self, params: &EvalParams, team: PlayableTeam) -> i16 {
+        let enemy_pieces = self.all_pieces[!team as usize]
+            ^ self.pieces[!team as usize][PieceKind::Pawn as usize]
+            ^ self.pieces[!team as usize][PieceKind::King as usize];
+
+        let enemy_king = match self.king_position(!team) {
+            Some(enemy_king) if enemy_pieces == 0 => enemy_king,
+            _ => return 0,
+        };
+
+        let mut passed = self.passed_pawn_bitmap(team);
+
+        while passed != 0 {
+            let pawn = Position::from_u8(pop_lsb(&mut passed) as u8);
+
+            if front_span(team, pawn) & self.get_occupied() != 0 {
+                continue;
+            }
+
+            let promotion = match team {
+                PlayableTeam::White => Position::new(pawn.x(), 7),
+                PlayableTeam::Black => Position::new(pawn.x(), 0),
+            };
+
+            // Pawns on their starting rank can move two squares at once
+            let pawn_moves = (7 - relative_rank(team, pawn)).min(5);
+            let mut king_moves = enemy_king.distance(&promotion);
+
+            if self.to_play != team {
+                king_moves = king_moves.saturating_sub(1);
+            }
+
+            if pawn_moves < king_moves {
+                return params.unstoppable_pawn_bonus;
+            }
+        }
+
         0
     }
+    /// The bonus for `team` driving a bare enemy king to the edge of the
+    /// board, and bringing its own king closer to help mate it, when `team`
+    /// is ahead in material
+    pub(crate) fn mop_up(&self, params: &EvalParams, team: PlayableTeam) -> i16 {
+        let ahead = match team {
+            PlayableTeam::White => self.material > 0,
+            PlayableTeam::Black => self.material < 0,
+        };
+
+        let enemy_bare = self.all_pieces[!team as usize]
+            == self.pieces[!team as usize][PieceKind::King as usize];
+
+        match (self.king_position(team), self.king_position(!team)) {
+            (Some(king), Some(enemy_king)) if ahead && enemy_bare => {
+                i16::from(enemy_king.centre_distance()) * params.mop_up_centre_weight
+                    + i16::from(14 - king.manhattan_distance(&enemy_king))
+                        * params.mop_up_king_distance_weight
+            }
+            _ => 0,
+        }
+    }
+}
+
+#[test]
+fn king_activity_test() {
+    let params = EvalParams::default();
+    let board = Board::from_fen("8/8/8/3k4/8/8/8/K7 w - - 0 1").unwrap();
+
+    assert_eq!(board.king_activity(&params, PlayableTeam::Black), 0);
+    assert_eq!(
+        board.king_activity(&params, PlayableTeam::White),
+        -6 * params.king_centre_weight
+    );
+
+    // The white king is two squares from the only pawn
+    let board = Board::from_fen("8/8/8/3k4/8/2p5/8/K7 w - - 0 1").unwrap();
+
+    assert_eq!(
+        board.king_activity(&params, PlayableTeam::White),
+        -6 * params.king_centre_weight - 2 * params.king_pawn_distance_weight
+    );
+}
+
+#[test]
+fn free_passed_pawns_test() {
+    let params = EvalParams::default();
+    // The a and b pawns are passed with nothing in front of them, and the
+    // d and e pawns are both held back by the pawn on e6
+    let board = Board::from_fen("7k/8/4p3/P7/1P1PP3/8/8/7K w - - 0 1").unwrap();
+
+    assert_eq!(
+        board.free_passed_pawns(&params, PlayableTeam::White),
+        params.free_passed_pawn_bonus[4] + params.free_passed_pawn_bonus[3]
+    );
+    assert_eq!(board.free_passed_pawns(&params, PlayableTeam::Black), 0);
+
+    // A piece in front of the pawn blocks its path
+    let board = Board::from_fen("7k/n7/4p3/P7/1P1PP3/8/8/7K w - - 0 1").unwrap();
+
+    assert_eq!(
+        board.free_passed_pawns(&params, PlayableTeam::White),
+        params.free_passed_pawn_bonus[3]
+    );
+}
+
+#[test]
+fn rule_of_the_square_test() {
+    let params = EvalParams::default();
+    // The black king is outside the square of the a pawn
+    let board = Board::from_fen("7k/8/8/P7/8/8/8/7K w - - 0 1").unwrap();
+    assert_eq!(
+        board.unstoppable_pawns(&params, PlayableTeam::White),
+        params.unstoppable_pawn_bonus
+    );
+
+    // A king just outside the square can still catch it with the move
+    let board = Board::from_fen("8/8/4k3/P7/8/8/8/7K w - - 0 1").unwrap();
+    assert_eq!(
+        board.unstoppable_pawns(&params, PlayableTeam::White),
+        params.unstoppable_pawn_bonus
+    );
+    let board = Board::from_fen("8/8/4k3/P7/8/8/8/7K b - - 0 1").unwrap();
+    assert_eq!(board.unstoppable_pawns(&params, PlayableTeam::White), 0);
+
+    // A black rook can always catch the pawn
+    let board = Board::from_fen("7k/8/8/P7/8/8/8/r6K w - - 0 1").unwrap();
+    assert_eq!(board.unstoppable_pawns(&params, PlayableTeam::White), 0);
+}
+
+#[test]
+fn mop_up_test() {
+    let params = EvalParams::default();
+    let centre = Board::from_fen("8/8/8/3k4/8/8/8/KQ6 w - - 0 1").unwrap();
+    let edge = Board::from_fen("3k4/8/8/8/8/8/8/KQ6 w - - 0 1").unwrap();
+    let kings_close = Board::from_fen("3k4/8/3K4/8/8/8/8/1Q6 w - - 0 1").unwrap();
+
+    assert!(
+        edge.mop_up(&params, PlayableTeam::White) > centre.mop_up(&params, PlayableTeam::White)
+    );
+    assert!(
+        kings_close.mop_up(&params, PlayableTeam::White)
+            > edge.mop_up(&params, PlayableTeam::White)
+    );
+
+    // Only the side ahead in material gets the bonus
+    assert_eq!(edge.mop_up(&params, PlayableTeam::Black), 0);
+    assert!(edge.end_game_evaluation(&params) > 0);
 }
@@ -0,0 +1,339 @@
+//! Recognises endings where material alone is misleading, such as a lone
+//! minor piece that can't mate, and scales the evaluation towards a draw.
+
+use super::{kpk, scoped_params, EvalParams};
+use crate::{magic::MASK_FILE, piece::PieceKind, Board, PlayableTeam, Position};
+
+/// The scale factor that leaves the evaluation as it is. Scale factors are
+/// out of this
+pub const SCALE_NORMAL: i16 = 64;
+/// The scale factor of a position that can't be won
+pub const SCALE_DRAW: i16 = 0;
+
+/// The scale factor for being a minor piece or less up, without pawns
+const SCALE_MINOR_UP: i16 = 16;
+/// The scale factors for opposite coloured bishops, with nothing else but
+/// pawns, and with other pieces
+const SCALE_OPPOSITE_BISHOPS: i16 = 16;
+const SCALE_OPPOSITE_BISHOPS_WITH_PIECES: i16 = 40;
+
+/// Every dark square, starting with a1
+const DARK_SQUARES: u64 = 0xaa55_aa55_aa55_aa55;
+
+impl Board {
+    /// Whether neither side can win, whatever the rest of the position
+    #[must_use]
+    pub fn is_known_draw(&self) -> bool {
+        PlayableTeam::teams()
+            .into_iter()
+            .all(|team| self.scale_factor(scoped_params(), team) == SCALE_DRAW)
+    }
+    /// Scales an evaluation, from white's perspective, by the scale factor
+    /// of the side it favours
+    pub(crate) fn scale_evaluation(&self, params: &EvalParams, evaluation: i16) -> i16 {
+        let strong = if evaluation > 0 {
+            PlayableTeam::White
+        } else {
+            PlayableTeam::Black
+        };
+
+        (i32::from(evaluation) * i32::from(self.scale_factor(params, strong))
+            / i32::from(SCALE_NORMAL)) as i16
+    }
+    /// How much of its advantage `strong` can expect to convert, out of
+    /// [`SCALE_NORMAL`]
+    pub(crate) fn scale_factor(&self, params: &EvalParams, strong: PlayableTeam) -> i16 {
+        let weak = !strong;
+        let pieces =
+            |team: PlayableTeam, kind: PieceKind| self.pieces[team as usize][kind as usize];
+        let non_pawn_material = |team: PlayableTeam| -> i16 {
+            PieceKind::kinds_no_pawn()
+                .into_iter()
+                .filter(|&kind| kind != PieceKind::King)
+                .map(|kind| pieces(team, kind).count_ones() as i16 * params.piece_value(kind))
+                .sum()
+        };
+        let bare =
+            |team: PlayableTeam| self.all_pieces[team as usize] == pieces(team, PieceKind::King);
+
+        let strong_pawns = pieces(strong, PieceKind::Pawn);
+        let strong_material = non_pawn_material(strong);
+
+        if strong_pawns == 0 {
+            // A minor piece can't mate, and two knights can't force it
+            if strong_material < params.piece_value(PieceKind::Rook)
+                || (bare(weak)
+                    && strong_material == 2 * params.piece_value(PieceKind::Knight)
+                    && pieces(strong, PieceKind::Knight).count_ones() == 2)
+            {
+                return SCALE_DRAW;
+            }
+
+            if strong_material - non_pawn_material(weak) <= params.piece_value(PieceKind::Bishop) {
+                return SCALE_MINOR_UP;
+            }
+        }
+
+        if let Some(scale) = self.king_and_pawn(strong) {
+            return scale;
+        }
+
+        if self.wrong_rook_pawn(strong) {
+            return SCALE_DRAW;
+        }
+
+        let bishops = [
+            pieces(PlayableTeam::White, PieceKind::Bishop),
+            pieces(PlayableTeam::Black, PieceKind::Bishop),
+        ];
+
+        if bishops.iter().all(|bishops| bishops.count_ones() == 1)
+            && (bishops[0] & DARK_SQUARES == 0) != (bishops[1] & DARK_SQUARES == 0)
+        {
+            let only_bishops = PlayableTeam::teams()
+                .into_iter()
+                .all(|team| non_pawn_material(team) == params.piece_value(PieceKind::Bishop));
+
+            return if only_bishops {
+                SCALE_OPPOSITE_BISHOPS
+            } else {
+                SCALE_OPPOSITE_BISHOPS_WITH_PIECES
+            };
+        }
+
+        SCALE_NORMAL
+    }
+    /// Looks up king and pawn against king in the bitbase, if that's the
+    /// material on the board
+    fn king_and_pawn(&self, strong: PlayableTeam) -> Option<i16> {
+        let pawn = self.pieces[strong as usize][PieceKind::Pawn as usize];
+
+        if self.all_pieces[strong as usize]
+            != pawn | self.pieces[strong as usize][PieceKind::King as usize]
+            || pawn.count_ones() != 1
+            || self.all_pieces[!strong as usize]
+                != self.pieces[!strong as usize][PieceKind::King as usize]
+        {
+            return None;
+        }
+
+        // The bitbase has the pawn as white's, so flip the board for black
+        let relative = |position: Position| match strong {
+            PlayableTeam::White => position,
+            PlayableTeam::Black => Position::new(position.x(), 7 - position.y()),
+        };
+        let to_play = if self.to_play == strong {
+            PlayableTeam::White
+        } else {
+            PlayableTeam::Black
+        };
+
+        let wins = kpk::probe(
+            to_play,
+            relative(self.king_position(strong)?),
+            relative(Position::from_bitmap(pawn)),
+            relative(self.king_position(!strong)?),
+        );
+
+        Some(if wins { SCALE_NORMAL } else { SCALE_DRAW })
+    }
+    /// Whether `strong` has only bishops and pawns on a rook's file, where
+    /// the bishops can't cover the promotion square and the enemy king
+    /// already guards it
+    fn wrong_rook_pawn(&self, strong: PlayableTeam) -> bool {
+        let pawns = self.pieces[strong as usize][PieceKind::Pawn as usize];
+        let bishops = self.pieces[strong as usize][PieceKind::Bishop as usize];
+        let king = self.pieces[strong as usize][PieceKind::King as usize];
+        let weak = !strong;
+
+        if pawns == 0
+            || bishops == 0
+            || self.all_pieces[strong as usize] != pawns | bishops | king
+            || self.all_pieces[weak as usize]
+                != self.pieces[weak as usize][PieceKind::King as usize]
+        {
+            return false;
+        }
+
+        let file = if pawns & !MASK_FILE[0] == 0 {
+            0
+        } else if pawns & !MASK_FILE[7] == 0 {
+            7
+        } else {
+            return false;
+        };
+
+        let promotion = Position::new(
+            file,
+            match strong {
+                PlayableTeam::White => 7,
+                PlayableTeam::Black => 0,
+            },
+        );
+        let promotion_is_dark = promotion.to_bitmap() & DARK_SQUARES != 0;
+        let bishops_are_dark = bishops & DARK_SQUARES != 0;
+
+        if bishops & !DARK_SQUARES != 0 && bishops_are_dark {
+            // Bishops of both colours
+            return false;
+        }
+
+        bishops_are_dark != promotion_is_dark
+            && self
+                .king_position(weak)
+                .map_or(false, |king| king.distance(&promotion) <= 1)
+    }
+}
+
+#[test]
+fn insufficient_material() {
+    for fen in [
+        "4k3/8/8/8/8/8/8/4K3 w - - 0 1",
+        "4k3/8/8/8/8/8/8/2B1K3 w - - 0 1",
+        "4k3/8/8/8/8/8/8/1N2K3 b - - 0 1",
+        "4k3/8/8/8/8/8/8/1NN1K3 w - - 0 1",
+        "2b1k3/8/8/8/8/8/8/1N2K3 w - - 0 1",
+    ] {
+        let board = Board::from_fen(fen).unwrap();
+
+        assert!(board.is_known_draw(), "{}", fen);
+        assert_eq!(board.static_evaluation(), 0, "{}", fen);
+    }
+
+    // Mate can be forced with a bishop and knight, or two bishops
+    for fen in [
+        "4k3/8/8/8/8/8/8/1NB1K3 w - - 0 1",
+        "4k3/8/8/8/8/8/8/2BBK3 w - - 0 1",
+    ] {
+        let board = Board::from_fen(fen).unwrap();
+
+        assert!(!board.is_known_draw(), "{}", fen);
+        assert!(board.static_evaluation() > 0, "{}", fen);
+    }
+
+    // A lone knight can't win, even against pawns
+    let board = Board::from_fen("4k3/8/8/8/8/8/pp6/1N2K3 w - - 0 1").unwrap();
+
+    assert_eq!(
+        board.scale_factor(&EvalParams::DEFAULT, PlayableTeam::White),
+        SCALE_DRAW
+    );
+    assert_eq!(
+        board.scale_factor(&EvalParams::DEFAULT, PlayableTeam::Black),
+        SCALE_NORMAL
+    );
+}
+
+#[test]
+fn minor_piece_up() {
+    let board = Board::from_fen("4k3/8/8/8/4b3/8/8/4K2R w - - 0 1").unwrap();
+
+    assert_eq!(
+        board.scale_factor(&EvalParams::DEFAULT, PlayableTeam::White),
+        SCALE_MINOR_UP
+    );
+
+    // How far ahead the rook is goes by the parameters' piece values
+    let mut params = EvalParams::default();
+    params.piece_values[PieceKind::Rook as usize] = 700;
+
+    assert_eq!(
+        board.scale_factor(&params, PlayableTeam::White),
+        SCALE_NORMAL
+    );
+
+    let board = Board::from_fen("4k3/8/8/8/8/8/8/3QK3 w - - 0 1").unwrap();
+
+    assert_eq!(
+        board.scale_factor(&EvalParams::DEFAULT, PlayableTeam::White),
+        SCALE_NORMAL
+    );
+}
+
+#[test]
+fn king_and_pawn() {
+    // Drawn with the defending king in front, and won with the opposition
+    let drawn = Board::from_fen("4k3/8/8/8/8/4P3/8/4K3 w - - 0 1").unwrap();
+    let won = Board::from_fen("4k3/8/4K3/4P3/8/8/8/8 w - - 0 1").unwrap();
+
+    assert_eq!(
+        drawn.scale_factor(&EvalParams::DEFAULT, PlayableTeam::White),
+        SCALE_DRAW
+    );
+    assert!(drawn.is_known_draw());
+    assert_eq!(
+        won.scale_factor(&EvalParams::DEFAULT, PlayableTeam::White),
+        SCALE_NORMAL
+    );
+    assert!(won.static_evaluation() > 0);
+
+    // The same positions with the colours reversed
+    let drawn = Board::from_fen("4k3/8/4p3/8/8/8/8/4K3 b - - 0 1").unwrap();
+    let won = Board::from_fen("8/8/8/8/4p3/4k3/8/4K3 b - - 0 1").unwrap();
+
+    assert_eq!(
+        drawn.scale_factor(&EvalParams::DEFAULT, PlayableTeam::Black),
+        SCALE_DRAW
+    );
+    assert_eq!(
+        won.scale_factor(&EvalParams::DEFAULT, PlayableTeam::Black),
+        SCALE_NORMAL
+    );
+}
+
+#[test]
+fn wrong_rook_pawn() {
+    // The bishop can't cover h8, so the king in the corner draws
+    let board = Board::from_fen("7k/8/8/7P/8/8/8/4KB2 w - - 0 1").unwrap();
+
+    assert_eq!(
+        board.scale_factor(&EvalParams::DEFAULT, PlayableTeam::White),
+        SCALE_DRAW
+    );
+    assert_eq!(board.static_evaluation(), 0);
+
+    // With a dark squared bishop, it's a win
+    let board = Board::from_fen("7k/8/8/7P/8/8/8/2B1K3 w - - 0 1").unwrap();
+
+    assert_eq!(
+        board.scale_factor(&EvalParams::DEFAULT, PlayableTeam::White),
+        SCALE_NORMAL
+    );
+
+    // As it is when the king is too far from the corner
+    let board = Board::from_fen("8/8/8/3k3P/8/8/8/4KB2 w - - 0 1").unwrap();
+
+    assert_eq!(
+        board.scale_factor(&EvalParams::DEFAULT, PlayableTeam::White),
+        SCALE_NORMAL
+    );
+}
+
+#[test]
+fn opposite_coloured_bishops() {
+    let board = Board::from_fen("4k3/5b2/8/8/2PP4/8/8/2B1K3 w - - 0 1").unwrap();
+
+    assert_eq!(
+        board.scale_factor(&EvalParams::DEFAULT, PlayableTeam::White),
+        SCALE_OPPOSITE_BISHOPS
+    );
+
+    let board = Board::from_fen("r3k3/5b2/8/8/2PP4/8/8/R1B1K3 w - - 0 1").unwrap();
+
+    assert_eq!(
+        board.scale_factor(&EvalParams::DEFAULT, PlayableTeam::White),
+        SCALE_OPPOSITE_BISHOPS_WITH_PIECES
+    );
+
+    // Bishops on the same colour aren't scaled
+    let board = Board::from_fen("4k3/8/5b2/8/2PP4/8/8/2B1K3 w - - 0 1").unwrap();
+
+    assert_eq!(
+        board.scale_factor(&EvalParams::DEFAULT, PlayableTeam::White),
+        SCALE_NORMAL
+    );
+
+    let unscaled = board.evaluation_trace();
+
+    assert_eq!(unscaled.scale_factor, SCALE_NORMAL);
+}
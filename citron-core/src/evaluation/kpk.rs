@@ -0,0 +1,202 @@
+//! A bitbase of every king and pawn against king position, saying whether
+//! the side with the pawn wins. It's generated by retrograde analysis the
+//! first time it's probed, with the pawn's side always white and the pawn
+//! always on the queen side, which covers the rest by symmetry.
+
+use std::lazy::SyncLazy;
+
+use crate::{
+    magic::{king_attacks, pawn_attacks, pop_lsb},
+    PlayableTeam, Position,
+};
+
+/// Two kings, a side to move, four files and six ranks for the pawn
+const POSITIONS: usize = 64 * 64 * 2 * 4 * 6;
+
+static KPK: SyncLazy<Vec<u64>> = SyncLazy::new(generate);
+
+// What's known of a position so far. A position's value is the union of
+// the values of the positions it can reach until it's classified
+const INVALID: u8 = 0;
+const UNKNOWN: u8 = 1;
+const DRAW: u8 = 2;
+const WIN: u8 = 4;
+
+/// The index of a position, where the kings and pawn are given by square
+/// index and the pawn is on files a to d
+const fn index(to_play: PlayableTeam, white_king: usize, black_king: usize, pawn: usize) -> usize {
+    white_king
+        | black_king << 6
+        | (to_play as usize) << 12
+        | (pawn % 8) << 13
+        | (6 - pawn / 8) << 15
+}
+
+struct KpkPosition {
+    to_play: PlayableTeam,
+    kings: [usize; 2],
+    pawn: usize,
+    result: u8,
+}
+
+impl KpkPosition {
+    fn new(index: usize) -> Self {
+        let kings = [index & 0x3f, (index >> 6) & 0x3f];
+        let to_play = if (index >> 12) & 1 == 0 {
+            PlayableTeam::White
+        } else {
+            PlayableTeam::Black
+        };
+        let pawn = (6 - ((index >> 15) & 7)) * 8 + ((index >> 13) & 3);
+
+        let king_moves = |king: usize| king_attacks(Position::from_u8(king as u8));
+        let pawn_attack = pawn_attacks(Position::from_u8(pawn as u8), PlayableTeam::White);
+        let distance =
+            |a: usize, b: usize| Position::from_u8(a as u8).distance(&Position::from_u8(b as u8));
+
+        let result = if distance(kings[0], kings[1]) <= 1
+            || kings[0] == pawn
+            || kings[1] == pawn
+            || (to_play == PlayableTeam::White && pawn_attack & 1 << kings[1] != 0)
+        {
+            INVALID
+        } else if to_play == PlayableTeam::White
+            && pawn / 8 == 6
+            && kings[0] != pawn + 8
+            && (distance(kings[1], pawn + 8) > 1 || distance(kings[0], pawn + 8) == 1)
+        {
+            // The pawn promotes without being taken
+            WIN
+        } else if to_play == PlayableTeam::Black
+            && (king_moves(kings[1]) & !(king_moves(kings[0]) | pawn_attack) == 0
+                || king_moves(kings[1]) & !king_moves(kings[0]) & 1 << pawn != 0)
+        {
+            // Stalemate, or the pawn can be taken
+            DRAW
+        } else {
+            UNKNOWN
+        };
+
+        Self {
+            to_play,
+            kings,
+            pawn,
+            result,
+        }
+    }
+    /// Works out the result from the positions that can be reached, if
+    /// enough of them are known
+    fn classify(&self, positions: &[Self]) -> u8 {
+        let (good, bad) = match self.to_play {
+            PlayableTeam::White => (WIN, DRAW),
+            PlayableTeam::Black => (DRAW, WIN),
+        };
+        let other = !self.to_play;
+
+        let mut reachable = INVALID;
+        let mut moves = king_attacks(Position::from_u8(self.kings[self.to_play as usize] as u8));
+
+        while moves != 0 {
+            let to = pop_lsb(&mut moves) as usize;
+
+            reachable |= match self.to_play {
+                PlayableTeam::White => positions[index(other, to, self.kings[1], self.pawn)].result,
+                PlayableTeam::Black => positions[index(other, self.kings[0], to, self.pawn)].result,
+            };
+        }
+
+        if self.to_play == PlayableTeam::White {
+            let [white_king, black_king] = self.kings;
+            let push = self.pawn + 8;
+
+            if self.pawn / 8 < 6 {
+                reachable |= positions[index(other, white_king, black_king, push)].result;
+            }
+
+            if self.pawn / 8 == 1 && push != white_king && push != black_king {
+                reachable |= positions[index(other, white_king, black_king, push + 8)].result;
+            }
+        }
+
+        if reachable & good != 0 {
+            good
+        } else if reachable & UNKNOWN != 0 {
+            UNKNOWN
+        } else {
+            bad
+        }
+    }
+}
+
+fn generate() -> Vec<u64> {
+    let mut positions: Vec<_> = (0..POSITIONS).map(KpkPosition::new).collect();
+    let mut changed = true;
+
+    while changed {
+        changed = false;
+
+        for i in 0..POSITIONS {
+            if positions[i].result == UNKNOWN {
+                let result = positions[i].classify(&positions);
+
+                if result != UNKNOWN {
+                    positions[i].result = result;
+                    changed = true;
+                }
+            }
+        }
+    }
+
+    let mut bits = vec![0; POSITIONS / 64];
+
+    for (i, position) in positions.iter().enumerate() {
+        if position.result == WIN {
+            bits[i / 64] |= 1 << (i % 64);
+        }
+    }
+
+    bits
+}
+
+/// Whether the side with the pawn wins with best play. Squares are given
+/// as if the pawn were white's, and `to_play` is relative to that
+pub fn probe(
+    to_play: PlayableTeam,
+    strong_king: Position,
+    pawn: Position,
+    weak_king: Position,
+) -> bool {
+    // Mirror the pawn onto the queen side
+    let flip = if pawn.x() > 3 { 7 } else { 0 };
+    let square = |position: Position| position.index() as usize ^ flip;
+
+    let i = index(
+        to_play,
+        square(strong_king),
+        square(weak_king),
+        square(pawn),
+    );
+
+    KPK[i / 64] & 1 << (i % 64) != 0
+}
+
+#[test]
+fn kpk_test() {
+    use PlayableTeam::{Black, White};
+
+    let square = |uci: &str| Position::from_uci(uci).unwrap();
+
+    // The king on the sixth rank in front of its pawn always wins
+    assert!(probe(Black, square("e6"), square("e5"), square("e8")));
+    assert!(probe(White, square("e6"), square("e5"), square("e8")));
+    // Stalemate
+    assert!(!probe(Black, square("e6"), square("e7"), square("e8")));
+    // The defending king can't catch the pawn
+    assert!(probe(White, square("a1"), square("h4"), square("a8")));
+    assert!(!probe(Black, square("a1"), square("c4"), square("c6")));
+    // A rook's pawn is drawn with the defending king in the corner
+    assert!(!probe(White, square("b6"), square("a5"), square("a8")));
+    assert!(!probe(White, square("g1"), square("h2"), square("h8")));
+    // The pawn promotes
+    assert!(probe(White, square("c6"), square("b7"), square("d8")));
+}
@@ -1,18 +1,232 @@
-use crate::Board;
+use super::EvalParams;
+use crate::{
+    magic::{self, pop_lsb, MASK_FILE, MASK_RANK},
+    piece::PieceKind,
+    Board, PlayableTeam, Position,
+};
 
 impl Board {
     #[must_use]
-    pub fn middle_game_evaluation(&self) -> i16 {
-        /*10 * (self.positions_pieces().fold(0, |moves, (position, p)| {
-            let mobility = p.mobility(position, self);
-            if p.team() == Team::Black {
-                moves - mobility
-            } else {
-                moves + mobility
+    pub fn middle_game_evaluation(&self, params: &EvalParams) -> i16 {
+        self.mobility(params, PlayableTeam::White) - self.mobility(params, PlayableTeam::Black)
+            + self.king_safety(params, PlayableTeam::White)
+            - self.king_safety(params, PlayableTeam::Black)
+    }
+    /// The mobility bonus for `team`, counting the squares each knight,
+    /// bishop, rook and queen can move to, excluding squares occupied by
+    /// its own pieces or attacked by enemy pawns
+    pub(crate) fn mobility(&self, params: &EvalParams, team: PlayableTeam) -> i16 {
+        let blockers = self.get_occupied();
+        let enemy_pawns = self.pieces[!team as usize][PieceKind::Pawn as usize];
+        let available =
+            !self.all_pieces[team as usize] & !magic::all_pawn_attacks(enemy_pawns, !team);
+
+        let mut mobility = 0;
+
+        for kind in [
+            PieceKind::Knight,
+            PieceKind::Bishop,
+            PieceKind::Rook,
+            PieceKind::Queen,
+        ] {
+            let mut pieces = self.pieces[team as usize][kind as usize];
+
+            while pieces != 0 {
+                let position = Position::from_u8(pop_lsb(&mut pieces) as u8);
+                let moves =
+                    Self::get_attacks_for_square(team, kind, position, blockers) & available;
+
+                mobility += moves.count_ones() as i16 * params.mobility_weights[kind as usize];
+            }
+        }
+
+        mobility
+    }
+    /// The king safety score for `team`, which is positive for a well
+    /// protected king and negative for an exposed one
+    pub(crate) fn king_safety(&self, params: &EvalParams, team: PlayableTeam) -> i16 {
+        self.king_position(team).map_or(0, |king| {
+            self.pawn_shield(params, team, king)
+                - self.king_open_files(params, team, king)
+                - self.king_attack(params, team, king)
+        })
+    }
+    /// The bonus for `team`'s pawns sheltering its king
+    pub(crate) fn pawn_shield(
+        &self,
+        params: &EvalParams,
+        team: PlayableTeam,
+        king: Position,
+    ) -> i16 {
+        let pawns = self.pieces[team as usize][PieceKind::Pawn as usize];
+        let files = Self::adjacent_files(king);
+
+        let (close, far) = match team {
+            PlayableTeam::White => (king.y() + 1, king.y() + 2),
+            PlayableTeam::Black => (king.y().wrapping_sub(1), king.y().wrapping_sub(2)),
+        };
+
+        let rank = |y: u8| MASK_RANK.get(y as usize).copied().unwrap_or(0);
+
+        (pawns & files & rank(close)).count_ones() as i16 * params.pawn_shield_weight
+            + (pawns & files & rank(far)).count_ones() as i16 * params.pawn_shield_weight / 2
+    }
+    /// The penalty for open and semi-open files on and next to `team`'s king
+    pub(crate) fn king_open_files(
+        &self,
+        params: &EvalParams,
+        team: PlayableTeam,
+        king: Position,
+    ) -> i16 {
+        let own_pawns = self.pieces[team as usize][PieceKind::Pawn as usize];
+        let enemy_pawns = self.pieces[!team as usize][PieceKind::Pawn as usize];
+
+        let mut penalty = 0;
+
+        for x in king.x().saturating_sub(1)..=(king.x() + 1).min(7) {
+            let file = MASK_FILE[x as usize];
+
+            if own_pawns & file == 0 {
+                penalty += params.semi_open_file_penalty;
+
+                if enemy_pawns & file == 0 {
+                    penalty += params.open_file_penalty;
+                }
+            }
+        }
+
+        penalty
+    }
+    /// The penalty for enemy pieces attacking the zone around `team`'s
+    /// king. Attack units grow quadratically, and are only counted once
+    /// at least two pieces join the attack
+    pub(crate) fn king_attack(
+        &self,
+        params: &EvalParams,
+        team: PlayableTeam,
+        king: Position,
+    ) -> i16 {
+        let zone = magic::king_attacks(king) | king.to_bitmap();
+        let blockers = self.get_occupied();
+
+        let mut attackers = 0;
+        let mut units = 0;
+
+        for kind in [
+            PieceKind::Knight,
+            PieceKind::Bishop,
+            PieceKind::Rook,
+            PieceKind::Queen,
+        ] {
+            let mut pieces = self.pieces[!team as usize][kind as usize];
+
+            while pieces != 0 {
+                let position = Position::from_u8(pop_lsb(&mut pieces) as u8);
+                let attacked = Self::get_attacks_for_square(!team, kind, position, blockers) & zone;
+
+                if attacked != 0 {
+                    attackers += 1;
+                    units += attacked.count_ones() as i16 * params.king_attack_units[kind as usize];
+                }
             }
-        }) >> 1)*/
-        // - self[self.king_positions.0].virtual_mobility(self.king_positions.0, self) * 7
-        // + self[self.king_positions.1].virtual_mobility(self.king_positions.1, self) * 7
-        0
+        }
+
+        if attackers < 2 {
+            0
+        } else {
+            (units * units / 4).min(params.maximum_king_danger)
+        }
+    }
+    /// Returns the position of `team`'s king, if it's still on the board
+    pub(crate) fn king_position(&self, team: PlayableTeam) -> Option<Position> {
+        let king = self.pieces[team as usize][PieceKind::King as usize];
+
+        if king == 0 {
+            None
+        } else {
+            Some(Position::from_bitmap(king))
+        }
+    }
+    /// Returns a mask of the king's file and the files either side of it
+    fn adjacent_files(king: Position) -> u64 {
+        (king.x().saturating_sub(1)..=(king.x() + 1).min(7))
+            .fold(0, |files, x| files | MASK_FILE[x as usize])
     }
 }
+
+#[test]
+fn mobility_test() {
+    let params = EvalParams::default();
+    let board = Board::new();
+
+    // Only the knights can move in the initial position, each to two squares
+    assert_eq!(
+        board.mobility(&params, PlayableTeam::White),
+        4 * params.mobility_weights[2]
+    );
+    assert_eq!(board.middle_game_evaluation(&params), 0);
+
+    // The knight on d4 can't use the squares the c6 pawn attacks
+    let board = Board::from_fen("4k3/8/2p5/8/3N4/8/8/4K3 w - - 0 1").unwrap();
+    assert_eq!(
+        board.mobility(&params, PlayableTeam::White),
+        7 * params.mobility_weights[2]
+    );
+}
+
+#[test]
+fn pawn_shield_test() {
+    let params = EvalParams::default();
+    let board = Board::from_fen("6k1/5ppp/8/8/8/6P1/5P1P/6K1 w - - 0 1").unwrap();
+
+    let white_king = Position::from_uci("g1").unwrap();
+    let black_king = Position::from_uci("g8").unwrap();
+
+    assert_eq!(
+        board.pawn_shield(&params, PlayableTeam::White, white_king),
+        2 * params.pawn_shield_weight + params.pawn_shield_weight / 2
+    );
+    assert_eq!(
+        board.pawn_shield(&params, PlayableTeam::Black, black_king),
+        3 * params.pawn_shield_weight
+    );
+}
+
+#[test]
+fn king_open_files_test() {
+    let params = EvalParams::default();
+    // White's g file is half open, and the h file fully open
+    let board = Board::from_fen("6k1/5pp1/8/8/8/8/5P2/6K1 w - - 0 1").unwrap();
+
+    let king = Position::from_uci("g1").unwrap();
+
+    assert_eq!(
+        board.king_open_files(&params, PlayableTeam::White, king),
+        2 * params.semi_open_file_penalty + params.open_file_penalty
+    );
+    assert_eq!(
+        board.king_open_files(
+            &params,
+            PlayableTeam::Black,
+            Position::from_uci("g8").unwrap()
+        ),
+        params.semi_open_file_penalty + params.open_file_penalty
+    );
+}
+
+#[test]
+fn king_attack_test() {
+    let params = EvalParams::default();
+    let king = Position::from_uci("g8").unwrap();
+
+    // A lone queen isn't counted as an attack
+    let board = Board::from_fen("6k1/5ppp/8/7Q/8/8/5PPP/6K1 w - - 0 1").unwrap();
+    assert_eq!(board.king_attack(&params, PlayableTeam::Black, king), 0);
+
+    let board = Board::from_fen("6k1/5ppp/8/6NQ/8/8/5PPP/6K1 w - - 0 1").unwrap();
+    assert!(board.king_attack(&params, PlayableTeam::Black, king) > 0);
+    assert!(
+        board.king_safety(&params, PlayableTeam::Black)
+            < board.king_safety(&params, PlayableTeam::White)
+    );
+}
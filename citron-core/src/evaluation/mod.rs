@@ -1,21 +1,115 @@
-use core::cmp::Ordering;
+use core::{
+    cmp::Ordering,
+    ops::{Add, AddAssign, Mul, Neg, Sub, SubAssign},
+};
 
-mod early_game;
 mod end_game;
+mod endgames;
+mod kpk;
 mod mid_game;
+mod params;
+mod pawns;
+mod pieces;
+mod trace;
+
+pub use endgames::SCALE_NORMAL;
+pub use params::{eval_params, EvalParams, EvalParamsError};
+pub(crate) use params::scoped_params;
+pub use trace::{EvalTerm, EvalTrace};
 
 #[cfg(feature = "debug")]
 use core::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
 
 use crate::{
-    piece::{PieceKind, PAWN_VALUE},
-    Board, PlayableTeam,
+    magic::pop_lsb,
+    piece::{Piece, PieceKind},
+    Board, PlayableTeam, Position,
 };
 
 #[cfg(feature = "debug")]
 pub static POSITIONS_CONSIDERED: AtomicUsize = AtomicUsize::new(0);
 
-const DEFAULT_MAXIMUM_ABSOLUTE_MATERIAL: i16 = 78 * PAWN_VALUE + 100;
+/// The game phase of the initial position. See [`PieceKind::phase`]
+pub const MAXIMUM_PHASE: i16 = 24;
+
+/// A pair of scores, one for the middle game and one for the end game,
+/// which are blended together based on the game phase
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TaperedScore {
+    pub middle_game: i16,
+    pub end_game: i16,
+}
+
+impl TaperedScore {
+    #[must_use]
+    pub const fn new(middle_game: i16, end_game: i16) -> Self {
+        Self {
+            middle_game,
+            end_game,
+        }
+    }
+    /// Interpolates between the middle and end game scores, where a
+    /// `phase` of [`MAXIMUM_PHASE`] is entirely the middle game, and a
+    /// `phase` of 0 is entirely the end game
+    #[must_use]
+    pub fn taper(&self, phase: i16) -> i16 {
+        let phase = i32::from(phase.clamp(0, MAXIMUM_PHASE));
+
+        ((i32::from(self.middle_game) * phase
+            + i32::from(self.end_game) * (i32::from(MAXIMUM_PHASE) - phase))
+            / i32::from(MAXIMUM_PHASE)) as i16
+    }
+}
+
+impl Add for TaperedScore {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        Self::new(
+            self.middle_game + rhs.middle_game,
+            self.end_game + rhs.end_game,
+        )
+    }
+}
+
+impl Sub for TaperedScore {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self::Output {
+        Self::new(
+            self.middle_game - rhs.middle_game,
+            self.end_game - rhs.end_game,
+        )
+    }
+}
+
+impl Neg for TaperedScore {
+    type Output = Self;
+
+    fn neg(self) -> Self::Output {
+        Self::new(-self.middle_game, -self.end_game)
+    }
+}
+
+impl Mul<i16> for TaperedScore {
+    type Output = Self;
+
+    fn mul(self, rhs: i16) -> Self {
+        Self::new(self.middle_game * rhs, self.end_game * rhs)
+    }
+}
+
+impl AddAssign for TaperedScore {
+    fn add_assign(&mut self, rhs: Self) {
+        *self = *self + rhs;
+    }
+}
+
+impl SubAssign for TaperedScore {
+    fn sub_assign(&mut self, rhs: Self) {
+        *self = *self - rhs;
+    }
+}
 
 impl Board {
     #[must_use]
@@ -23,15 +117,89 @@ impl Board {
         #[cfg(feature = "debug")]
         POSITIONS_CONSIDERED.fetch_add(1, AtomicOrdering::SeqCst);
 
-        self.material
-            + self.trade_bonus()
-            + if self.turn <= 30 {
-                self.early_game_evaluation()
-            } else if self.turn <= 70 {
-                self.middle_game_evaluation()
-            } else {
-                self.end_game_evaluation()
+        if self.is_known_draw() {
+            return 0;
+        }
+
+        #[cfg(feature = "nn_evaluation")]
+        if let Some(evaluation) = self.nnue_evaluation() {
+            return self.scale_evaluation(scoped_params(), evaluation);
+        }
+
+        self.evaluate(
+            scoped_params(),
+            self.piece_square_score,
+            self.pawn_structure(),
+        )
+    }
+    /// Evaluates the board with `params` rather than the installed
+    /// parameters. Nothing is cached or kept up to date incrementally, so
+    /// this is slower than [`Board::static_evaluation`], but is what tuning
+    /// needs
+    #[must_use]
+    pub fn evaluate_with(&self, params: &EvalParams) -> i16 {
+        let pawn_structure = self.pawn_structure_for(params, PlayableTeam::White)
+            - self.pawn_structure_for(params, PlayableTeam::Black);
+
+        self.evaluate(params, self.piece_square_score_with(params), pawn_structure)
+    }
+    fn evaluate(
+        &self,
+        params: &EvalParams,
+        piece_square_score: TaperedScore,
+        pawn_structure: TaperedScore,
+    ) -> i16 {
+        let score = piece_square_score
+            + pawn_structure
+            + self.piece_evaluation(params)
+            + TaperedScore::new(
+                self.middle_game_evaluation(params),
+                self.end_game_evaluation(params),
+            );
+
+        self.scale_evaluation(
+            params,
+            self.material_for(params, PlayableTeam::White)
+                - self.material_for(params, PlayableTeam::Black)
+                + self.trade_bonus(params)
+                + score.taper(self.phase),
+        )
+    }
+    /// The value of `team`'s pieces, excluding its king
+    pub(crate) fn material_for(&self, params: &EvalParams, team: PlayableTeam) -> i16 {
+        PieceKind::kinds_no_king()
+            .into_iter()
+            .map(|kind| {
+                self.pieces[team as usize][kind as usize].count_ones() as i16
+                    * params.piece_values[kind as usize]
+            })
+            .sum()
+    }
+    /// The sum of every piece's piece-square score with `params`, from
+    /// white's perspective
+    fn piece_square_score_with(&self, params: &EvalParams) -> TaperedScore {
+        let mut score = TaperedScore::new(0, 0);
+
+        for team in PlayableTeam::teams() {
+            for kind in PieceKind::kinds() {
+                let piece = Piece::new(team.into(), kind);
+                let mut pieces = self.pieces[team as usize][kind as usize];
+
+                while pieces != 0 {
+                    let position = Position::from_u8(pop_lsb(&mut pieces) as u8);
+                    score += piece.positional_value_with(params, position);
+                }
             }
+        }
+
+        score
+    }
+    /// Returns the game phase, based off the non-pawn material remaining.
+    /// It's [`MAXIMUM_PHASE`] in the initial position, and falls to 0 as
+    /// pieces are traded off
+    #[must_use]
+    pub const fn phase(&self) -> i16 {
+        self.phase
     }
     pub fn calculate_material(&mut self) {
         for kind in PieceKind::kinds_no_king() {
@@ -53,11 +221,56 @@ impl Board {
     /// If a side is up material, they wish to get the
     /// recrateing amount of material on the board as low as
     /// possible
-    fn trade_bonus(&self) -> i16 {
-        match self.material.cmp(&0) {
-            Ordering::Less => -((DEFAULT_MAXIMUM_ABSOLUTE_MATERIAL - self.absolute_material) >> 7),
+    fn trade_bonus(&self, params: &EvalParams) -> i16 {
+        let white = self.material_for(params, PlayableTeam::White);
+        let black = self.material_for(params, PlayableTeam::Black);
+        let bonus = (params.maximum_absolute_material - (white + black)) >> 7;
+
+        match white.cmp(&black) {
+            Ordering::Less => -bonus,
             Ordering::Equal => 0,
-            Ordering::Greater => (DEFAULT_MAXIMUM_ABSOLUTE_MATERIAL - self.absolute_material) >> 7,
+            Ordering::Greater => bonus,
         }
     }
 }
+
+#[test]
+fn taper_test() {
+    let score = TaperedScore::new(100, -50);
+
+    assert_eq!(score.taper(MAXIMUM_PHASE), 100);
+    assert_eq!(score.taper(0), -50);
+    assert_eq!(score.taper(MAXIMUM_PHASE / 2), 25);
+    // Promotions can push the phase above the maximum
+    assert_eq!(score.taper(MAXIMUM_PHASE + 4), 100);
+}
+
+#[test]
+fn phase_is_material_based() {
+    let board = Board::new();
+    assert_eq!(board.phase(), MAXIMUM_PHASE);
+
+    // Bare kings are an end game, whatever the move number
+    let board = Board::from_fen("4k3/8/8/8/8/8/8/4K3 w - - 0 10").unwrap();
+    assert_eq!(board.phase(), 0);
+
+    let board = Board::from_fen("r3k3/8/8/8/8/8/8/4KQ2 w - - 0 10").unwrap();
+    assert_eq!(board.phase(), 6);
+}
+
+#[test]
+fn piece_square_score_is_incremental() {
+    let mut board =
+        Board::from_fen("r2q1rk1/1p3p1p/1b4p1/pPp2b2/3pn1P1/P2Q4/B1P1NP1P/R1B2RK1 b - - 0 30")
+            .unwrap();
+
+    for uci in ["e4f2", "f1f2", "b6f2", "g1f2"] {
+        let played_move = board.parse_uci_move(uci).unwrap();
+        board.make_move_mut(&played_move);
+
+        let recalculated = Board::from_fen(&board.to_fen()).unwrap();
+
+        assert_eq!(board.piece_square_score, recalculated.piece_square_score);
+        assert_eq!(board.phase, recalculated.phase);
+    }
+}
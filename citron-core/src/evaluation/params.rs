@@ -0,0 +1,484 @@
+use core::fmt::{self, Display};
+use std::{cell::Cell, lazy::SyncOnceCell, path::Path};
+
+use super::TaperedScore;
+use crate::{
+    heatmap::{END_GAME_TABLES, MIDDLE_GAME_TABLES},
+    piece::{
+        PieceKind, BISHOP_VALUE, KING_VALUE, KNIGHT_VALUE, PAWN_VALUE, QUEEN_VALUE, ROOK_VALUE,
+    },
+};
+
+/// The parameters the engine evaluates with, set once by [`EvalParams::install`]
+static EVAL_PARAMS: SyncOnceCell<EvalParams> = SyncOnceCell::new();
+
+thread_local! {
+    /// Parameters that replace the installed ones in static evaluations on
+    /// this thread, set by [`EvalParams::scope`]
+    static SCOPED_PARAMS: Cell<Option<&'static EvalParams>> = const { Cell::new(None) };
+}
+
+/// Every weight used by the static evaluation, so they can be tuned and
+/// loaded from a file, rather than being hard-coded
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EvalParams {
+    /// The value of each piece, indexed by [`PieceKind`]. Everything that
+    /// counts material goes by these, except for the king, which is always
+    /// worth [`KING_VALUE`]
+    pub piece_values: [i16; 6],
+    /// The middle game piece-square tables, indexed by [`PieceKind`]. Each
+    /// table is written from white's side of the board, with the 8th rank
+    /// first, and is mirrored vertically for black's pieces
+    pub middle_game_tables: [[i16; 64]; 6],
+    /// The end game piece-square tables, laid out as the middle game ones
+    pub end_game_tables: [[i16; 64]; 6],
+    /// The margin a capture must be able to raise the score by to be
+    /// searched in quiescence
+    pub delta: i16,
+    /// The absolute material in the initial position, which the trade
+    /// bonus grows from as pieces are traded off
+    pub maximum_absolute_material: i16,
+
+    /// The bonus for each square a piece can move to, indexed by [`PieceKind`]
+    pub mobility_weights: [i16; 6],
+    /// The bonus for each pawn directly in front of, or diagonally in front
+    /// of, the king. Pawns a further square ahead are worth half
+    pub pawn_shield_weight: i16,
+    /// The penalty for each file on or next to the king without any of its
+    /// own pawns on it
+    pub semi_open_file_penalty: i16,
+    /// The extra penalty for each file on or next to the king without any
+    /// pawns on it at all
+    pub open_file_penalty: i16,
+    /// The attack units for each square a piece attacks in the enemy king's
+    /// zone, indexed by [`PieceKind`]
+    pub king_attack_units: [i16; 6],
+    /// The most the king attack penalty can reach
+    pub maximum_king_danger: i16,
+
+    /// The penalty for each square the king is away from the centre
+    pub king_centre_weight: i16,
+    /// The penalty for each square the king is away from the closest pawn
+    pub king_pawn_distance_weight: i16,
+    /// The extra bonus for a passed pawn with nothing in front of it,
+    /// indexed by its relative rank
+    pub free_passed_pawn_bonus: [i16; 8],
+    /// The bonus for a passed pawn the enemy king can't catch, when the
+    /// enemy has no pieces left to stop it with
+    pub unstoppable_pawn_bonus: i16,
+    /// The bonus for each square the losing king is away from the centre
+    pub mop_up_centre_weight: i16,
+    /// The bonus for each square the kings are closer together than the
+    /// furthest they could be apart
+    pub mop_up_king_distance_weight: i16,
+
+    /// The penalty for each pawn on a file beyond the first
+    pub doubled_pawn_penalty: TaperedScore,
+    /// The penalty for a pawn with no friendly pawns on the neighbouring files
+    pub isolated_pawn_penalty: TaperedScore,
+    /// The penalty for a pawn that's behind its neighbours, and can't
+    /// safely advance to join them
+    pub backward_pawn_penalty: TaperedScore,
+    /// The bonus for a pawn defended by another pawn
+    pub supported_pawn_bonus: TaperedScore,
+    /// The bonus for a pawn with a friendly pawn beside it on the same rank
+    pub phalanx_pawn_bonus: TaperedScore,
+    /// The penalty for each group of pawns on adjacent files, beyond the first
+    pub pawn_island_penalty: TaperedScore,
+    /// The bonus for a passed pawn, indexed by its rank relative to its side
+    pub passed_pawn_bonus: [TaperedScore; 8],
+    /// The extra bonus for a passed pawn defended by another pawn
+    pub supported_passed_pawn_bonus: TaperedScore,
+
+    /// The bonus for having both bishops
+    pub bishop_pair_bonus: TaperedScore,
+    /// The bonus for a rook on a file without any pawns
+    pub rook_open_file_bonus: TaperedScore,
+    /// The bonus for a rook on a file without any of its own pawns
+    pub rook_semi_open_file_bonus: TaperedScore,
+    /// The bonus for a rook on the 7th rank, when it's trapping the enemy
+    /// king or attacking pawns there
+    pub rook_on_seventh_bonus: TaperedScore,
+    /// The bonus for an outpost, indexed by [`PieceKind`]. Only knights and
+    /// bishops can hold outposts
+    pub outpost_bonus: [TaperedScore; 6],
+    /// The penalty for each of its own pawns on a bishop's square colour.
+    /// Blocked pawns count twice, as they'll be stuck there
+    pub bad_bishop_penalty: TaperedScore,
+    /// The penalty for each minor piece still at home once the queen has moved
+    pub early_queen_penalty: TaperedScore,
+}
+
+/// The error returned when a parameters file can't be loaded
+#[derive(Debug)]
+pub enum EvalParamsError {
+    Io(std::io::Error),
+    /// A line names a parameter that doesn't exist
+    UnknownParameter(String),
+    /// A parameter has the wrong number of values, or one isn't a number
+    InvalidValues(String),
+}
+
+impl Display for EvalParamsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "{}", e),
+            Self::UnknownParameter(name) => write!(f, "Unknown parameter {}", name),
+            Self::InvalidValues(name) => write!(f, "Invalid values for parameter {}", name),
+        }
+    }
+}
+
+impl From<std::io::Error> for EvalParamsError {
+    fn from(e: std::io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl EvalParams {
+    /// The hand-picked parameters citron evaluates with by default
+    pub const DEFAULT: Self = Self {
+        piece_values: [
+            PAWN_VALUE,
+            ROOK_VALUE,
+            KNIGHT_VALUE,
+            BISHOP_VALUE,
+            QUEEN_VALUE,
+            0,
+        ],
+        middle_game_tables: MIDDLE_GAME_TABLES,
+        end_game_tables: END_GAME_TABLES,
+        delta: 2 * PAWN_VALUE,
+        maximum_absolute_material: 78 * PAWN_VALUE + 100,
+
+        mobility_weights: [0, 2, 4, 5, 1, 0],
+        pawn_shield_weight: 12,
+        semi_open_file_penalty: 15,
+        open_file_penalty: 10,
+        king_attack_units: [0, 3, 2, 2, 5, 0],
+        maximum_king_danger: 500,
+
+        king_centre_weight: 6,
+        king_pawn_distance_weight: 4,
+        free_passed_pawn_bonus: [0, 5, 5, 10, 20, 35, 60, 0],
+        unstoppable_pawn_bonus: 600,
+        mop_up_centre_weight: 10,
+        mop_up_king_distance_weight: 4,
+
+        doubled_pawn_penalty: TaperedScore::new(10, 20),
+        isolated_pawn_penalty: TaperedScore::new(10, 15),
+        backward_pawn_penalty: TaperedScore::new(8, 10),
+        supported_pawn_bonus: TaperedScore::new(8, 10),
+        phalanx_pawn_bonus: TaperedScore::new(6, 6),
+        pawn_island_penalty: TaperedScore::new(5, 10),
+        passed_pawn_bonus: [
+            TaperedScore::new(0, 0),
+            TaperedScore::new(5, 10),
+            TaperedScore::new(5, 15),
+            TaperedScore::new(10, 25),
+            TaperedScore::new(15, 45),
+            TaperedScore::new(25, 75),
+            TaperedScore::new(40, 120),
+            TaperedScore::new(0, 0),
+        ],
+        supported_passed_pawn_bonus: TaperedScore::new(0, 15),
+
+        bishop_pair_bonus: TaperedScore::new(30, 50),
+        rook_open_file_bonus: TaperedScore::new(25, 10),
+        rook_semi_open_file_bonus: TaperedScore::new(12, 6),
+        rook_on_seventh_bonus: TaperedScore::new(20, 30),
+        outpost_bonus: [
+            TaperedScore::new(0, 0),
+            TaperedScore::new(0, 0),
+            TaperedScore::new(25, 15),
+            TaperedScore::new(15, 8),
+            TaperedScore::new(0, 0),
+            TaperedScore::new(0, 0),
+        ],
+        bad_bishop_penalty: TaperedScore::new(3, 5),
+        early_queen_penalty: TaperedScore::new(8, 0),
+    };
+
+    /// Makes these the parameters the engine evaluates with. This must be
+    /// done before any boards are created, as the piece-square score and
+    /// material are kept up to date as pieces move. Returns false if some
+    /// were already installed
+    pub fn install(self) -> bool {
+        EVAL_PARAMS.set(self).is_ok()
+    }
+    /// The value of a piece of `kind`
+    #[must_use]
+    pub const fn piece_value(&self, kind: PieceKind) -> i16 {
+        match kind {
+            PieceKind::King => KING_VALUE,
+            PieceKind::None => 0,
+            kind => self.piece_values[kind as usize],
+        }
+    }
+    /// Evaluates with these parameters in place of the installed ones
+    /// while `f` runs on this thread, so that engines with different
+    /// parameters can play each other. The piece-square and pawn structure
+    /// scores still come from the installed parameters, as they're kept up
+    /// to date as pieces move and cached by pawn structure
+    pub fn scope<T>(&'static self, f: impl FnOnce() -> T) -> T {
+        let previous = SCOPED_PARAMS.with(|scoped| scoped.replace(Some(self)));
+        let result = f();
+
+        SCOPED_PARAMS.with(|scoped| scoped.set(previous));
+
+        result
+    }
+    /// Loads parameters from a file written by [`EvalParams::save`]. Any
+    /// parameters missing from the file keep their default values
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, EvalParamsError> {
+        std::fs::read_to_string(path)?.parse()
+    }
+    /// Writes the parameters to a file, one parameter per line
+    pub fn save(&self, path: impl AsRef<Path>) -> std::io::Result<()> {
+        std::fs::write(path, self.to_string())
+    }
+    /// Returns every parameter by name, with mutable references to each of
+    /// its values
+    pub fn fields_mut(&mut self) -> Vec<(&'static str, Vec<&mut i16>)> {
+        fn scalar(value: &mut i16) -> Vec<&mut i16> {
+            vec![value]
+        }
+        fn tapered(score: &mut TaperedScore) -> Vec<&mut i16> {
+            vec![&mut score.middle_game, &mut score.end_game]
+        }
+        fn tapered_array(scores: &mut [TaperedScore]) -> Vec<&mut i16> {
+            scores.iter_mut().flat_map(tapered).collect()
+        }
+
+        let [pawn_middle, rook_middle, knight_middle, bishop_middle, queen_middle, king_middle] =
+            &mut self.middle_game_tables;
+        let [pawn_end, rook_end, knight_end, bishop_end, queen_end, king_end] =
+            &mut self.end_game_tables;
+
+        vec![
+            ("piece_values", self.piece_values.iter_mut().collect()),
+            ("pawn_middle_game", pawn_middle.iter_mut().collect()),
+            ("rook_middle_game", rook_middle.iter_mut().collect()),
+            ("knight_middle_game", knight_middle.iter_mut().collect()),
+            ("bishop_middle_game", bishop_middle.iter_mut().collect()),
+            ("queen_middle_game", queen_middle.iter_mut().collect()),
+            ("king_middle_game", king_middle.iter_mut().collect()),
+            ("pawn_end_game", pawn_end.iter_mut().collect()),
+            ("rook_end_game", rook_end.iter_mut().collect()),
+            ("knight_end_game", knight_end.iter_mut().collect()),
+            ("bishop_end_game", bishop_end.iter_mut().collect()),
+            ("queen_end_game", queen_end.iter_mut().collect()),
+            ("king_end_game", king_end.iter_mut().collect()),
+            ("delta", scalar(&mut self.delta)),
+            (
+                "maximum_absolute_material",
+                scalar(&mut self.maximum_absolute_material),
+            ),
+            (
+                "mobility_weights",
+                self.mobility_weights.iter_mut().collect(),
+            ),
+            ("pawn_shield_weight", scalar(&mut self.pawn_shield_weight)),
+            (
+                "semi_open_file_penalty",
+                scalar(&mut self.semi_open_file_penalty),
+            ),
+            ("open_file_penalty", scalar(&mut self.open_file_penalty)),
+            (
+                "king_attack_units",
+                self.king_attack_units.iter_mut().collect(),
+            ),
+            ("maximum_king_danger", scalar(&mut self.maximum_king_danger)),
+            ("king_centre_weight", scalar(&mut self.king_centre_weight)),
+            (
+                "king_pawn_distance_weight",
+                scalar(&mut self.king_pawn_distance_weight),
+            ),
+            (
+                "free_passed_pawn_bonus",
+                self.free_passed_pawn_bonus.iter_mut().collect(),
+            ),
+            (
+                "unstoppable_pawn_bonus",
+                scalar(&mut self.unstoppable_pawn_bonus),
+            ),
+            (
+                "mop_up_centre_weight",
+                scalar(&mut self.mop_up_centre_weight),
+            ),
+            (
+                "mop_up_king_distance_weight",
+                scalar(&mut self.mop_up_king_distance_weight),
+            ),
+            (
+                "doubled_pawn_penalty",
+                tapered(&mut self.doubled_pawn_penalty),
+            ),
+            (
+                "isolated_pawn_penalty",
+                tapered(&mut self.isolated_pawn_penalty),
+            ),
+            (
+                "backward_pawn_penalty",
+                tapered(&mut self.backward_pawn_penalty),
+            ),
+            (
+                "supported_pawn_bonus",
+                tapered(&mut self.supported_pawn_bonus),
+            ),
+            ("phalanx_pawn_bonus", tapered(&mut self.phalanx_pawn_bonus)),
+            (
+                "pawn_island_penalty",
+                tapered(&mut self.pawn_island_penalty),
+            ),
+            (
+                "passed_pawn_bonus",
+                tapered_array(&mut self.passed_pawn_bonus),
+            ),
+            (
+                "supported_passed_pawn_bonus",
+                tapered(&mut self.supported_passed_pawn_bonus),
+            ),
+            ("bishop_pair_bonus", tapered(&mut self.bishop_pair_bonus)),
+            (
+                "rook_open_file_bonus",
+                tapered(&mut self.rook_open_file_bonus),
+            ),
+            (
+                "rook_semi_open_file_bonus",
+                tapered(&mut self.rook_semi_open_file_bonus),
+            ),
+            (
+                "rook_on_seventh_bonus",
+                tapered(&mut self.rook_on_seventh_bonus),
+            ),
+            ("outpost_bonus", tapered_array(&mut self.outpost_bonus)),
+            ("bad_bishop_penalty", tapered(&mut self.bad_bishop_penalty)),
+            (
+                "early_queen_penalty",
+                tapered(&mut self.early_queen_penalty),
+            ),
+        ]
+    }
+    /// Returns every value of every parameter, in a fixed order, for tuning
+    pub fn values_mut(&mut self) -> Vec<&mut i16> {
+        self.fields_mut()
+            .into_iter()
+            .flat_map(|(_, values)| values)
+            .collect()
+    }
+}
+
+impl Default for EvalParams {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+impl Display for EvalParams {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (name, values) in self.clone().fields_mut() {
+            write!(f, "{}", name)?;
+
+            for value in values {
+                write!(f, " {}", value)?;
+            }
+
+            writeln!(f)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl core::str::FromStr for EvalParams {
+    type Err = EvalParamsError;
+
+    /// Parses parameters written by [`Display`], one parameter per line,
+    /// with its name followed by its values. Blank lines and lines starting
+    /// with `#` are skipped
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let mut params = Self::default();
+
+        {
+            let mut fields = params.fields_mut();
+
+            for line in input.lines().map(str::trim) {
+                if line.is_empty() || line.starts_with('#') {
+                    continue;
+                }
+
+                let mut tokens = line.split_whitespace();
+                let name = tokens.next().unwrap_or_default();
+
+                let field = fields
+                    .iter_mut()
+                    .find(|(field, _)| *field == name)
+                    .ok_or_else(|| EvalParamsError::UnknownParameter(name.to_string()))?;
+
+                let values = tokens
+                    .map(str::parse)
+                    .collect::<Result<Vec<i16>, _>>()
+                    .map_err(|_| EvalParamsError::InvalidValues(name.to_string()))?;
+
+                if values.len() != field.1.len() {
+                    return Err(EvalParamsError::InvalidValues(name.to_string()));
+                }
+
+                for (target, value) in field.1.iter_mut().zip(values) {
+                    **target = value;
+                }
+            }
+        }
+
+        Ok(params)
+    }
+}
+
+/// Returns the parameters the engine evaluates with, which are the
+/// defaults unless others have been installed
+pub fn eval_params() -> &'static EvalParams {
+    EVAL_PARAMS.get().unwrap_or(&EvalParams::DEFAULT)
+}
+
+/// Returns the parameters static evaluations on this thread use, which are
+/// those of the enclosing [`EvalParams::scope`], if there is one
+pub(crate) fn scoped_params() -> &'static EvalParams {
+    SCOPED_PARAMS.with(Cell::get).unwrap_or_else(eval_params)
+}
+
+#[test]
+fn params_round_trip() {
+    let mut params = EvalParams::default();
+
+    params.piece_values[0] = 90;
+    params.passed_pawn_bonus[6] = TaperedScore::new(50, 150);
+    params.middle_game_tables[5][62] = -7;
+
+    assert_eq!(params.to_string().parse::<EvalParams>().unwrap(), params);
+    assert_eq!("delta 150\n".parse::<EvalParams>().unwrap().delta, 150);
+
+    assert!(matches!(
+        "knight_value 300".parse::<EvalParams>(),
+        Err(EvalParamsError::UnknownParameter(_))
+    ));
+    assert!(matches!(
+        "bishop_pair_bonus 30".parse::<EvalParams>(),
+        Err(EvalParamsError::InvalidValues(_))
+    ));
+}
+
+#[test]
+fn params_scope() {
+    let board = crate::Board::from_fen("4k3/8/8/8/8/8/8/3QK3 w - - 0 1").unwrap();
+    let installed = board.static_evaluation();
+
+    let mut params = eval_params().clone();
+    params.piece_values[PieceKind::Queen as usize] += 100;
+    let params: &'static EvalParams = Box::leak(Box::new(params));
+
+    // The queen is worth 100 more, and with more material on the board the
+    // trade bonus is a point less
+    assert_eq!(params.scope(|| board.static_evaluation()), installed + 99);
+    assert_eq!(board.static_evaluation(), installed);
+}
@@ -0,0 +1,325 @@
+use std::cell::RefCell;
+
+use super::{eval_params, EvalParams, TaperedScore};
+use crate::{
+    magic::{self, pop_lsb, MASK_FILE, MASK_RANK},
+    piece::PieceKind,
+    Board, PlayableTeam, Position,
+};
+
+/// The number of entries in the pawn hash table
+const PAWN_TABLE_SIZE: usize = 1 << 14;
+
+thread_local! {
+    /// Each thread keeps its own pawn hash table, so evaluation doesn't
+    /// need any mutable state passed to it
+    static PAWN_TABLE: RefCell<PawnTable> = RefCell::new(PawnTable::new());
+}
+
+/// A fixed size cache of pawn structure scores, keyed by the pawn hash of
+/// the board. Pawn structures change rarely during a search, so almost
+/// every lookup hits
+struct PawnTable {
+    entries: Vec<Option<(u64, TaperedScore)>>,
+}
+
+impl PawnTable {
+    #[must_use]
+    fn new() -> Self {
+        Self {
+            entries: vec![None; PAWN_TABLE_SIZE],
+        }
+    }
+    #[must_use]
+    fn get(&self, pawn_hash: u64) -> Option<TaperedScore> {
+        match self.entries[pawn_hash as usize % PAWN_TABLE_SIZE] {
+            Some((key, score)) if key == pawn_hash => Some(score),
+            _ => None,
+        }
+    }
+    fn insert(&mut self, pawn_hash: u64, score: TaperedScore) {
+        self.entries[pawn_hash as usize % PAWN_TABLE_SIZE] = Some((pawn_hash, score));
+    }
+}
+
+impl Board {
+    /// Returns the hash of just the pawns on the board
+    #[must_use]
+    pub const fn pawn_hash(&self) -> u64 {
+        self.pawn_hash
+    }
+    /// The pawn structure score, from white's perspective, looked up in
+    /// the pawn hash table where possible. The table is only valid for the
+    /// installed [`EvalParams`]
+    pub(crate) fn pawn_structure(&self) -> TaperedScore {
+        PAWN_TABLE.with(|table| {
+            if let Some(score) = table.borrow().get(self.pawn_hash) {
+                return score;
+            }
+
+            let params = eval_params();
+            let score = self.pawn_structure_for(params, PlayableTeam::White)
+                - self.pawn_structure_for(params, PlayableTeam::Black);
+
+            table.borrow_mut().insert(self.pawn_hash, score);
+
+            score
+        })
+    }
+    /// The pawn structure score for `team` alone, without the pawn hash table
+    pub(crate) fn pawn_structure_for(
+        &self,
+        params: &EvalParams,
+        team: PlayableTeam,
+    ) -> TaperedScore {
+        let mut score = TaperedScore::new(0, 0);
+
+        score -= params.doubled_pawn_penalty * self.doubled_pawns(team);
+        score -= params.isolated_pawn_penalty * self.isolated_pawns(team);
+        score -= params.backward_pawn_penalty * self.backward_pawns(team);
+        score += params.supported_pawn_bonus * self.supported_pawns(team);
+        score += params.phalanx_pawn_bonus * self.phalanx_pawns(team);
+        score -= params.pawn_island_penalty * self.pawn_islands(team).saturating_sub(1);
+        score += self.passed_pawn_score(params, team);
+
+        score
+    }
+    /// Counts `team`'s pawns on files it already has a pawn on
+    pub(crate) fn doubled_pawns(&self, team: PlayableTeam) -> i16 {
+        let pawns = self.pieces[team as usize][PieceKind::Pawn as usize];
+
+        MASK_FILE
+            .iter()
+            .map(|file| ((pawns & file).count_ones() as i16 - 1).max(0))
+            .sum()
+    }
+    /// Counts `team`'s pawns with no friendly pawns on the files next to them
+    pub(crate) fn isolated_pawns(&self, team: PlayableTeam) -> i16 {
+        let pawns = self.pieces[team as usize][PieceKind::Pawn as usize];
+
+        self.count_pawns(team, |pawn| pawns & neighbouring_files(pawn.x()) == 0)
+    }
+    /// Counts `team`'s backward pawns. They have friendly pawns on the files
+    /// next to them, but all further advanced, and the square in front of
+    /// them is attacked by an enemy pawn
+    pub(crate) fn backward_pawns(&self, team: PlayableTeam) -> i16 {
+        let pawns = self.pieces[team as usize][PieceKind::Pawn as usize];
+        let enemy_pawns = self.pieces[!team as usize][PieceKind::Pawn as usize];
+        let enemy_attacks = magic::all_pawn_attacks(enemy_pawns, !team);
+
+        self.count_pawns(team, |pawn| {
+            let neighbours = pawns & neighbouring_files(pawn.x());
+            let behind_or_level = !ranks_in_front(team, pawn.y());
+
+            let stop = match team {
+                PlayableTeam::White => pawn.to_bitmap() << 8,
+                PlayableTeam::Black => pawn.to_bitmap() >> 8,
+            };
+
+            neighbours != 0 && neighbours & behind_or_level == 0 && stop & enemy_attacks != 0
+        })
+    }
+    /// Counts `team`'s pawns defended by another of its pawns
+    pub(crate) const fn supported_pawns(&self, team: PlayableTeam) -> i16 {
+        let pawns = self.pieces[team as usize][PieceKind::Pawn as usize];
+
+        (magic::all_pawn_attacks(pawns, team) & pawns).count_ones() as i16
+    }
+    /// Counts `team`'s pawns with a friendly pawn directly beside them
+    pub(crate) const fn phalanx_pawns(&self, team: PlayableTeam) -> i16 {
+        let pawns = self.pieces[team as usize][PieceKind::Pawn as usize];
+
+        let beside = ((pawns << 1) & !MASK_FILE[0]) | ((pawns >> 1) & !MASK_FILE[7]);
+
+        (beside & pawns).count_ones() as i16
+    }
+    /// Counts the groups of adjacent files `team` has pawns on
+    pub(crate) fn pawn_islands(&self, team: PlayableTeam) -> i16 {
+        let pawns = self.pieces[team as usize][PieceKind::Pawn as usize];
+
+        let mut islands = 0;
+        let mut on_island = false;
+
+        for file in MASK_FILE {
+            let occupied = pawns & file != 0;
+
+            if occupied && !on_island {
+                islands += 1;
+            }
+            on_island = occupied;
+        }
+
+        islands
+    }
+    /// The bonus for `team`'s passed pawns, growing as they advance, with
+    /// extra for those defended by another pawn
+    pub(crate) fn passed_pawn_score(
+        &self,
+        params: &EvalParams,
+        team: PlayableTeam,
+    ) -> TaperedScore {
+        let pawns = self.pieces[team as usize][PieceKind::Pawn as usize];
+        let supported = magic::all_pawn_attacks(pawns, team);
+
+        let mut passed = self.passed_pawn_bitmap(team);
+        let mut score = TaperedScore::new(0, 0);
+
+        while passed != 0 {
+            let pawn = Position::from_u8(pop_lsb(&mut passed) as u8);
+            let rank = relative_rank(team, pawn) as usize;
+
+            score += params.passed_pawn_bonus[rank];
+
+            if supported & pawn.to_bitmap() != 0 {
+                score += params.supported_passed_pawn_bonus;
+            }
+        }
+
+        score
+    }
+    /// Returns a bitmap of `team`'s passed pawns, which have no enemy pawns
+    /// in front of them on their own or neighbouring files
+    pub(crate) fn passed_pawn_bitmap(&self, team: PlayableTeam) -> u64 {
+        let enemy_pawns = self.pieces[!team as usize][PieceKind::Pawn as usize];
+
+        let mut pawns = self.pieces[team as usize][PieceKind::Pawn as usize];
+        let mut passed = 0;
+
+        while pawns != 0 {
+            let pawn = Position::from_u8(pop_lsb(&mut pawns) as u8);
+
+            if passed_pawn_span(team, pawn) & enemy_pawns == 0 {
+                passed |= pawn.to_bitmap();
+            }
+        }
+
+        passed
+    }
+    fn count_pawns(&self, team: PlayableTeam, predicate: impl Fn(Position) -> bool) -> i16 {
+        let mut pawns = self.pieces[team as usize][PieceKind::Pawn as usize];
+        let mut count = 0;
+
+        while pawns != 0 {
+            if predicate(Position::from_u8(pop_lsb(&mut pawns) as u8)) {
+                count += 1;
+            }
+        }
+
+        count
+    }
+}
+
+/// Returns the rank of `position`, counted from `team`'s side of the board
+pub(crate) const fn relative_rank(team: PlayableTeam, position: Position) -> u8 {
+    match team {
+        PlayableTeam::White => position.y(),
+        PlayableTeam::Black => 7 - position.y(),
+    }
+}
+
+/// Returns every rank in front of `y`, from `team`'s point of view
+pub(crate) fn ranks_in_front(team: PlayableTeam, y: u8) -> u64 {
+    MASK_RANK
+        .iter()
+        .enumerate()
+        .filter(|(rank, _)| match team {
+            PlayableTeam::White => *rank > y as usize,
+            PlayableTeam::Black => (*rank as u8) < y,
+        })
+        .fold(0, |ranks, (_, mask)| ranks | mask)
+}
+
+/// Returns the squares in front of `position` on its file, from `team`'s
+/// point of view
+pub(crate) fn front_span(team: PlayableTeam, position: Position) -> u64 {
+    ranks_in_front(team, position.y()) & MASK_FILE[position.x() as usize]
+}
+
+/// Returns the squares in front of `position` on its own and neighbouring
+/// files, which must be clear of enemy pawns for a pawn to be passed
+pub(crate) fn passed_pawn_span(team: PlayableTeam, position: Position) -> u64 {
+    ranks_in_front(team, position.y())
+        & (MASK_FILE[position.x() as usize] | neighbouring_files(position.x()))
+}
+
+/// Returns the files either side of `x`, but not `x` itself
+pub(crate) const fn neighbouring_files(x: u8) -> u64 {
+    let left = if x > 0 { MASK_FILE[x as usize - 1] } else { 0 };
+    let right = if x < 7 { MASK_FILE[x as usize + 1] } else { 0 };
+
+    left | right
+}
+
+#[test]
+fn doubled_and_isolated_pawns_test() {
+    // White has doubled, isolated a pawns, and a healthy e and f pawn pair
+    let board = Board::from_fen("4k3/8/8/8/P7/P7/4PP2/4K3 w - - 0 1").unwrap();
+
+    assert_eq!(board.doubled_pawns(PlayableTeam::White), 1);
+    assert_eq!(board.isolated_pawns(PlayableTeam::White), 2);
+    assert_eq!(board.pawn_islands(PlayableTeam::White), 2);
+    assert_eq!(board.phalanx_pawns(PlayableTeam::White), 2);
+}
+
+#[test]
+fn backward_pawns_test() {
+    // The d3 pawn can't advance, as the c5 pawn attacks d4, and its
+    // neighbours on c4 and e4 are both ahead of it
+    let board = Board::from_fen("4k3/8/8/2p5/2P1P3/3P4/8/4K3 w - - 0 1").unwrap();
+
+    assert_eq!(board.backward_pawns(PlayableTeam::White), 1);
+    assert_eq!(board.supported_pawns(PlayableTeam::White), 2);
+
+    // Without the enemy pawn it can advance safely
+    let board = Board::from_fen("4k3/8/8/8/2P1P3/3P4/8/4K3 w - - 0 1").unwrap();
+
+    assert_eq!(board.backward_pawns(PlayableTeam::White), 0);
+}
+
+#[test]
+fn passed_pawns_test() {
+    let params = EvalParams::default();
+    // The a and b pawns are passed, with the b pawn defending the a pawn,
+    // while the d and e pawns are both held back by the pawn on e6
+    let board = Board::from_fen("7k/8/4p3/P7/1P1PP3/8/8/7K w - - 0 1").unwrap();
+
+    assert_eq!(
+        board.passed_pawn_bitmap(PlayableTeam::White),
+        Position::from_uci("a5").unwrap().to_bitmap()
+            | Position::from_uci("b4").unwrap().to_bitmap()
+    );
+    assert_eq!(
+        board.passed_pawn_score(&params, PlayableTeam::White),
+        params.passed_pawn_bonus[4]
+            + params.passed_pawn_bonus[3]
+            + params.supported_passed_pawn_bonus
+    );
+    // Black's e pawn is held back by the white d and e pawns
+    assert_eq!(board.passed_pawn_bitmap(PlayableTeam::Black), 0);
+}
+
+#[test]
+fn pawn_table_test() {
+    let board = Board::from_fen("4k3/pp3ppp/8/8/8/P7/P3PP2/4K3 w - - 0 1").unwrap();
+
+    let params = EvalParams::default();
+    let uncached = board.pawn_structure_for(&params, PlayableTeam::White)
+        - board.pawn_structure_for(&params, PlayableTeam::Black);
+
+    assert_eq!(board.pawn_structure(), uncached);
+    // The second lookup comes from the table
+    assert_eq!(board.pawn_structure(), uncached);
+
+    // Moving a piece other than a pawn doesn't change the pawn hash
+    let moved = board
+        .make_move(&board.parse_uci_move("e1d1").unwrap())
+        .unwrap();
+
+    assert_eq!(moved.pawn_hash(), board.pawn_hash());
+    assert_ne!(
+        Board::from_fen("4k3/pp3ppp/8/8/8/P7/P4P2/4K3 w - - 0 1")
+            .unwrap()
+            .pawn_hash(),
+        board.pawn_hash()
+    );
+}
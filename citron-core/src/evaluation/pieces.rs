@@ -0,0 +1,287 @@
+use super::{
+    pawns::{neighbouring_files, ranks_in_front, relative_rank},
+    EvalParams, TaperedScore,
+};
+use crate::{
+    magic::{self, pop_lsb, MASK_FILE, MASK_RANK},
+    piece::PieceKind,
+    Board, PlayableTeam, Position,
+};
+
+/// Every light square on the board
+const LIGHT_SQUARES: u64 = 0x55aa_55aa_55aa_55aa;
+/// The starting squares of white's knights and bishops
+const MINOR_PIECE_SQUARES: u64 = 0x66;
+/// The starting square of white's queen
+const QUEEN_SQUARE: u64 = 0x8;
+
+impl Board {
+    /// The score for where the pieces are placed, beyond their
+    /// piece-square values, from white's perspective
+    pub(crate) fn piece_evaluation(&self, params: &EvalParams) -> TaperedScore {
+        self.piece_evaluation_for(params, PlayableTeam::White)
+            - self.piece_evaluation_for(params, PlayableTeam::Black)
+    }
+    fn piece_evaluation_for(&self, params: &EvalParams, team: PlayableTeam) -> TaperedScore {
+        self.bishop_pair(params, team)
+            + self.rook_files(params, team)
+            + self.rooks_on_seventh(params, team)
+            + self.outposts(params, team)
+            + self.bad_bishops(params, team)
+            + self.early_queen(params, team)
+    }
+    /// The bonus for `team` having two or more bishops
+    pub(crate) fn bishop_pair(&self, params: &EvalParams, team: PlayableTeam) -> TaperedScore {
+        if self.pieces[team as usize][PieceKind::Bishop as usize].count_ones() >= 2 {
+            params.bishop_pair_bonus
+        } else {
+            TaperedScore::new(0, 0)
+        }
+    }
+    /// The bonus for `team`'s rooks on open and semi-open files
+    pub(crate) fn rook_files(&self, params: &EvalParams, team: PlayableTeam) -> TaperedScore {
+        let pawns = self.pieces[team as usize][PieceKind::Pawn as usize];
+        let enemy_pawns = self.pieces[!team as usize][PieceKind::Pawn as usize];
+
+        let mut rooks = self.pieces[team as usize][PieceKind::Rook as usize];
+        let mut score = TaperedScore::new(0, 0);
+
+        while rooks != 0 {
+            let file = MASK_FILE[(pop_lsb(&mut rooks) % 8) as usize];
+
+            if file & pawns == 0 {
+                if file & enemy_pawns == 0 {
+                    score += params.rook_open_file_bonus;
+                } else {
+                    score += params.rook_semi_open_file_bonus;
+                }
+            }
+        }
+
+        score
+    }
+    /// The bonus for `team`'s rooks on the 7th rank, when the enemy king is
+    /// stuck on the 8th, or there are enemy pawns to attack
+    pub(crate) fn rooks_on_seventh(&self, params: &EvalParams, team: PlayableTeam) -> TaperedScore {
+        let (seventh, eighth) = match team {
+            PlayableTeam::White => (MASK_RANK[6], MASK_RANK[7]),
+            PlayableTeam::Black => (MASK_RANK[1], MASK_RANK[0]),
+        };
+
+        let enemy_king = self.pieces[!team as usize][PieceKind::King as usize];
+        let enemy_pawns = self.pieces[!team as usize][PieceKind::Pawn as usize];
+
+        if enemy_king & eighth == 0 && enemy_pawns & seventh == 0 {
+            return TaperedScore::new(0, 0);
+        }
+
+        let rooks = self.pieces[team as usize][PieceKind::Rook as usize] & seventh;
+
+        params.rook_on_seventh_bonus * rooks.count_ones() as i16
+    }
+    /// The bonus for `team`'s knights and bishops on outposts. These are
+    /// squares in the enemy half of the board, defended by a pawn, that no
+    /// enemy pawn can ever attack
+    pub(crate) fn outposts(&self, params: &EvalParams, team: PlayableTeam) -> TaperedScore {
+        let pawns = self.pieces[team as usize][PieceKind::Pawn as usize];
+        let enemy_pawns = self.pieces[!team as usize][PieceKind::Pawn as usize];
+        let defended = magic::all_pawn_attacks(pawns, team);
+
+        let mut score = TaperedScore::new(0, 0);
+
+        for kind in [PieceKind::Knight, PieceKind::Bishop] {
+            let mut pieces = self.pieces[team as usize][kind as usize] & defended;
+
+            while pieces != 0 {
+                let position = Position::from_u8(pop_lsb(&mut pieces) as u8);
+
+                let attackers = ranks_in_front(team, position.y())
+                    & neighbouring_files(position.x())
+                    & enemy_pawns;
+
+                if (3..=5).contains(&relative_rank(team, position)) && attackers == 0 {
+                    score += params.outpost_bonus[kind as usize];
+                }
+            }
+        }
+
+        score
+    }
+    /// The penalty for `team`'s bishops hemmed in by their own pawns on
+    /// the same square colour
+    pub(crate) fn bad_bishops(&self, params: &EvalParams, team: PlayableTeam) -> TaperedScore {
+        let pawns = self.pieces[team as usize][PieceKind::Pawn as usize];
+        let blocked = match team {
+            PlayableTeam::White => pawns & (self.get_occupied() >> 8),
+            PlayableTeam::Black => pawns & (self.get_occupied() << 8),
+        };
+
+        let mut bishops = self.pieces[team as usize][PieceKind::Bishop as usize];
+        let mut score = TaperedScore::new(0, 0);
+
+        while bishops != 0 {
+            let bishop = pop_lsb(&mut bishops);
+
+            let colour = if LIGHT_SQUARES & (1 << bishop) == 0 {
+                !LIGHT_SQUARES
+            } else {
+                LIGHT_SQUARES
+            };
+
+            let count = (pawns & colour).count_ones() + (blocked & colour).count_ones();
+
+            score -= params.bad_bishop_penalty * count as i16;
+        }
+
+        score
+    }
+    /// The penalty for `team` moving its queen before developing its
+    /// knights and bishops
+    pub(crate) fn early_queen(&self, params: &EvalParams, team: PlayableTeam) -> TaperedScore {
+        let (minor_squares, queen_square) = match team {
+            PlayableTeam::White => (MINOR_PIECE_SQUARES, QUEEN_SQUARE),
+            PlayableTeam::Black => (MINOR_PIECE_SQUARES << 56, QUEEN_SQUARE << 56),
+        };
+
+        let queens = self.pieces[team as usize][PieceKind::Queen as usize];
+
+        if queens == 0 || queens & queen_square != 0 {
+            return TaperedScore::new(0, 0);
+        }
+
+        let undeveloped = (self.pieces[team as usize][PieceKind::Knight as usize]
+            | self.pieces[team as usize][PieceKind::Bishop as usize])
+            & minor_squares;
+
+        -(params.early_queen_penalty * undeveloped.count_ones() as i16)
+    }
+}
+
+#[test]
+fn bishop_pair_test() {
+    let params = EvalParams::default();
+    let board = Board::new();
+
+    assert_eq!(
+        board.bishop_pair(&params, PlayableTeam::White),
+        params.bishop_pair_bonus
+    );
+
+    let board = Board::from_fen("rn1qkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w - - 0 1").unwrap();
+
+    assert_eq!(
+        board.bishop_pair(&params, PlayableTeam::Black),
+        TaperedScore::new(0, 0)
+    );
+}
+
+#[test]
+fn rook_files_test() {
+    let params = EvalParams::default();
+    // The a rook is on an open file, the d rook on a semi-open file, and
+    // the h rook is behind its own pawn
+    let board = Board::from_fen("4k3/3p4/8/8/8/8/7P/R2RK2R w - - 0 1").unwrap();
+
+    assert_eq!(
+        board.rook_files(&params, PlayableTeam::White),
+        params.rook_open_file_bonus + params.rook_semi_open_file_bonus
+    );
+}
+
+#[test]
+fn rooks_on_seventh_test() {
+    let params = EvalParams::default();
+    let board = Board::from_fen("6k1/R7/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+
+    assert_eq!(
+        board.rooks_on_seventh(&params, PlayableTeam::White),
+        params.rook_on_seventh_bonus
+    );
+
+    // With the king out and no pawns to attack, the rook does nothing there
+    let board = Board::from_fen("8/R7/6k1/8/8/8/8/4K3 w - - 0 1").unwrap();
+
+    assert_eq!(
+        board.rooks_on_seventh(&params, PlayableTeam::White),
+        TaperedScore::new(0, 0)
+    );
+
+    let board = Board::from_fen("8/1p6/6k1/8/8/8/r7/4K3 w - - 0 1").unwrap();
+
+    assert_eq!(
+        board.rooks_on_seventh(&params, PlayableTeam::Black),
+        params.rook_on_seventh_bonus
+    );
+}
+
+#[test]
+fn outposts_test() {
+    let params = EvalParams::default();
+    // The knight on d5 is defended by the e4 pawn, and black has no c or e
+    // pawns left to chase it away
+    let board = Board::from_fen("4k3/pp3p1p/8/3N4/4P3/8/8/4K3 w - - 0 1").unwrap();
+
+    assert_eq!(
+        board.outposts(&params, PlayableTeam::White),
+        params.outpost_bonus[PieceKind::Knight as usize]
+    );
+
+    // A black pawn on c7 could still kick it
+    let board = Board::from_fen("4k3/ppp2p1p/8/3N4/4P3/8/8/4K3 w - - 0 1").unwrap();
+
+    assert_eq!(
+        board.outposts(&params, PlayableTeam::White),
+        TaperedScore::new(0, 0)
+    );
+}
+
+#[test]
+fn bad_bishops_test() {
+    let params = EvalParams::default();
+    // The c1 bishop is on a dark square, as are the d2 and e3 pawns
+    let board = Board::from_fen("4k3/8/8/8/8/4P3/3P4/2B1K3 w - - 0 1").unwrap();
+
+    assert_eq!(
+        board.bad_bishops(&params, PlayableTeam::White),
+        -(params.bad_bishop_penalty * 2)
+    );
+
+    // Once the e3 pawn is blocked it counts twice
+    let board = Board::from_fen("4k3/8/8/8/4p3/4P3/3P4/2B1K3 w - - 0 1").unwrap();
+
+    assert_eq!(
+        board.bad_bishops(&params, PlayableTeam::White),
+        -(params.bad_bishop_penalty * 3)
+    );
+
+    // The light squared bishop isn't hindered by them
+    let board = Board::from_fen("4k3/8/8/8/4p3/4P3/3P4/3BK3 w - - 0 1").unwrap();
+
+    assert_eq!(
+        board.bad_bishops(&params, PlayableTeam::White),
+        TaperedScore::new(0, 0)
+    );
+}
+
+#[test]
+fn early_queen_test() {
+    let params = EvalParams::default();
+    let board = Board::new();
+
+    assert_eq!(
+        board.early_queen(&params, PlayableTeam::White),
+        TaperedScore::new(0, 0)
+    );
+
+    let board =
+        Board::from_fen("rnbqkbnr/pppp1ppp/8/4p3/4P2Q/8/PPPP1PPP/RNB1KBNR w - - 0 1").unwrap();
+
+    assert_eq!(
+        board.early_queen(&params, PlayableTeam::White),
+        -(params.early_queen_penalty * 4)
+    );
+    assert_eq!(
+        board.early_queen(&params, PlayableTeam::Black),
+        TaperedScore::new(0, 0)
+    );
+}
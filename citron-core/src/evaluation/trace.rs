@@ -0,0 +1,316 @@
+use core::fmt::{self, Display};
+
+use super::{eval_params, scoped_params, TaperedScore, SCALE_NORMAL};
+use crate::{
+    magic::pop_lsb,
+    piece::{Piece, PieceKind},
+    Board, PlayableTeam, Position,
+};
+
+/// A single term of the evaluation, scored from each side's own perspective
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EvalTerm {
+    pub name: &'static str,
+    pub white: TaperedScore,
+    pub black: TaperedScore,
+}
+
+impl EvalTerm {
+    /// Creates a term, scoring each side with `score`
+    fn new(name: &'static str, score: impl Fn(PlayableTeam) -> TaperedScore) -> Self {
+        Self {
+            name,
+            white: score(PlayableTeam::White),
+            black: score(PlayableTeam::Black),
+        }
+    }
+    /// Creates a term that only applies in the middle game
+    fn middle_game(name: &'static str, score: impl Fn(PlayableTeam) -> i16) -> Self {
+        Self::new(name, |team| TaperedScore::new(score(team), 0))
+    }
+    /// Creates a term that only applies in the end game
+    fn end_game(name: &'static str, score: impl Fn(PlayableTeam) -> i16) -> Self {
+        Self::new(name, |team| TaperedScore::new(0, score(team)))
+    }
+    /// The term from white's perspective
+    #[must_use]
+    pub fn total(&self) -> TaperedScore {
+        self.white - self.black
+    }
+}
+
+/// A breakdown of [`Board::static_evaluation`] into each of its terms
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EvalTrace {
+    /// The game phase the positional terms are tapered by
+    pub phase: i16,
+    /// The material each side has, indexed by [`PlayableTeam`]
+    pub material: [i16; 2],
+    /// The bonus for trading down when ahead, from white's perspective
+    pub trade_bonus: i16,
+    /// Every positional term, which are summed before being tapered
+    pub terms: Vec<EvalTerm>,
+    /// The network's evaluation, which replaces the hand written one when
+    /// a network is in use
+    pub network: Option<i16>,
+    /// The scale factor, out of [`SCALE_NORMAL`], for the side the
+    /// evaluation favours, which shrinks it in drawish endings
+    pub scale_factor: i16,
+}
+
+impl EvalTrace {
+    /// The sum of every positional term, from white's perspective
+    #[must_use]
+    pub fn positional(&self) -> TaperedScore {
+        self.terms
+            .iter()
+            .fold(TaperedScore::new(0, 0), |score, term| score + term.total())
+    }
+    /// The evaluation from white's perspective before it's scaled
+    #[must_use]
+    pub fn unscaled(&self) -> i16 {
+        if let Some(network) = self.network {
+            return network;
+        }
+
+        self.material[PlayableTeam::White as usize] - self.material[PlayableTeam::Black as usize]
+            + self.trade_bonus
+            + self.positional().taper(self.phase)
+    }
+    /// The evaluation from white's perspective, which is always equal to
+    /// [`Board::static_evaluation`]
+    #[must_use]
+    pub fn total(&self) -> i16 {
+        (i32::from(self.unscaled()) * i32::from(self.scale_factor) / i32::from(SCALE_NORMAL)) as i16
+    }
+}
+
+impl Display for EvalTrace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{:<20} | {:>13} | {:>13} | {:>13} | {:>7}",
+            "Term", "White", "Black", "Total", "Tapered"
+        )?;
+        writeln!(
+            f,
+            "{:<20} | {:>6} {:>6} | {:>6} {:>6} | {:>6} {:>6} |",
+            "", "MG", "EG", "MG", "EG", "MG", "EG"
+        )?;
+        writeln!(
+            f,
+            "{:-<20}-+-{:-<13}-+-{:-<13}-+-{:-<13}-+-{:-<7}",
+            "", "", "", "", ""
+        )?;
+
+        let [white, black] = self.material;
+        writeln!(
+            f,
+            "{:<20} | {:>6} {:>6} | {:>6} {:>6} | {:>6} {:>6} | {:>7}",
+            "Material",
+            white,
+            white,
+            black,
+            black,
+            white - black,
+            white - black,
+            white - black
+        )?;
+        writeln!(
+            f,
+            "{:<20} | {:>13} | {:>13} | {:>6} {:>6} | {:>7}",
+            "Trade bonus", "", "", self.trade_bonus, self.trade_bonus, self.trade_bonus
+        )?;
+
+        for term in &self.terms {
+            let total = term.total();
+
+            writeln!(
+                f,
+                "{:<20} | {:>6} {:>6} | {:>6} {:>6} | {:>6} {:>6} | {:>7}",
+                term.name,
+                term.white.middle_game,
+                term.white.end_game,
+                term.black.middle_game,
+                term.black.end_game,
+                total.middle_game,
+                total.end_game,
+                total.taper(self.phase)
+            )?;
+        }
+
+        let positional = self.positional();
+
+        writeln!(
+            f,
+            "{:-<20}-+-{:-<13}-+-{:-<13}-+-{:-<13}-+-{:-<7}",
+            "", "", "", "", ""
+        )?;
+        writeln!(
+            f,
+            "{:<20} | {:>13} | {:>13} | {:>6} {:>6} | {:>7}",
+            "Positional",
+            "",
+            "",
+            positional.middle_game,
+            positional.end_game,
+            positional.taper(self.phase)
+        )?;
+        writeln!(f)?;
+        writeln!(f, "Phase: {}/{}", self.phase, super::MAXIMUM_PHASE)?;
+
+        if self.network.is_some() {
+            writeln!(f, "The network's evaluation is used in place of the above")?;
+        }
+
+        if self.scale_factor != SCALE_NORMAL {
+            writeln!(
+                f,
+                "Scaled by {}/{} for a drawish ending",
+                self.scale_factor, SCALE_NORMAL
+            )?;
+        }
+
+        write!(
+            f,
+            "Evaluation: {:+.2} (white's perspective)",
+            f64::from(self.total()) / 100.
+        )
+    }
+}
+
+impl Board {
+    /// Itemises every term of [`Board::static_evaluation`], for each side
+    #[must_use]
+    pub fn evaluation_trace(&self) -> EvalTrace {
+        let params = scoped_params();
+        // Like the piece-square score, the pawn structure score always comes
+        // from the installed parameters, as it's cached by pawn structure
+        let installed = eval_params();
+        let material = [
+            self.material_for(params, PlayableTeam::White),
+            self.material_for(params, PlayableTeam::Black),
+        ];
+
+        let terms = vec![
+            EvalTerm::new("Pawn squares", |team| {
+                self.piece_square_total(team, PieceKind::Pawn)
+            }),
+            EvalTerm::new("Knight squares", |team| {
+                self.piece_square_total(team, PieceKind::Knight)
+            }),
+            EvalTerm::new("Bishop squares", |team| {
+                self.piece_square_total(team, PieceKind::Bishop)
+            }),
+            EvalTerm::new("Rook squares", |team| {
+                self.piece_square_total(team, PieceKind::Rook)
+            }),
+            EvalTerm::new("Queen squares", |team| {
+                self.piece_square_total(team, PieceKind::Queen)
+            }),
+            EvalTerm::new("King squares", |team| {
+                self.piece_square_total(team, PieceKind::King)
+            }),
+            EvalTerm::new("Pawn structure", |team| {
+                self.pawn_structure_for(installed, team)
+            }),
+            EvalTerm::new("Bishop pair", |team| self.bishop_pair(params, team)),
+            EvalTerm::new("Rook files", |team| self.rook_files(params, team)),
+            EvalTerm::new("Rooks on 7th", |team| self.rooks_on_seventh(params, team)),
+            EvalTerm::new("Outposts", |team| self.outposts(params, team)),
+            EvalTerm::new("Bad bishops", |team| self.bad_bishops(params, team)),
+            EvalTerm::new("Early queen", |team| self.early_queen(params, team)),
+            EvalTerm::middle_game("Mobility", |team| self.mobility(params, team)),
+            EvalTerm::middle_game("King safety", |team| self.king_safety(params, team)),
+            EvalTerm::end_game("King activity", |team| self.king_activity(params, team)),
+            EvalTerm::end_game("Free passed pawns", |team| {
+                self.free_passed_pawns(params, team)
+            }),
+            EvalTerm::end_game("Unstoppable pawns", |team| {
+                self.unstoppable_pawns(params, team)
+            }),
+            EvalTerm::end_game("Mop up", |team| self.mop_up(params, team)),
+        ];
+
+        let mut trace = EvalTrace {
+            phase: self.phase,
+            material,
+            trade_bonus: self.trade_bonus(params),
+            terms,
+            #[cfg(feature = "nn_evaluation")]
+            network: self.nnue_evaluation(),
+            #[cfg(not(feature = "nn_evaluation"))]
+            network: None,
+            scale_factor: SCALE_NORMAL,
+        };
+
+        let strong = if trace.unscaled() > 0 {
+            PlayableTeam::White
+        } else {
+            PlayableTeam::Black
+        };
+        trace.scale_factor = self.scale_factor(params, strong);
+
+        trace
+    }
+    /// The piece-square score of `team`'s pieces of `kind`, from its own
+    /// perspective
+    fn piece_square_total(&self, team: PlayableTeam, kind: PieceKind) -> TaperedScore {
+        let piece = Piece::new(team.into(), kind);
+        let mut pieces = self.pieces[team as usize][kind as usize];
+        let mut score = TaperedScore::new(0, 0);
+
+        while pieces != 0 {
+            score += piece.positional_value(Position::from_u8(pop_lsb(&mut pieces) as u8));
+        }
+
+        match team {
+            PlayableTeam::White => score,
+            PlayableTeam::Black => -score,
+        }
+    }
+}
+
+#[test]
+fn evaluation_trace_test() {
+    for fen in [
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w - - 0 1",
+        "r2q1rk1/1p3p1p/1b4p1/pPp2b2/3pn1P1/P2Q4/B1P1NP1P/R1B2RK1 b - - 0 30",
+        "4k3/pp3p1p/8/3N4/4P3/8/8/4K3 w - - 0 1",
+        "3k4/8/8/8/8/8/8/KQ6 w - - 0 1",
+        "7k/8/8/P7/8/8/8/7K w - - 0 1",
+        "rnbqkbnr/pppp1ppp/8/4p3/4P2Q/8/PPPP1PPP/RNB1KBNR b - - 0 1",
+    ] {
+        let board = Board::from_fen(fen).unwrap();
+        let trace = board.evaluation_trace();
+
+        assert_eq!(trace.total(), board.static_evaluation(), "{}", fen);
+        assert_eq!(
+            trace.material[0] - trace.material[1],
+            board.material,
+            "{}",
+            fen
+        );
+    }
+
+    // Within a scope the trace uses the same parameters as the search. The
+    // pawn structure still comes from the installed ones
+    let mut params = eval_params().clone();
+    params.piece_values[PieceKind::Knight as usize] += 50;
+    params.bishop_pair_bonus = TaperedScore::new(60, 90);
+    params.doubled_pawn_penalty = TaperedScore::new(40, 60);
+    let params: &'static super::EvalParams = Box::leak(Box::new(params));
+
+    let board = Board::from_fen("r1b1kb1r/pp3ppp/8/8/8/2N2P2/PP3P1P/R1B1K2R w - - 0 1").unwrap();
+    let scoped = params.scope(|| board.evaluation_trace());
+
+    assert_eq!(scoped.total(), params.scope(|| board.static_evaluation()));
+    assert_ne!(scoped.total(), board.evaluation_trace().total());
+
+    // The initial position is symmetrical, so every term cancels out
+    let trace = Board::new().evaluation_trace();
+
+    for term in &trace.terms {
+        assert_eq!(term.white, term.black, "{}", term.name);
+    }
+}
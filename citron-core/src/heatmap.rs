@@ -1,137 +1,249 @@
 use crate::{
-    piece::{Piece, PieceKind},
+    evaluation::{eval_params, EvalParams, TaperedScore},
+    piece::Piece,
     Position, Team,
 };
 
 impl Piece {
+    /// Returns the piece-square score of the piece on `position`. Scores
+    /// are positive for white pieces, and negative for black pieces
     #[must_use]
-    pub fn positional_value(&self, position: Position) -> i16 {
-        let index = position.index() as usize;
-
-        match (self.kind(), self.team()) {
-            (PieceKind::Pawn, Team::White) => WHITE_PAWN_HEATMAP[index],
-            (PieceKind::Pawn, Team::Black) => BLACK_PAWN_HEATMAP[index],
-            (PieceKind::Rook, Team::White) => WHITE_ROOK_HEATMAP[index],
-            (PieceKind::Rook, Team::Black) => BLACK_ROOK_HEATMAP[index],
-            (PieceKind::Knight, Team::White) => WHITE_KNIGHT_HEATMAP[index],
-            (PieceKind::Knight, Team::Black) => BLACK_KNIGHT_HEATMAP[index],
-            _ => 0,
+    pub fn positional_value(&self, position: Position) -> TaperedScore {
+        self.positional_value_with(eval_params(), position)
+    }
+    /// Returns the piece-square score of the piece on `position`, using the
+    /// tables in `params`
+    #[must_use]
+    pub const fn positional_value_with(
+        &self,
+        params: &EvalParams,
+        position: Position,
+    ) -> TaperedScore {
+        let kind = self.kind() as usize;
+
+        match self.team() {
+            Team::White => {
+                let index = (position.index() ^ 56) as usize;
+
+                TaperedScore::new(
+                    params.middle_game_tables[kind][index],
+                    params.end_game_tables[kind][index],
+                )
+            }
+            Team::Black => {
+                let index = position.index() as usize;
+
+                TaperedScore::new(
+                    -params.middle_game_tables[kind][index],
+                    -params.end_game_tables[kind][index],
+                )
+            }
+            Team::Neither => TaperedScore::new(0, 0),
         }
     }
 }
 
-pub const HEATMAPS: [[&[(u64, i16)]; 2]; 6] = [
-    [WHITE_PAWN_HEATMAPS, BLACK_PAWN_HEATMAPS],
-    [&[], &[]],
-    [&[], &[]],
-    [&[], &[]],
-    [&[], &[]],
-    [&[], &[]],
+/// The default middle game piece-square tables, indexed by
+/// [`PieceKind`](crate::piece::PieceKind). Each
+/// table is written from white's side of the board, with the 8th rank
+/// first, and is mirrored vertically for black's pieces
+pub const MIDDLE_GAME_TABLES: [[i16; 64]; 6] = [
+    PAWN_MIDDLE_GAME,
+    ROOK_MIDDLE_GAME,
+    KNIGHT_MIDDLE_GAME,
+    BISHOP_MIDDLE_GAME,
+    QUEEN_MIDDLE_GAME,
+    KING_MIDDLE_GAME,
+];
+
+/// The default end game piece-square tables, laid out as [`MIDDLE_GAME_TABLES`]
+pub const END_GAME_TABLES: [[i16; 64]; 6] = [
+    PAWN_END_GAME,
+    ROOK_END_GAME,
+    KNIGHT_END_GAME,
+    BISHOP_END_GAME,
+    QUEEN_END_GAME,
+    KING_END_GAME,
+];
+
+#[rustfmt::skip]
+const PAWN_MIDDLE_GAME: [i16; 64] = [
+      0,   0,   0,   0,   0,   0,   0,   0, // 8th rank
+     50,  50,  50,  50,  50,  50,  50,  50, // 7th rank
+     10,  10,  20,  30,  30,  20,  10,  10, // 6th rank
+      5,   5,  10,  25,  25,  10,   5,   5, // 5th rank
+      0,   0,   0,  20,  20,   0,   0,   0, // 4th rank
+      5,  -5, -10,   0,   0, -10,  -5,   5, // 3rd rank
+      5,  10,  10, -20, -20,  10,  10,   5, // 2nd rank
+      0,   0,   0,   0,   0,   0,   0,   0, // 1st rank
 ];
 
-pub const WHITE_PAWN_HEATMAPS: &[(u64, i16)] = &[
-    (
-        0b00000000_00000000_00000000_00011000_00011000_00000000_00000000_00000000,
-        20,
-    ),
-    (
-        0b00000100_00000100_00000100_00000100_00000100_00000100_00000100_00000100,
-        30,
-    ),
+#[rustfmt::skip]
+const PAWN_END_GAME: [i16; 64] = [
+      0,   0,   0,   0,   0,   0,   0,   0, // 8th rank
+     80,  80,  80,  80,  80,  80,  80,  80, // 7th rank
+     50,  50,  50,  50,  50,  50,  50,  50, // 6th rank
+     30,  30,  30,  30,  30,  30,  30,  30, // 5th rank
+     20,  20,  20,  20,  20,  20,  20,  20, // 4th rank
+     10,  10,  10,  10,  10,  10,  10,  10, // 3rd rank
+      0,   0,   0,   0,   0,   0,   0,   0, // 2nd rank
+      0,   0,   0,   0,   0,   0,   0,   0, // 1st rank
 ];
 
-pub const BLACK_PAWN_HEATMAPS: &[(u64, i16)] = &[
-    (
-        0b00000000_00000000_00000000_00001100_00001100_00000000_00000000_00000000,
-        -20,
-    ),
-    (
-        0b00100000_00100000_00100000_00100000_00100000_00100000_00100000_00100000,
-        -30,
-    ),
+#[rustfmt::skip]
+const ROOK_MIDDLE_GAME: [i16; 64] = [
+      0,   0,   0,   0,   0,   0,   0,   0, // 8th rank
+      5,  10,  10,  10,  10,  10,  10,   5, // 7th rank
+     -5,   0,   0,   0,   0,   0,   0,  -5, // 6th rank
+     -5,   0,   0,   0,   0,   0,   0,  -5, // 5th rank
+     -5,   0,   0,   0,   0,   0,   0,  -5, // 4th rank
+     -5,   0,   0,   0,   0,   0,   0,  -5, // 3rd rank
+     -5,   0,   0,   0,   0,   0,   0,  -5, // 2nd rank
+      0,   0,   0,   5,   5,   0,   0,   0, // 1st rank
 ];
 
-pub const WHITE_PAWN_HEATMAP: [i16; 64] = [
-    0, 0, 0, 0, 0, 0, 30, 40, // a file
-    0, 0, 0, 0, 0, 0, 30, 40, // b file
-    0, 0, 0, 0, 0, 0, 30, 40, // c file
-    0, 0, 0, 20, 20, 30, 30, 40, // d file
-    0, 0, 0, 20, 20, 30, 30, 40, // e file
-    0, 0, 0, 0, 0, 0, 30, 40, // f file
-    0, 0, 0, 0, 0, 0, 30, 40, // g file
-    0, 0, 0, 0, 0, 0, 30, 40, // h file
+#[rustfmt::skip]
+const ROOK_END_GAME: [i16; 64] = [
+      5,   5,   5,   5,   5,   5,   5,   5, // 8th rank
+     10,  10,  10,  10,  10,  10,  10,  10, // 7th rank
+      0,   0,   0,   0,   0,   0,   0,   0, // 6th rank
+      0,   0,   0,   0,   0,   0,   0,   0, // 5th rank
+      0,   0,   0,   0,   0,   0,   0,   0, // 4th rank
+      0,   0,   0,   0,   0,   0,   0,   0, // 3rd rank
+      0,   0,   0,   0,   0,   0,   0,   0, // 2nd rank
+      0,   0,   0,   0,   0,   0,   0,   0, // 1st rank
 ];
 
-pub const BLACK_PAWN_HEATMAP: [i16; 64] = [
-    -40, -30, 0, 0, 0, 0, 0, 0, // a file
-    -40, -30, 0, 0, 0, 0, 0, 0, // b file
-    -40, -30, 0, 0, 0, 0, 0, 0, // c file
-    -40, -30, -30, -20, -20, 0, 0, 0, // d file
-    -40, -30, -30, -20, -20, 0, 0, 0, // e file
-    -40, -30, 0, 0, 0, 0, 0, 0, // f file
-    -40, -30, 0, 0, 0, 0, 0, 0, // g file
-    -40, -30, 0, 0, 0, 0, 0, 0, // h file
+#[rustfmt::skip]
+const KNIGHT_MIDDLE_GAME: [i16; 64] = [
+    -50, -40, -30, -30, -30, -30, -40, -50, // 8th rank
+    -40, -20,   0,   0,   0,   0, -20, -40, // 7th rank
+    -30,   0,  10,  15,  15,  10,   0, -30, // 6th rank
+    -30,   5,  15,  20,  20,  15,   5, -30, // 5th rank
+    -30,   0,  15,  20,  20,  15,   0, -30, // 4th rank
+    -30,   5,  10,  15,  15,  10,   5, -30, // 3rd rank
+    -40, -20,   0,   5,   5,   0, -20, -40, // 2nd rank
+    -50, -40, -30, -30, -30, -30, -40, -50, // 1st rank
 ];
 
-pub const WHITE_ROOK_HEATMAP: [i16; 64] = [
-    10, 0, 0, 0, 0, 0, 0, 0, // a file
-    0, 0, 0, 0, 0, 0, 0, 0, // b file
-    0, 0, 0, 0, 0, 0, 0, 0, // c file
-    35, 0, 0, 0, 0, 0, 0, 0, // d file
-    0, 0, 0, 0, 0, 0, 0, 0, // e file
-    35, 0, 0, 0, 0, 0, 0, 0, // f file
-    0, 0, 0, 0, 0, 0, 0, 0, // g file
-    10, 0, 0, 0, 0, 0, 0, 0, // h file
+#[rustfmt::skip]
+const KNIGHT_END_GAME: [i16; 64] = [
+    -40, -30, -20, -20, -20, -20, -30, -40, // 8th rank
+    -30, -15,   0,   0,   0,   0, -15, -30, // 7th rank
+    -20,   0,  10,  15,  15,  10,   0, -20, // 6th rank
+    -20,   5,  15,  20,  20,  15,   5, -20, // 5th rank
+    -20,   0,  15,  20,  20,  15,   0, -20, // 4th rank
+    -20,   5,  10,  15,  15,  10,   5, -20, // 3rd rank
+    -30, -15,   0,   5,   5,   0, -15, -30, // 2nd rank
+    -40, -30, -20, -20, -20, -20, -30, -40, // 1st rank
 ];
 
-pub const BLACK_ROOK_HEATMAP: [i16; 64] = [
-    0, 0, 0, 0, 0, 0, 0, -10, // a file
-    0, 0, 0, 0, 0, 0, 0, 0, // b file
-    0, 0, 0, 0, 0, 0, 0, 0, // c file
-    0, 0, 0, 0, 0, 0, 0, -35, // d file
-    0, 0, 0, 0, 0, 0, 0, 0, // e file
-    0, 0, 0, 0, 0, 0, 0, -35, // f file
-    0, 0, 0, 0, 0, 0, 0, 0, // g file
-    0, 0, 0, 0, 0, 0, 0, -10, // h file
+#[rustfmt::skip]
+const BISHOP_MIDDLE_GAME: [i16; 64] = [
+    -20, -10, -10, -10, -10, -10, -10, -20, // 8th rank
+    -10,   0,   0,   0,   0,   0,   0, -10, // 7th rank
+    -10,   0,   5,  10,  10,   5,   0, -10, // 6th rank
+    -10,   5,   5,  10,  10,   5,   5, -10, // 5th rank
+    -10,   0,  10,  10,  10,  10,   0, -10, // 4th rank
+    -10,  10,  10,  10,  10,  10,  10, -10, // 3rd rank
+    -10,   5,   0,   0,   0,   0,   5, -10, // 2nd rank
+    -20, -10, -10, -10, -10, -10, -10, -20, // 1st rank
 ];
 
-pub const WHITE_KNIGHT_HEATMAP: [i16; 64] = [
-    -30, -20, -20, -20, -20, -20, -20, -30, // a file
-    -10, 0, 0, 0, 0, 0, 0, 0, // b file
-    0, 0, 0, 0, 0, 0, 0, 0, // c file
-    0, -10, 0, 0, 0, 0, 0, 0, // d file
-    0, -10, 0, 0, 0, 0, 0, 0, // e file
-    0, 0, 0, 0, 0, 0, 0, 0, // f file
-    -10, 0, 0, 0, 0, 0, 0, 0, // g file
-    -30, -20, -20, -20, -20, -20, -20, -30, // h file
+#[rustfmt::skip]
+const BISHOP_END_GAME: [i16; 64] = [
+    -15, -10, -10, -10, -10, -10, -10, -15, // 8th rank
+    -10,   0,   0,   0,   0,   0,   0, -10, // 7th rank
+    -10,   0,   5,   5,   5,   5,   0, -10, // 6th rank
+    -10,   0,   5,  10,  10,   5,   0, -10, // 5th rank
+    -10,   0,   5,  10,  10,   5,   0, -10, // 4th rank
+    -10,   0,   5,   5,   5,   5,   0, -10, // 3rd rank
+    -10,   0,   0,   0,   0,   0,   0, -10, // 2nd rank
+    -15, -10, -10, -10, -10, -10, -10, -15, // 1st rank
 ];
 
-pub const BLACK_KNIGHT_HEATMAP: [i16; 64] = [
-    30, 20, 20, 20, 20, 20, 20, 30, // a file
-    0, 0, 0, 0, 0, 0, 0, 10, // b file
-    0, 0, 0, 0, 0, 0, 0, 0, // c file
-    0, 0, 0, 0, 0, 0, 10, 0, // d file
-    0, 0, 0, 0, 0, 0, 10, 0, // e file
-    0, 0, 0, 0, 0, 0, 0, 0, // f file
-    0, 0, 0, 0, 0, 0, 0, 10, // g file
-    30, 20, 20, 20, 20, 20, 20, 30, // h file
+#[rustfmt::skip]
+const QUEEN_MIDDLE_GAME: [i16; 64] = [
+    -20, -10, -10,  -5,  -5, -10, -10, -20, // 8th rank
+    -10,   0,   0,   0,   0,   0,   0, -10, // 7th rank
+    -10,   0,   5,   5,   5,   5,   0, -10, // 6th rank
+     -5,   0,   5,   5,   5,   5,   0,  -5, // 5th rank
+      0,   0,   5,   5,   5,   5,   0,  -5, // 4th rank
+    -10,   5,   5,   5,   5,   5,   0, -10, // 3rd rank
+    -10,   0,   5,   0,   0,   0,   0, -10, // 2nd rank
+    -20, -10, -10,  -5,  -5, -10, -10, -20, // 1st rank
+];
+
+#[rustfmt::skip]
+const QUEEN_END_GAME: [i16; 64] = [
+    -20, -10, -10,  -5,  -5, -10, -10, -20, // 8th rank
+    -10,   0,   5,   5,   5,   5,   0, -10, // 7th rank
+    -10,   5,  10,  10,  10,  10,   5, -10, // 6th rank
+     -5,   5,  10,  15,  15,  10,   5,  -5, // 5th rank
+     -5,   5,  10,  15,  15,  10,   5,  -5, // 4th rank
+    -10,   5,  10,  10,  10,  10,   5, -10, // 3rd rank
+    -10,   0,   5,   5,   5,   5,   0, -10, // 2nd rank
+    -20, -10, -10,  -5,  -5, -10, -10, -20, // 1st rank
+];
+
+#[rustfmt::skip]
+const KING_MIDDLE_GAME: [i16; 64] = [
+    -30, -40, -40, -50, -50, -40, -40, -30, // 8th rank
+    -30, -40, -40, -50, -50, -40, -40, -30, // 7th rank
+    -30, -40, -40, -50, -50, -40, -40, -30, // 6th rank
+    -30, -40, -40, -50, -50, -40, -40, -30, // 5th rank
+    -20, -30, -30, -40, -40, -30, -30, -20, // 4th rank
+    -10, -20, -20, -20, -20, -20, -20, -10, // 3rd rank
+     20,  20,   0,   0,   0,   0,  20,  20, // 2nd rank
+     20,  30,  10,   0,   0,  10,  30,  20, // 1st rank
+];
+
+#[rustfmt::skip]
+const KING_END_GAME: [i16; 64] = [
+    -50, -40, -30, -20, -20, -30, -40, -50, // 8th rank
+    -30, -20, -10,   0,   0, -10, -20, -30, // 7th rank
+    -30, -10,  20,  30,  30,  20, -10, -30, // 6th rank
+    -30, -10,  30,  40,  40,  30, -10, -30, // 5th rank
+    -30, -10,  30,  40,  40,  30, -10, -30, // 4th rank
+    -30, -10,  20,  30,  30,  20, -10, -30, // 3rd rank
+    -30, -30,   0,   0,   0,   0, -30, -30, // 2nd rank
+    -50, -30, -30, -30, -30, -30, -30, -50, // 1st rank
 ];
 
 #[test]
 fn positional_value_test() {
+    use crate::piece::PieceKind;
+
     assert_eq!(
         Piece::new(Team::White, PieceKind::Knight)
             .positional_value(Position::from_uci("a8").unwrap()),
-        WHITE_KNIGHT_HEATMAP[Position::from_uci("a8").unwrap().index() as usize]
+        TaperedScore::new(KNIGHT_MIDDLE_GAME[0], KNIGHT_END_GAME[0])
     );
     assert_eq!(
         Piece::new(Team::White, PieceKind::King)
-            .positional_value(Position::from_uci("e1").unwrap()),
-        0
+            .positional_value(Position::from_uci("g1").unwrap()),
+        TaperedScore::new(30, -30)
     );
     assert_eq!(
         Piece::new(Team::Black, PieceKind::King)
-            .positional_value(Position::from_uci("e8").unwrap()),
-        0
+            .positional_value(Position::from_uci("g8").unwrap()),
+        TaperedScore::new(-30, 30)
     );
+    assert_eq!(Piece::Empty.positional_value(Position::new(0, 0)), TaperedScore::new(0, 0));
+}
+
+#[test]
+fn tables_are_mirrored_by_colour() {
+    use crate::piece::PieceKind;
+
+    for kind in PieceKind::kinds() {
+        for position in Position::positions() {
+            let mirrored = Position::new(position.x(), 7 - position.y());
+
+            assert_eq!(
+                Piece::new(Team::White, kind).positional_value(position),
+                -Piece::new(Team::Black, kind).positional_value(mirrored)
+            );
+        }
+    }
 }
@@ -7,23 +7,40 @@ use core::{
 };
 
 pub mod analysis;
+mod attacks;
+pub mod bitboard;
+pub mod book;
+mod builder;
+pub mod datagen;
+pub mod epd;
 mod evaluation;
 mod heatmap;
 mod killer;
 pub mod magic;
 pub mod move_gen;
 mod move_ordering;
+#[cfg(feature = "nn_evaluation")]
+pub mod nnue;
 pub mod pgn;
 pub mod piece;
 mod position;
 mod quiescence;
+pub mod render;
+pub mod tablebase;
+pub mod tournament;
 mod transposition_table;
+pub mod tuning;
 
+pub use bitboard::Bitboard;
+pub use builder::{BoardBuilder, BoardError};
+pub use evaluation::{EvalParams, EvalParamsError, EvalTerm, EvalTrace, TaperedScore};
 use move_gen::Move;
 pub use position::Position;
+pub use render::{BoardRenderer, PieceSet};
 
 pub use move_gen::MoveGen;
-use piece::{Piece, PieceKind, PAWN_VALUE};
+use piece::{Piece, PieceKind};
+pub use book::polyglot_key;
 pub use transposition_table::hash;
 use transposition_table::ZOBRIST_KEYS;
 
@@ -48,6 +65,16 @@ pub struct Board {
     king_positions: (Position, Position),
     /// The hash of the current board
     hash: u64,
+    /// The hash of just the pawns, used to look up the pawn structure score
+    pawn_hash: u64,
+    /// The sum of every piece's piece-square score, from white's perspective
+    piece_square_score: TaperedScore,
+    /// The game phase, see [`Board::phase`]
+    phase: i16,
+    /// The network's feature transformer output, kept up to date as
+    /// pieces move
+    #[cfg(feature = "nn_evaluation")]
+    accumulator: nnue::Accumulator,
 }
 
 impl Board {
@@ -60,6 +87,11 @@ impl Board {
         absolute_material: 0,
         king_positions: (Position::new(0, 0), Position::new(0, 0)),
         hash: 0,
+        pawn_hash: 0,
+        piece_square_score: TaperedScore::new(0, 0),
+        phase: 0,
+        #[cfg(feature = "nn_evaluation")]
+        accumulator: nnue::Accumulator::EMPTY,
     };
     /// Creates a new board, with a default configuration
     #[must_use]
@@ -71,43 +103,126 @@ impl Board {
     pub const fn to_play(&self) -> PlayableTeam {
         self.to_play
     }
-    /// Makes a [`Move`]
+    /// Makes a [`Move`], returning the resulting board
     pub fn make_move(&self, played_move: &Move) -> Option<Self> {
         let mut board = self.clone();
 
-        if played_move.captured_piece_kind() != PieceKind::None {
-            if board.to_play == PlayableTeam::White {
-                board.material += played_move.captured_piece_kind().value();
-            } else {
-                board.material -= played_move.captured_piece_kind().value();
+        board.make_move_mut(played_move);
+
+        Some(board)
+    }
+    /// Makes a [`Move`] in place, returning the [`UndoInfo`] needed to take
+    /// it back with [`Board::unmake_move`]. Pawns reaching the last rank are
+    /// promoted to queens
+    pub fn make_move_mut(&mut self, played_move: &Move) -> UndoInfo {
+        let undo = UndoInfo {
+            hash: self.hash,
+            material: self.material,
+            absolute_material: self.absolute_material,
+            king_positions: self.king_positions,
+            to_play: self.to_play,
+            turn: self.turn,
+            #[cfg(feature = "nn_evaluation")]
+            accumulator: self.accumulator,
+        };
+
+        let team = self.to_play;
+        let sign = match team {
+            PlayableTeam::White => 1,
+            PlayableTeam::Black => -1,
+        };
+        let (from, to) = played_move.from_to();
+        let captured = played_move.captured_piece_kind();
+
+        if captured != PieceKind::None {
+            self.material += sign * captured.value();
+
+            if captured != PieceKind::King {
+                self.absolute_material -= captured.value();
             }
 
-            // board.absolute_material -= played_move.captured_piece_kind().value();
+            self.remove_piece(Piece::new((!team).into(), captured), to);
+        }
 
-            board.remove_piece(
-                Piece::new((!board.to_play).into(), played_move.captured_piece_kind()),
-                played_move.to(),
-            );
+        let moved = played_move.moved_piece_kind();
+
+        self.remove_piece(Piece::new(team.into(), moved), from);
+
+        if played_move.flags().is_promotion() {
+            let promotion_value = PieceKind::Queen.value() - PieceKind::Pawn.value();
+
+            self.material += sign * promotion_value;
+            self.absolute_material += promotion_value;
+
+            self.add_piece(Piece::new(team.into(), PieceKind::Queen), to);
+        } else {
+            self.add_piece(Piece::new(team.into(), moved), to);
         }
-        board.move_piece(
-            played_move.moved_piece_kind(),
-            played_move.from(),
-            played_move.to(),
-        );
 
-        board.to_play = !board.to_play;
+        if moved == PieceKind::King {
+            match team {
+                PlayableTeam::White => self.king_positions.0 = to,
+                PlayableTeam::Black => self.king_positions.1 = to,
+            }
+        }
 
-        if board.to_play == PlayableTeam::Black {
-            board.hash ^= ZOBRIST_KEYS.1;
+        if team == PlayableTeam::Black {
+            self.turn += 1;
         }
 
-        Some(board)
+        self.to_play = !team;
+        self.hash ^= ZOBRIST_KEYS.1;
+
+        #[cfg(feature = "nn_evaluation")]
+        self.refresh_accumulator();
+
+        debug_assert_eq!(self.hash, hash(self));
+
+        undo
     }
-    fn move_piece(&mut self, kind: PieceKind, from: Position, to: Position) {
-        let piece = Piece::new(self.to_play.into(), kind);
+    /// Takes back a [`Move`] made with [`Board::make_move_mut`], restoring
+    /// the board to exactly how it was before the move
+    pub fn unmake_move(&mut self, played_move: &Move, undo: UndoInfo) {
+        let team = undo.to_play;
+        let (from, to) = played_move.from_to();
+        let moved = played_move.moved_piece_kind();
+
+        let placed = if played_move.flags().is_promotion() {
+            PieceKind::Queen
+        } else {
+            moved
+        };
+
+        // The accumulator is restored as it was, so there's no need to
+        // update it as the pieces go back
+        #[cfg(feature = "nn_evaluation")]
+        {
+            self.accumulator.computed = [false; 2];
+        }
+
+        self.remove_piece(Piece::new(team.into(), placed), to);
+        self.add_piece(Piece::new(team.into(), moved), from);
+
+        if played_move.captured_piece_kind() != PieceKind::None {
+            self.add_piece(
+                Piece::new((!team).into(), played_move.captured_piece_kind()),
+                to,
+            );
+        }
 
-        self.remove_piece(piece, from);
-        self.add_piece(piece, to);
+        self.hash = undo.hash;
+        self.material = undo.material;
+        self.absolute_material = undo.absolute_material;
+        self.king_positions = undo.king_positions;
+        self.to_play = undo.to_play;
+        self.turn = undo.turn;
+
+        #[cfg(feature = "nn_evaluation")]
+        {
+            self.accumulator = undo.accumulator;
+        }
+
+        debug_assert_eq!(self.hash, hash(self));
     }
     fn add_piece(&mut self, piece: Piece, position: Position) {
         let square = position.to_bitmap();
@@ -116,6 +231,16 @@ impl Board {
         self.all_pieces[piece.team() as usize] |= square;
 
         self.hash ^= ZOBRIST_KEYS.0[position.index() as usize][piece as usize];
+
+        if piece.kind() == PieceKind::Pawn {
+            self.pawn_hash ^= ZOBRIST_KEYS.0[position.index() as usize][piece as usize];
+        }
+
+        self.piece_square_score += piece.positional_value(position);
+        self.phase += piece.kind().phase();
+
+        #[cfg(feature = "nn_evaluation")]
+        self.update_accumulator(piece, position, true);
     }
     fn remove_piece(&mut self, piece: Piece, position: Position) {
         let square = position.to_bitmap();
@@ -124,6 +249,16 @@ impl Board {
         self.all_pieces[piece.team() as usize] ^= square;
 
         self.hash ^= ZOBRIST_KEYS.0[position.index() as usize][piece as usize];
+
+        if piece.kind() == PieceKind::Pawn {
+            self.pawn_hash ^= ZOBRIST_KEYS.0[position.index() as usize][piece as usize];
+        }
+
+        self.piece_square_score -= piece.positional_value(position);
+        self.phase -= piece.kind().phase();
+
+        #[cfg(feature = "nn_evaluation")]
+        self.update_accumulator(piece, position, false);
     }
     /// Makes a null move (Effectively just switching who it is to move)
     #[must_use]
@@ -131,6 +266,7 @@ impl Board {
         let mut board = self.clone();
 
         board.to_play = !board.to_play;
+        board.hash ^= ZOBRIST_KEYS.1;
 
         board
     }
@@ -187,6 +323,7 @@ impl Board {
         if let Some(to_play) = fen_parts.next() {
             if to_play.trim() == "b" {
                 board.to_play = PlayableTeam::Black;
+                board.hash ^= ZOBRIST_KEYS.1;
             }
         }
 
@@ -203,10 +340,57 @@ impl Board {
         board.turn = fen_parts.next()?.parse().ok()?;
         board.calculate_material();
 
+        #[cfg(feature = "nn_evaluation")]
+        board.refresh_accumulator();
+
         Some(board)
     }
-    const fn in_endgame(&self) -> bool {
-        self.absolute_material <= 24 * PAWN_VALUE
+    /// Writes the board as a FEN string. Castling rights and en passant
+    /// squares aren't tracked, so are always written as `-`
+    #[must_use]
+    pub fn to_fen(&self) -> String {
+        let mut fen = String::new();
+
+        for y in (0..8).rev() {
+            let mut empty = 0;
+
+            for x in 0..8 {
+                let piece = self.piece_at(Position::new(x, y));
+
+                if piece.is_empty() {
+                    empty += 1;
+                } else {
+                    if empty != 0 {
+                        fen.push_str(&empty.to_string());
+                        empty = 0;
+                    }
+                    fen.push(piece.fen_char());
+                }
+            }
+
+            if empty != 0 {
+                fen.push_str(&empty.to_string());
+            }
+            if y != 0 {
+                fen.push('/');
+            }
+        }
+
+        let to_play = match self.to_play {
+            PlayableTeam::White => 'w',
+            PlayableTeam::Black => 'b',
+        };
+
+        format!("{fen} {to_play} - - 0 {}", self.turn)
+    }
+    /// Returns the turn the board is on, as given by the FEN it was
+    /// created from
+    #[must_use]
+    pub const fn turn(&self) -> u16 {
+        self.turn
+    }
+    fn in_endgame(&self) -> bool {
+        self.absolute_material <= 24 * PieceKind::Pawn.value()
     }
     #[must_use]
     pub fn kind_at(&self, team: PlayableTeam, position: Position) -> PieceKind {
@@ -257,6 +441,24 @@ impl Board {
     pub const fn hash(&self) -> u64 {
         self.hash
     }
+    /// Returns the squares holding `team`'s pieces of `kind`
+    #[must_use]
+    pub const fn pieces(&self, team: PlayableTeam, kind: PieceKind) -> Bitboard {
+        match kind {
+            PieceKind::None => Bitboard::EMPTY,
+            _ => Bitboard(self.pieces[team as usize][kind as usize]),
+        }
+    }
+    /// Returns the squares holding any of `team`'s pieces
+    #[must_use]
+    pub const fn team_occupancy(&self, team: PlayableTeam) -> Bitboard {
+        Bitboard(self.all_pieces[team as usize])
+    }
+    /// Returns the squares holding a piece of either team
+    #[must_use]
+    pub const fn occupied(&self) -> Bitboard {
+        Bitboard(self.get_occupied())
+    }
     const fn get_occupied(&self) -> u64 {
         self.all_pieces[0] | self.all_pieces[1]
     }
@@ -265,16 +467,26 @@ impl Board {
     }
 }
 
+/// The state of a [`Board`] that can't be recovered from the [`Move`]
+/// alone, returned by [`Board::make_move_mut`] so the move can be unmade
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct UndoInfo {
+    hash: u64,
+    material: i16,
+    absolute_material: i16,
+    king_positions: (Position, Position),
+    to_play: PlayableTeam,
+    turn: u16,
+    /// The network's accumulator, which is cheaper to copy back than to
+    /// recompute after a king move
+    #[cfg(feature = "nn_evaluation")]
+    accumulator: nnue::Accumulator,
+}
+
+/// Draws the board with the default [`BoardRenderer`]
 impl Display for Board {
     fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
-        for y in 0..8 {
-            for x in 0..8 {
-                write!(f, "| {} |", self.piece_at(Position::new(x, 7 - y)))?;
-            }
-            writeln!(f)?;
-        }
-
-        Ok(())
+        write!(f, "{}", BoardRenderer::default().render(self))
     }
 }
 
@@ -284,10 +496,20 @@ impl Default for Board {
     }
 }
 
-/*
+#[test]
+fn fen_round_trip() {
+    for fen in [
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w - - 0 0",
+        "r2q1rk1/1p3p1p/1b4p1/pPp2b2/3pn1P1/P2Q4/B1P1NP1P/R1B2RK1 b - - 0 30",
+        "4k3/8/8/8/8/8/8/4K3 w - - 0 71",
+    ] {
+        assert_eq!(Board::from_fen(fen).unwrap().to_fen(), fen);
+    }
+}
+
 #[test]
 fn king_position_test() {
-    let board = Board::new();
+    let board = Board::from_fen("4k3/8/8/8/8/8/8/4K3 w - - 0 1").unwrap();
 
     assert_eq!(
         board.king_positions,
@@ -297,12 +519,8 @@ fn king_position_test() {
         )
     );
 
-    // Not a legal move
     let king_moved = board
-        .make_move(
-            Position::from_uci("e1").unwrap(),
-            Position::from_uci("e2").unwrap(),
-        )
+        .make_move(&board.parse_uci_move("e1e2").unwrap())
         .unwrap();
 
     assert_eq!(
@@ -312,7 +530,67 @@ fn king_position_test() {
             Position::from_uci("e8").unwrap()
         )
     );
-}*/
+}
+
+#[test]
+fn make_unmake_restores_board() {
+    for fen in [
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w - - 0 0",
+        "r2q1rk1/1p3p1p/1b4p1/pPp2b2/3pn1P1/P2Q4/B1P1NP1P/R1B2RK1 b - - 0 30",
+        "1n2k3/P7/8/8/8/8/7p/4K1N1 w - - 0 40",
+        "1n2k3/P7/8/8/8/8/7p/4K1N1 b - - 0 40",
+    ] {
+        let mut board = Board::from_fen(fen).unwrap();
+        let original = board.clone();
+
+        for possible_move in MoveGen::new(&original).into_inner() {
+            let undo = board.make_move_mut(&possible_move);
+
+            assert_eq!(board.hash, hash(&board));
+            assert_eq!(
+                board.pawn_hash,
+                Board::from_fen(&board.to_fen()).unwrap().pawn_hash
+            );
+            assert_ne!(board.to_play, original.to_play);
+
+            let mut recalculated = board.clone();
+            recalculated.material = 0;
+            recalculated.absolute_material = 0;
+            recalculated.calculate_material();
+            assert_eq!(board.absolute_material, recalculated.absolute_material);
+            if possible_move.captured_piece_kind() != PieceKind::King {
+                assert_eq!(board.material, recalculated.material);
+            }
+
+            board.unmake_move(&possible_move, undo);
+
+            assert_eq!(board, original);
+        }
+    }
+}
+
+#[test]
+fn promotion_makes_queen() {
+    let board = Board::from_fen("1n2k3/P7/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+
+    let promoted = board
+        .make_move(&board.parse_uci_move("a7a8q").unwrap())
+        .unwrap();
+
+    assert_eq!(
+        promoted.piece_at(Position::from_uci("a8").unwrap()),
+        Piece::WhiteQueen
+    );
+    assert_eq!(
+        promoted.material,
+        board.material + PieceKind::Queen.value() - PieceKind::Pawn.value()
+    );
+    assert_eq!(promoted.turn, board.turn);
+    assert_eq!(
+        promoted.make_move(&promoted.legal_moves()[0]).unwrap().turn,
+        board.turn + 1
+    );
+}
 
 /// A playable team
 #[repr(u8)]
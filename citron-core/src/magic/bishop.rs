@@ -37,7 +37,7 @@ const fn init_bishop_masks() -> [u64; 64] {
     masks
 }
 
-const fn get_bishop_attacks_slow(square: u64, blockers: u64) -> u64 {
+pub(super) const fn get_bishop_attacks_slow(square: u64, blockers: u64) -> u64 {
     let mut attacks = 0;
     let square = square as usize;
 
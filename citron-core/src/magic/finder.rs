@@ -0,0 +1,335 @@
+//! Searches for magic numbers and builds their attack tables at runtime,
+//! rather than relying on the hard-coded ones in `consts`.
+//!
+//! A magic maps each subset of the blockers under a piece's mask to an
+//! index, with `(blockers * magic) >> shift`. It's valid if no two subsets
+//! with different attacks share an index.
+//!
+//! By default each square gets the smallest shift its mask allows, and
+//! the squares' tables are packed one after the other. With
+//! [`MagicFinder::fixed_shift`] every square uses the same shift, so the
+//! tables are sparse, and each is placed at the lowest offset where it only
+//! overlaps empty entries or entries holding the same attacks. This is
+//! known as fixed shift fancy magics.
+
+use super::{
+    bishop::{get_bishop_attacks_slow, BISHOP_MASKS},
+    get_blockers_from_index,
+    rook::{get_rook_attacks_slow, ROOK_MASKS},
+};
+use crate::Position;
+
+/// A piece that slides along rays, and so needs magics
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Slider {
+    Rook,
+    Bishop,
+}
+
+impl Slider {
+    /// The squares whose pieces block the slider on `square`, excluding
+    /// the edges, which it always attacks
+    #[must_use]
+    pub const fn mask(self, square: usize) -> u64 {
+        match self {
+            Self::Rook => ROOK_MASKS[square],
+            Self::Bishop => BISHOP_MASKS[square],
+        }
+    }
+    /// Walks the slider's rays from `square`, stopping at the first blocker
+    /// in each direction
+    #[must_use]
+    pub const fn slow_attacks(self, square: usize, blockers: u64) -> u64 {
+        match self {
+            Self::Rook => get_rook_attacks_slow(square as u64, blockers),
+            Self::Bishop => get_bishop_attacks_slow(square as u64, blockers),
+        }
+    }
+    /// The index bits every square needs with fixed shift magics, the
+    /// most any square's mask has
+    const fn fixed_bits(self) -> u32 {
+        match self {
+            Self::Rook => 12,
+            Self::Bishop => 9,
+        }
+    }
+}
+
+/// A xorshift64* generator. It's plenty for picking magics, and much
+/// faster than `StdRng` in unoptimised builds
+struct Prng(u64);
+
+impl Prng {
+    const fn new(seed: u64) -> Self {
+        // The state can't be 0, or it stays 0
+        match seed.wrapping_add(0x9e37_79b9_7f4a_7c15) {
+            0 => Self(1),
+            state => Self(state),
+        }
+    }
+    const fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+}
+
+/// Settings for the search
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MagicFinder {
+    /// Use the same shift for every square, and overlap the squares'
+    /// tables to cut their size
+    pub fixed_shift: bool,
+    /// The number of magics to try for each square with a fixed shift,
+    /// keeping the one whose table fits in lowest
+    pub candidates: usize,
+    /// Seeds the search, so the same settings always find the same magics
+    pub seed: u64,
+}
+
+impl Default for MagicFinder {
+    fn default() -> Self {
+        Self {
+            fixed_shift: false,
+            candidates: 8,
+            seed: 0,
+        }
+    }
+}
+
+/// Magics for every square, and the attack table they index
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MagicTable {
+    pub slider: Slider,
+    pub magics: [u64; 64],
+    /// The number of bits each square's index has, so the product is
+    /// shifted right by 64 minus this
+    pub index_bits: [u32; 64],
+    /// Where each square's table starts in `attacks`
+    pub offsets: [usize; 64],
+    pub attacks: Vec<u64>,
+}
+
+impl MagicTable {
+    /// Returns the squares the slider on `position` attacks
+    #[must_use]
+    pub fn attacks(&self, position: Position, blockers: u64) -> u64 {
+        let square = position.index() as usize;
+        let blockers = blockers & self.slider.mask(square);
+        let index = blockers.wrapping_mul(self.magics[square]) >> (64 - self.index_bits[square]);
+
+        self.attacks[self.offsets[square] + index as usize]
+    }
+}
+
+/// Each subset of the blockers under a square's mask, and the attacks
+/// with those blockers
+fn occupancies(slider: Slider, square: usize) -> Vec<(u64, u64)> {
+    let mask = slider.mask(square);
+
+    (0..1 << mask.count_ones())
+        .map(|index| {
+            let blockers = get_blockers_from_index(index, mask);
+
+            (blockers, slider.slow_attacks(square, blockers))
+        })
+        .collect()
+}
+
+/// A table reused between attempts at a square's magic, so that each
+/// failed magic doesn't need a fresh one. An entry is only filled if it was
+/// written during the current attempt
+struct Attempt {
+    bits: u32,
+    entries: Vec<u64>,
+    attempts: Vec<u32>,
+    current: u32,
+}
+
+impl Attempt {
+    fn new(bits: u32) -> Self {
+        Self {
+            bits,
+            entries: vec![0; 1 << bits],
+            attempts: vec![0; 1 << bits],
+            current: 0,
+        }
+    }
+    /// Fills the table using `magic`, returning false if two occupancies
+    /// with different attacks collide
+    fn try_magic(&mut self, magic: u64, occupancies: &[(u64, u64)]) -> bool {
+        self.current += 1;
+
+        for &(blockers, attacks) in occupancies {
+            let index = (blockers.wrapping_mul(magic) >> (64 - self.bits)) as usize;
+
+            if self.attempts[index] != self.current {
+                self.attempts[index] = self.current;
+                self.entries[index] = attacks;
+            } else if self.entries[index] != attacks {
+                return false;
+            }
+        }
+
+        true
+    }
+    /// Returns the table filled by the last attempt. Slider attacks are
+    /// never empty, so unused entries are left as 0
+    fn table(&self) -> Vec<u64> {
+        self.entries
+            .iter()
+            .zip(&self.attempts)
+            .map(|(&entry, &attempt)| if attempt == self.current { entry } else { 0 })
+            .collect()
+    }
+}
+
+/// Returns the lowest offset `table` can be placed at in `attacks` without
+/// clashing. Tables can't overlap much in the dense part of `attacks`, so
+/// only offsets past where a table would fit entirely are tried
+fn lowest_offset(attacks: &[u64], table: &[u64]) -> usize {
+    let filled: Vec<_> = table
+        .iter()
+        .enumerate()
+        .filter(|(_, &entry)| entry != 0)
+        .collect();
+
+    (attacks.len().saturating_sub(table.len())..attacks.len())
+        .find(|&offset| {
+            filled.iter().all(|&(index, &entry)| {
+                attacks
+                    .get(offset + index)
+                    .map_or(true, |&old| old == 0 || old == entry)
+            })
+        })
+        .unwrap_or(attacks.len())
+}
+
+impl MagicFinder {
+    /// Searches for magics for every square
+    #[must_use]
+    pub fn find(&self, slider: Slider) -> MagicTable {
+        let mut rng = Prng::new(self.seed);
+
+        let mut magics = [0; 64];
+        let mut index_bits = [0; 64];
+        let mut offsets = [0; 64];
+        let mut attacks = Vec::new();
+
+        for square in 0..64 {
+            let occupancies = occupancies(slider, square);
+            let mask = slider.mask(square);
+            let bits = if self.fixed_shift {
+                slider.fixed_bits()
+            } else {
+                mask.count_ones()
+            };
+
+            let candidates = if self.fixed_shift {
+                self.candidates.max(1)
+            } else {
+                1
+            };
+            let mut attempt = Attempt::new(bits);
+            let mut candidate = || {
+                let (magic, table) = loop {
+                    // Magics with few bits set are much more likely to work
+                    let magic = rng.next() & rng.next() & rng.next();
+
+                    // The top bits of the product become the index, so
+                    // skip magics that leave them mostly empty
+                    if (mask.wrapping_mul(magic) >> 56).count_ones() < 6 {
+                        continue;
+                    }
+
+                    if attempt.try_magic(magic, &occupancies) {
+                        break (magic, attempt.table());
+                    }
+                };
+
+                let offset = if self.fixed_shift {
+                    lowest_offset(&attacks, &table)
+                } else {
+                    attacks.len()
+                };
+
+                (offset, magic, table)
+            };
+
+            let mut best = candidate();
+
+            for _ in 1..candidates {
+                let next = candidate();
+
+                if next.0 < best.0 {
+                    best = next;
+                }
+            }
+
+            let (offset, magic, table) = best;
+
+            if attacks.len() < offset + table.len() {
+                attacks.resize(offset + table.len(), 0);
+            }
+
+            for (entry, new) in attacks[offset..].iter_mut().zip(table) {
+                if new != 0 {
+                    *entry = new;
+                }
+            }
+
+            magics[square] = magic;
+            index_bits[square] = bits;
+            offsets[square] = offset;
+        }
+
+        MagicTable {
+            slider,
+            magics,
+            index_bits,
+            offsets,
+            attacks,
+        }
+    }
+}
+
+#[test]
+fn found_magics() {
+    for slider in [Slider::Rook, Slider::Bishop] {
+        let plain = MagicFinder::default().find(slider);
+        let fixed = MagicFinder {
+            fixed_shift: true,
+            ..MagicFinder::default()
+        }
+        .find(slider);
+
+        // Packing each square's table one after the other leaves no gaps
+        let total: usize = (0..64)
+            .map(|square| 1 << slider.mask(square).count_ones())
+            .sum();
+        assert_eq!(plain.attacks.len(), total);
+
+        // Overlapping the fixed shift tables saves space over giving each
+        // square its own
+        assert!(fixed.attacks.len() < 64 << slider.fixed_bits());
+
+        for table in [plain, fixed] {
+            for position in Position::positions() {
+                for (blockers, attacks) in occupancies(slider, position.index() as usize) {
+                    assert_eq!(table.attacks(position, blockers), attacks);
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn seeded_search() {
+    let finder = MagicFinder {
+        seed: 7,
+        ..MagicFinder::default()
+    };
+
+    assert_eq!(finder.find(Slider::Bishop), finder.find(Slider::Bishop));
+}
@@ -2,7 +2,10 @@ use crate::{PlayableTeam, Position};
 
 mod bishop;
 mod consts;
+pub mod finder;
 mod pawn;
+#[cfg(feature = "pext")]
+mod pext;
 mod rook;
 
 use bishop::{BISHOP_ATTACKS, BISHOP_MASKS};
@@ -20,8 +23,43 @@ pub const fn pawn_attacks(position: Position, team: PlayableTeam) -> u64 {
     PAWN_ATTACKS[team as usize][square]
 }
 
+/// Returns every square attacked by a set of pawns belonging to `team`
 #[must_use]
-pub fn bishop_attacks(position: Position, mut blockers: u64) -> u64 {
+pub const fn all_pawn_attacks(pawns: u64, team: PlayableTeam) -> u64 {
+    match team {
+        PlayableTeam::White => ((pawns << 9) & !MASK_FILE[0]) | ((pawns << 7) & !MASK_FILE[7]),
+        PlayableTeam::Black => ((pawns >> 9) & !MASK_FILE[7]) | ((pawns >> 7) & !MASK_FILE[0]),
+    }
+}
+
+/// Returns the squares a bishop on `position` attacks, stopping at the
+/// first piece in `blockers` in each direction. Uses `pext` when built with
+/// the `pext` feature, and magic multiplication otherwise
+#[inline]
+#[must_use]
+pub fn bishop_attacks(position: Position, blockers: u64) -> u64 {
+    #[cfg(feature = "pext")]
+    return pext::bishop_attacks(position, blockers);
+
+    #[cfg(not(feature = "pext"))]
+    bishop_attacks_magic(position, blockers)
+}
+
+/// Returns the squares a rook on `position` attacks, see [`bishop_attacks`]
+#[inline]
+#[must_use]
+pub fn rook_attacks(position: Position, blockers: u64) -> u64 {
+    #[cfg(feature = "pext")]
+    return pext::rook_attacks(position, blockers);
+
+    #[cfg(not(feature = "pext"))]
+    rook_attacks_magic(position, blockers)
+}
+
+/// Looks up a bishop's attacks with magic multiplication, whether or not
+/// the `pext` feature is enabled
+#[must_use]
+pub fn bishop_attacks_magic(position: Position, mut blockers: u64) -> u64 {
     let square = position.index() as usize;
 
     blockers &= BISHOP_MASKS[square];
@@ -30,8 +68,10 @@ pub fn bishop_attacks(position: Position, mut blockers: u64) -> u64 {
         >> (64 - BISHOP_INDEX_BITS[square])) as usize]
 }
 
+/// Looks up a rook's attacks with magic multiplication, whether or not the
+/// `pext` feature is enabled
 #[must_use]
-pub fn rook_attacks(position: Position, mut blockers: u64) -> u64 {
+pub fn rook_attacks_magic(position: Position, mut blockers: u64) -> u64 {
     let square = position.index() as usize;
 
     blockers &= ROOK_MASKS[square];
@@ -62,34 +102,15 @@ pub const fn pop_lsb(mask: &mut u64) -> u64 {
     index as u64
 }
 
+/// Returns the index of the lowest set bit. `board` mustn't be empty
 #[must_use]
 pub const fn bitscan_forward(board: u64) -> u64 {
-    const INDEX64: [u64; 64] = [
-        0, 47, 1, 56, 48, 27, 2, 60, 57, 49, 41, 37, 28, 16, 3, 61, 54, 58, 35, 52, 50, 42, 21, 44,
-        38, 32, 29, 23, 17, 11, 4, 62, 46, 55, 26, 59, 40, 36, 15, 53, 34, 51, 20, 43, 31, 22, 10,
-        45, 25, 39, 14, 33, 19, 30, 9, 24, 13, 18, 8, 12, 7, 6, 5, 63,
-    ];
-    const DEBRUIJN64: u64 = 0x03f79d71b4cb0a89;
-
-    INDEX64[(((board ^ (board - 1)).wrapping_mul(DEBRUIJN64)) >> 58) as usize]
+    board.trailing_zeros() as u64
 }
 
-const fn bitscan_backward(mut board: u64) -> u64 {
-    const INDEX64: [u64; 64] = [
-        0, 47, 1, 56, 48, 27, 2, 60, 57, 49, 41, 37, 28, 16, 3, 61, 54, 58, 35, 52, 50, 42, 21, 44,
-        38, 32, 29, 23, 17, 11, 4, 62, 46, 55, 26, 59, 40, 36, 15, 53, 34, 51, 20, 43, 31, 22, 10,
-        45, 25, 39, 14, 33, 19, 30, 9, 24, 13, 18, 8, 12, 7, 6, 5, 63,
-    ];
-
-    const DEBRUIJN64: u64 = 0x03f79d71b4cb0a89;
-
-    board |= board >> 1;
-    board |= board >> 2;
-    board |= board >> 4;
-    board |= board >> 8;
-    board |= board >> 16;
-    board |= board >> 32;
-    INDEX64[((board.wrapping_mul(DEBRUIJN64)) >> 58) as usize]
+/// Returns the index of the highest set bit. `board` mustn't be empty
+const fn bitscan_backward(board: u64) -> u64 {
+    63 - board.leading_zeros() as u64
 }
 
 const fn get_blockers_from_index(index: u64, mut mask: u64) -> u64 {
@@ -188,3 +209,36 @@ const fn row(square: usize) -> u64 {
 const fn col(square: usize) -> u64 {
     (square & 7) as u64
 }
+
+#[test]
+fn magics_match_slow_attacks() {
+    use finder::Slider;
+
+    // The magic lookups are called directly, so the hard-coded magics are
+    // checked even when the pext feature is enabled
+    for (slider, attacks) in [
+        (Slider::Rook, rook_attacks_magic as fn(Position, u64) -> u64),
+        (Slider::Bishop, bishop_attacks_magic),
+    ] {
+        for position in Position::positions() {
+            let square = position.index() as usize;
+            let mask = slider.mask(square);
+
+            for index in 0..1 << mask.count_ones() {
+                let blockers = get_blockers_from_index(index, mask);
+                let expected = slider.slow_attacks(square, blockers);
+
+                assert_eq!(
+                    attacks(position, blockers),
+                    expected,
+                    "{:?} {}",
+                    slider,
+                    position
+                );
+
+                // Pieces outside the mask never change the attacks
+                assert_eq!(attacks(position, blockers | !mask), expected);
+            }
+        }
+    }
+}
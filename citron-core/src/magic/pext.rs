@@ -0,0 +1,104 @@
+//! Slider attacks indexed with the BMI2 `pext` instruction, which gathers
+//! the blocker bits under a piece's mask into a dense index, in place of
+//! magic multiplication. Tables are packed with no gaps, so they're also
+//! smaller than the magic ones.
+//!
+//! Enabled with the `pext` feature, which needs BMI2 to be enabled at
+//! compile time, such as with `RUSTFLAGS="-C target-cpu=native"` (and
+//! `RUSTDOCFLAGS` for doc tests). `pext` is microcoded and slow on AMD CPUs
+//! before Zen 3, so the magic lookup is kept as the default.
+
+#[cfg(not(all(target_arch = "x86_64", target_feature = "bmi2")))]
+compile_error!(
+    "The pext feature needs BMI2, build with RUSTFLAGS=\"-C target-feature=+bmi2\" on a CPU that supports it"
+);
+
+use core::arch::x86_64::_pext_u64;
+use std::lazy::SyncLazy;
+
+use super::{
+    bishop::{get_bishop_attacks_slow, BISHOP_MASKS},
+    get_blockers_from_index,
+    rook::{get_rook_attacks_slow, ROOK_MASKS},
+};
+use crate::Position;
+
+/// Every square's attacks, one after the other, indexed by the square's
+/// offset plus the `pext` of the blockers
+struct PextTable {
+    offsets: [usize; 64],
+    attacks: Vec<u64>,
+}
+
+impl PextTable {
+    fn new(masks: &[u64; 64], slow_attacks: fn(u64, u64) -> u64) -> Self {
+        let mut offsets = [0; 64];
+        let mut attacks = Vec::new();
+
+        for (square, &mask) in masks.iter().enumerate() {
+            offsets[square] = attacks.len();
+
+            // `pext` packs the bits of the mask from lowest to highest, the
+            // same order the blockers are spread out in
+            for index in 0..1 << mask.count_ones() {
+                let blockers = get_blockers_from_index(index, mask);
+
+                attacks.push(slow_attacks(square as u64, blockers));
+            }
+        }
+
+        Self { offsets, attacks }
+    }
+    #[inline]
+    fn get(&self, square: usize, mask: u64, blockers: u64) -> u64 {
+        // SAFETY: BMI2 is enabled at compile time, or the module wouldn't
+        // build
+        let index = unsafe { _pext_u64(blockers, mask) } as usize;
+
+        self.attacks[self.offsets[square] + index]
+    }
+}
+
+static BISHOP_ATTACKS: SyncLazy<PextTable> =
+    SyncLazy::new(|| PextTable::new(&BISHOP_MASKS, get_bishop_attacks_slow));
+
+static ROOK_ATTACKS: SyncLazy<PextTable> =
+    SyncLazy::new(|| PextTable::new(&ROOK_MASKS, get_rook_attacks_slow));
+
+#[inline]
+pub fn bishop_attacks(position: Position, blockers: u64) -> u64 {
+    let square = position.index() as usize;
+
+    BISHOP_ATTACKS.get(square, BISHOP_MASKS[square], blockers)
+}
+
+#[inline]
+pub fn rook_attacks(position: Position, blockers: u64) -> u64 {
+    let square = position.index() as usize;
+
+    ROOK_ATTACKS.get(square, ROOK_MASKS[square], blockers)
+}
+
+#[test]
+fn pext_matches_magic() {
+    use super::{bishop_attacks_magic, rook_attacks_magic};
+
+    for position in Position::positions() {
+        let square = position.index() as usize;
+
+        for (mask, pext, magic) in [
+            (
+                BISHOP_MASKS[square],
+                bishop_attacks as fn(Position, u64) -> u64,
+                bishop_attacks_magic as fn(Position, u64) -> u64,
+            ),
+            (ROOK_MASKS[square], rook_attacks, rook_attacks_magic),
+        ] {
+            for index in 0..1 << mask.count_ones() {
+                let blockers = get_blockers_from_index(index, mask);
+
+                assert_eq!(pext(position, blockers), magic(position, blockers));
+            }
+        }
+    }
+}
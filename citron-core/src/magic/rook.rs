@@ -34,7 +34,7 @@ const fn init_rook_masks() -> [u64; 64] {
     masks
 }
 
-const fn get_rook_attacks_slow(square: u64, blockers: u64) -> u64 {
+pub(super) const fn get_rook_attacks_slow(square: u64, blockers: u64) -> u64 {
     let mut attacks = 0;
     let square = square as usize;
 
@@ -1,7 +1,7 @@
 use crate::{
-    magic::{self, pop_lsb, MASK_FILE, MASK_RANK},
+    magic::{self, MASK_FILE, MASK_RANK},
     piece::PieceKind,
-    Board, PlayableTeam, Position,
+    Bitboard, Board, PlayableTeam, Position,
 };
 
 use std::fmt::Display;
@@ -64,12 +64,32 @@ impl Move {
     pub const fn captured_piece_kind(&self) -> PieceKind {
         self.captured_piece_kind
     }
+    /// Writes the move in UCI long algebraic notation, such as `e2e4`, or
+    /// `e7e8q` for a promotion
+    #[must_use]
+    pub fn to_uci(&self) -> String {
+        let (from_x, from_y) = self.from.to_uci();
+        let (to_x, to_y) = self.to.to_uci();
+
+        let mut uci = format!("{from_x}{from_y}{to_x}{to_y}");
+
+        if self.flags.is_promotion() {
+            uci.push('q');
+        }
+
+        uci
+    }
     pub(crate) const fn ordering_value(&self) -> &u16 {
         &self.ordering_value
     }
     pub(crate) fn ordering_value_mut(&mut self) -> &mut u16 {
         &mut self.ordering_value
     }
+    /// Returns the move's [`MoveFlags`]
+    #[must_use]
+    pub const fn flags(&self) -> &MoveFlags {
+        &self.flags
+    }
     pub(crate) fn flags_mut(&mut self) -> &mut MoveFlags {
         &mut self.flags
     }
@@ -77,16 +97,16 @@ impl Move {
 
 impl Display for Move {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let (from_x, from_y) = self.from.to_uci();
-        let (to_x, to_y) = self.to.to_uci();
-        write!(f, "({from_x}{from_y}) ({to_x}{to_y})")
+        write!(f, "{}", self.to_uci())
     }
 }
 
+/// Extra information attached to a [`Move`], such as whether it's a promotion
 #[derive(Debug, Default, Clone, PartialEq, Eq, Hash)]
 pub struct MoveFlags(u8);
 
 impl MoveFlags {
+    #[must_use]
     pub const fn is_promotion(&self) -> bool {
         self.0 & 1 == 1
     }
@@ -119,6 +139,120 @@ impl MoveGen {
     }
 }
 
+/// An error returned when a move given as text can't be turned into a [`Move`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MoveParseError {
+    /// The text isn't a well formed move
+    InvalidSyntax,
+    /// The text is well formed, but no legal move matches it
+    IllegalMove,
+    /// More than one legal move matches the text
+    AmbiguousMove,
+}
+
+impl Display for MoveParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Self::InvalidSyntax => "invalid move syntax",
+                Self::IllegalMove => "illegal move",
+                Self::AmbiguousMove => "ambiguous move",
+            }
+        )
+    }
+}
+
+impl Board {
+    /// Returns true if `team`'s king could be captured, were it the other
+    /// side's turn to play
+    #[must_use]
+    pub fn in_check(&self, team: PlayableTeam) -> bool {
+        self.king_position(team)
+            .map_or(false, |king| self.is_square_attacked(king, !team))
+    }
+    /// Generates every move for the side to play that doesn't leave its
+    /// own king open to capture
+    #[must_use]
+    pub fn legal_moves(&self) -> Vec<Move> {
+        let mut moves = MoveGen::new(self).into_inner();
+
+        moves.retain(|possible_move| {
+            self.make_move(possible_move)
+                .map_or(false, |board| !board.in_check(self.to_play))
+        });
+
+        moves
+    }
+    /// Parses a move written in UCI long algebraic notation, such as
+    /// `e2e4` or `e7e8q`, by matching it against the legal moves in the
+    /// position, so the moved and captured pieces and the move's flags
+    /// are filled in
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`MoveParseError`] if the text isn't a well formed UCI
+    /// move, or if it doesn't match any legal move
+    pub fn parse_uci_move(&self, uci: &str) -> Result<Move, MoveParseError> {
+        let uci = uci.trim();
+
+        if !matches!(uci.len(), 4 | 5) || !uci.is_ascii() {
+            return Err(MoveParseError::InvalidSyntax);
+        }
+
+        let from = Position::from_uci(&uci[0..2]).ok_or(MoveParseError::InvalidSyntax)?;
+        let to = Position::from_uci(&uci[2..4]).ok_or(MoveParseError::InvalidSyntax)?;
+
+        let promotion = match uci[4..].chars().next() {
+            None => None,
+            Some(c @ ('q' | 'r' | 'b' | 'n' | 'Q' | 'R' | 'B' | 'N')) => {
+                Some(c.to_ascii_lowercase())
+            }
+            Some(_) => return Err(MoveParseError::InvalidSyntax),
+        };
+
+        self.legal_moves()
+            .into_iter()
+            .find(|possible_move| {
+                possible_move.from_to() == (from, to)
+                    && match promotion {
+                        // Pawns are always promoted to queens
+                        Some(c) => possible_move.flags().is_promotion() && c == 'q',
+                        None => !possible_move.flags().is_promotion(),
+                    }
+            })
+            .ok_or(MoveParseError::IllegalMove)
+    }
+}
+
+#[test]
+fn uci_moves() {
+    let board = Board::new();
+
+    let pawn_push = board.parse_uci_move("e2e4").unwrap();
+    assert_eq!(pawn_push.moved_piece_kind(), PieceKind::Pawn);
+    assert_eq!(pawn_push.to_uci(), "e2e4");
+    assert_eq!(pawn_push.to_string(), "e2e4");
+
+    let knight = board.parse_uci_move("g1f3").unwrap();
+    assert_eq!(knight.moved_piece_kind(), PieceKind::Knight);
+
+    assert_eq!(board.parse_uci_move("e2e5"), Err(MoveParseError::IllegalMove));
+    assert_eq!(board.parse_uci_move("e3e4"), Err(MoveParseError::IllegalMove));
+    assert_eq!(board.parse_uci_move("e2"), Err(MoveParseError::InvalidSyntax));
+    assert_eq!(board.parse_uci_move("e2e4x"), Err(MoveParseError::InvalidSyntax));
+
+    let board = Board::from_fen("1n2k3/P7/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+
+    let promotion = board.parse_uci_move("a7b8q").unwrap();
+    assert!(promotion.flags().is_promotion());
+    assert_eq!(promotion.captured_piece_kind(), PieceKind::Knight);
+    assert_eq!(promotion.to_uci(), "a7b8q");
+
+    assert_eq!(board.parse_uci_move("a7a8"), Err(MoveParseError::IllegalMove));
+}
+
 #[test]
 fn original_position_moves() {
     let board = Board::new();
@@ -181,25 +315,22 @@ impl Board {
             self.pieces[PlayableTeam::White as usize][PieceKind::Pawn as usize] << 8;
         moved_pawns &= self.get_not_occupied();
 
-        let mut promotions = moved_pawns & MASK_RANK[7];
+        let promotions = moved_pawns & MASK_RANK[7];
         moved_pawns &= !MASK_RANK[7];
 
-        while moved_pawns != 0 {
-            let to = pop_lsb(&mut moved_pawns);
+        for to in Bitboard(moved_pawns) {
             move_list.push(Move::new(
-                Position::from_u8((to - 8) as u8),
-                Position::from_u8(to as u8),
+                Position::from_u8(to.index() - 8),
+                to,
                 PieceKind::Pawn,
                 PieceKind::None,
             ));
         }
 
-        while promotions != 0 {
-            let to = pop_lsb(&mut promotions);
-
+        for to in Bitboard(promotions) {
             let mut new_move = Move::new(
-                Position::from_u8((to - 8) as u8),
-                Position::from_u8(to as u8),
+                Position::from_u8(to.index() - 8),
+                to,
                 PieceKind::Pawn,
                 PieceKind::None,
             );
@@ -213,13 +344,12 @@ impl Board {
         let single_pushes = (self.pieces[PlayableTeam::White as usize][PieceKind::Pawn as usize]
             << 8)
             & self.get_not_occupied();
-        let mut double_pushes = (single_pushes << 8) & self.get_not_occupied() & MASK_RANK[3];
+        let double_pushes = (single_pushes << 8) & self.get_not_occupied() & MASK_RANK[3];
 
-        while double_pushes != 0 {
-            let to = pop_lsb(&mut double_pushes);
+        for to in Bitboard(double_pushes) {
             move_list.push(Move::new(
-                Position::from_u8((to - 16) as u8),
-                Position::from_u8(to as u8),
+                Position::from_u8(to.index() - 16),
+                to,
                 PieceKind::Pawn,
                 PieceKind::None,
             ));
@@ -232,28 +362,24 @@ impl Board {
                 & self.all_pieces[PlayableTeam::Black as usize]
                 & !MASK_FILE[7];
 
-        let mut left_promotion_attacks = left_attacks & MASK_RANK[7];
+        let left_promotion_attacks = left_attacks & MASK_RANK[7];
         left_attacks &= !MASK_RANK[7];
 
-        while left_attacks != 0 {
-            let to = pop_lsb(&mut left_attacks);
-
+        for to in Bitboard(left_attacks) {
             move_list.push(Move::new(
-                Position::from_u8((to - 7) as u8),
-                Position::from_u8(to as u8),
+                Position::from_u8(to.index() - 7),
+                to,
                 PieceKind::Pawn,
-                self.kind_at(PlayableTeam::Black, Position::from_u8(to as u8)),
+                self.kind_at(PlayableTeam::Black, to),
             ));
         }
 
-        while left_promotion_attacks != 0 {
-            let to = pop_lsb(&mut left_promotion_attacks);
-
+        for to in Bitboard(left_promotion_attacks) {
             let mut new_move = Move::new(
-                Position::from_u8((to - 7) as u8),
-                Position::from_u8(to as u8),
+                Position::from_u8(to.index() - 7),
+                to,
                 PieceKind::Pawn,
-                self.kind_at(PlayableTeam::Black, Position::from_u8(to as u8)),
+                self.kind_at(PlayableTeam::Black, to),
             );
             new_move.flags_mut().set_promotion(true);
 
@@ -267,28 +393,24 @@ impl Board {
                 & self.all_pieces[PlayableTeam::Black as usize]
                 & !MASK_FILE[0];
 
-        let mut right_promotion_attacks = right_attacks & MASK_RANK[7];
+        let right_promotion_attacks = right_attacks & MASK_RANK[7];
         right_attacks &= !MASK_RANK[7];
 
-        while right_attacks != 0 {
-            let to = pop_lsb(&mut right_attacks);
-
+        for to in Bitboard(right_attacks) {
             move_list.push(Move::new(
-                Position::from_u8((to - 9) as u8),
-                Position::from_u8(to as u8),
+                Position::from_u8(to.index() - 9),
+                to,
                 PieceKind::Pawn,
-                self.kind_at(PlayableTeam::Black, Position::from_u8(to as u8)),
+                self.kind_at(PlayableTeam::Black, to),
             ));
         }
 
-        while right_promotion_attacks != 0 {
-            let to = pop_lsb(&mut right_promotion_attacks);
-
+        for to in Bitboard(right_promotion_attacks) {
             let mut new_move = Move::new(
-                Position::from_u8((to - 9) as u8),
-                Position::from_u8(to as u8),
+                Position::from_u8(to.index() - 9),
+                to,
                 PieceKind::Pawn,
-                self.kind_at(PlayableTeam::Black, Position::from_u8(to as u8)),
+                self.kind_at(PlayableTeam::Black, to),
             );
             new_move.flags_mut().set_promotion(true);
 
@@ -320,25 +442,22 @@ impl Board {
             self.pieces[PlayableTeam::Black as usize][PieceKind::Pawn as usize] >> 8;
         moved_pawns &= self.get_not_occupied();
 
-        let mut promotions = moved_pawns & MASK_RANK[0];
+        let promotions = moved_pawns & MASK_RANK[0];
         moved_pawns &= !MASK_RANK[0];
 
-        while moved_pawns != 0 {
-            let to = pop_lsb(&mut moved_pawns);
+        for to in Bitboard(moved_pawns) {
             move_list.push(Move::new(
-                Position::from_u8((to + 8) as u8),
-                Position::from_u8(to as u8),
+                Position::from_u8(to.index() + 8),
+                to,
                 PieceKind::Pawn,
                 PieceKind::None,
             ));
         }
 
-        while promotions != 0 {
-            let to = pop_lsb(&mut promotions);
-
+        for to in Bitboard(promotions) {
             let mut new_move = Move::new(
-                Position::from_u8((to + 8) as u8),
-                Position::from_u8(to as u8),
+                Position::from_u8(to.index() + 8),
+                to,
                 PieceKind::Pawn,
                 PieceKind::None,
             );
@@ -352,13 +471,12 @@ impl Board {
         let single_pushes = (self.pieces[PlayableTeam::Black as usize][PieceKind::Pawn as usize]
             >> 8)
             & self.get_not_occupied();
-        let mut double_pushes = (single_pushes >> 8) & self.get_not_occupied() & MASK_RANK[4];
+        let double_pushes = (single_pushes >> 8) & self.get_not_occupied() & MASK_RANK[4];
 
-        while double_pushes != 0 {
-            let to = pop_lsb(&mut double_pushes);
+        for to in Bitboard(double_pushes) {
             move_list.push(Move::new(
-                Position::from_u8((to + 16) as u8),
-                Position::from_u8(to as u8),
+                Position::from_u8(to.index() + 16),
+                to,
                 PieceKind::Pawn,
                 PieceKind::None,
             ));
@@ -371,28 +489,24 @@ impl Board {
                 & self.all_pieces[PlayableTeam::White as usize]
                 & !MASK_FILE[0];
 
-        let mut left_promotion_attacks = left_attacks & MASK_RANK[0];
+        let left_promotion_attacks = left_attacks & MASK_RANK[0];
         left_attacks &= !MASK_RANK[0];
 
-        while left_attacks != 0 {
-            let to = pop_lsb(&mut left_attacks);
-
+        for to in Bitboard(left_attacks) {
             move_list.push(Move::new(
-                Position::from_u8((to + 7) as u8),
-                Position::from_u8(to as u8),
+                Position::from_u8(to.index() + 7),
+                to,
                 PieceKind::Pawn,
-                self.kind_at(PlayableTeam::White, Position::from_u8(to as u8)),
+                self.kind_at(PlayableTeam::White, to),
             ));
         }
 
-        while left_promotion_attacks != 0 {
-            let to = pop_lsb(&mut left_promotion_attacks);
-
+        for to in Bitboard(left_promotion_attacks) {
             let mut new_move = Move::new(
-                Position::from_u8((to + 7) as u8),
-                Position::from_u8(to as u8),
+                Position::from_u8(to.index() + 7),
+                to,
                 PieceKind::Pawn,
-                self.kind_at(PlayableTeam::White, Position::from_u8(to as u8)),
+                self.kind_at(PlayableTeam::White, to),
             );
             new_move.flags_mut().set_promotion(true);
 
@@ -406,28 +520,24 @@ impl Board {
                 & self.all_pieces[PlayableTeam::White as usize]
                 & !MASK_FILE[0];
 
-        let mut right_promotion_attacks = right_attacks & MASK_RANK[0];
+        let right_promotion_attacks = right_attacks & MASK_RANK[0];
         right_attacks &= !MASK_RANK[0];
 
-        while right_attacks != 0 {
-            let to = pop_lsb(&mut right_attacks);
-
+        for to in Bitboard(right_attacks) {
             move_list.push(Move::new(
-                Position::from_u8((to + 9) as u8),
-                Position::from_u8(to as u8),
+                Position::from_u8(to.index() + 9),
+                to,
                 PieceKind::Pawn,
-                self.kind_at(PlayableTeam::White, Position::from_u8(to as u8)),
+                self.kind_at(PlayableTeam::White, to),
             ));
         }
 
-        while right_promotion_attacks != 0 {
-            let to = pop_lsb(&mut right_promotion_attacks);
-
+        for to in Bitboard(right_promotion_attacks) {
             let mut new_move = Move::new(
-                Position::from_u8((to + 9) as u8),
-                Position::from_u8(to as u8),
+                Position::from_u8(to.index() + 9),
+                to,
                 PieceKind::Pawn,
-                self.kind_at(PlayableTeam::White, Position::from_u8(to as u8)),
+                self.kind_at(PlayableTeam::White, to),
             );
             new_move.flags_mut().set_promotion(true);
 
@@ -441,27 +551,30 @@ impl Board {
         &self,
         team: PlayableTeam,
         kind: PieceKind,
-        mut pieces: u64,
+        pieces: u64,
         blockers: u64,
         move_list: &mut Vec<Move>,
     ) {
-        while pieces != 0 {
-            let from = Position::from_u8(magic::pop_lsb(&mut pieces) as u8);
-
-            let mut moves = Self::get_attacks_for_square(kind, from, blockers);
-
-            while moves != 0 {
-                let to = Position::from_u8(magic::pop_lsb(&mut moves) as u8);
+        for from in Bitboard(pieces) {
+            let moves = Self::get_attacks_for_square(team, kind, from, blockers);
 
+            for to in Bitboard(moves) {
                 if self.team_at(to) != self.to_play.into() {
                     move_list.push(Move::new(from, to, kind, self.kind_at(!team, to)));
                 }
             }
         }
     }
-    fn get_attacks_for_square(kind: PieceKind, position: Position, blockers: u64) -> u64 {
+    /// Returns the squares a piece of `team` and `kind` on `position`
+    /// attacks, given the pieces that block sliders
+    pub(crate) fn get_attacks_for_square(
+        team: PlayableTeam,
+        kind: PieceKind,
+        position: Position,
+        blockers: u64,
+    ) -> u64 {
         match kind {
-            PieceKind::Pawn => todo!(),
+            PieceKind::Pawn => magic::pawn_attacks(position, team),
             PieceKind::Rook => magic::rook_attacks(position, blockers),
             PieceKind::Knight => magic::knight_attacks(position),
             PieceKind::Bishop => magic::bishop_attacks(position, blockers),
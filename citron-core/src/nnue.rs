@@ -1,126 +1,551 @@
-use crate::PlayableTeam;
+//! An efficiently updatable neural network evaluation, used in place of the
+//! hand written evaluation when the `nn_evaluation` feature is enabled and
+//! a network has been installed.
+//!
+//! The network uses HalfKP features: for each side, every non-king piece
+//! is indexed by that side's king square, the piece's kind and colour, and
+//! its square, all from that side's point of view. These feed a feature
+//! transformer of [`L1`] neurons per side, kept up to date incrementally as
+//! pieces move, followed by two clipped ReLU hidden layers of [`L2`] and
+//! [`L3`] neurons and a single output.
+//!
+//! # File format
+//!
+//! Networks are stored as a flat binary file, with every number little
+//! endian and every matrix stored one output neuron at a time:
+//!
+//! | Field                       | Type  | Count              |
+//! |-----------------------------|-------|--------------------|
+//! | Magic, `CNUE`               | bytes | 4                  |
+//! | Version, currently 1        | `u32` | 1                  |
+//! | Feature transformer weights | `i16` | [`FEATURES`] × L1  |
+//! | Feature transformer biases  | `i16` | L1                 |
+//! | First hidden layer weights  | `i8`  | L2 × 2 × L1        |
+//! | First hidden layer biases   | `i32` | L2                 |
+//! | Second hidden layer weights | `i8`  | L3 × L2            |
+//! | Second hidden layer biases  | `i32` | L3                 |
+//! | Output weights              | `i8`  | L3                 |
+//! | Output bias                 | `i32` | 1                  |
+//!
+//! Feature transformer weights are stored one feature at a time, so each
+//! feature's [`L1`] weights are contiguous. A feature's index is
+//! `king * 640 + (kind * 2 + colour) * 64 + square`, where squares count
+//! from a1 to h8 and are flipped vertically for black, `kind` follows
+//! [`PieceKind`] (pawn, rook, knight, bishop, queen), and `colour` is 0 for
+//! the side's own pieces and 1 for its opponent's.
+//!
+//! The first hidden layer takes the side to move's accumulator followed by
+//! the other side's, each clipped to `0..=127`. Hidden layer sums are
+//! shifted right by 6 and clipped to `0..=127`, and the output is divided
+//! by 16 to give centipawns for the side to move.
 
-use tch::{nn::Linear, IndexOp};
+use std::{
+    io::{self, Read, Write},
+    lazy::SyncOnceCell,
+    path::Path,
+};
 
-pub struct NnueAccumulator {
-    white: [f64; 40960],
-    black: [f64; 40960],
+use crate::{
+    magic::pop_lsb,
+    piece::{Piece, PieceKind},
+    Board, PlayableTeam, Position, Team,
+};
+
+/// The number of HalfKP features for each side
+pub const FEATURES: usize = 64 * 10 * 64;
+/// The number of feature transformer neurons for each side
+pub const L1: usize = 256;
+/// The number of neurons in the first hidden layer
+pub const L2: usize = 32;
+/// The number of neurons in the second hidden layer
+pub const L3: usize = 32;
+
+/// The inputs to the first hidden layer, both sides' accumulators
+const L1_INPUTS: usize = 2 * L1;
+const MAGIC: &[u8; 4] = b"CNUE";
+const VERSION: u32 = 1;
+/// The most any clipped ReLU activation can be
+const ACTIVATION_MAX: i32 = 127;
+/// The shift which scales hidden layer sums back into activations
+const WEIGHT_SHIFT: u32 = 6;
+/// The divisor which turns the output into centipawns
+const OUTPUT_SCALE: i32 = 16;
+
+/// The network the engine evaluates with, set once by [`Network::install`]
+static NETWORK: SyncOnceCell<Network> = SyncOnceCell::new();
+
+/// The weights of a network, quantised to integers
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Network {
+    feature_weights: Vec<i16>,
+    feature_biases: Vec<i16>,
+    hidden1_weights: Vec<i8>,
+    hidden1_biases: Vec<i32>,
+    hidden2_weights: Vec<i8>,
+    hidden2_biases: Vec<i32>,
+    output_weights: Vec<i8>,
+    output_bias: i32,
+}
+
+/// The feature transformer's output for each side, indexed by
+/// [`PlayableTeam`]. A side's accumulator needs a full refresh whenever
+/// its king moves, so each side tracks whether its values are up to date
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(C, align(64))]
+pub struct Accumulator {
+    values: [[i16; L1]; 2],
+    pub(crate) computed: [bool; 2],
+}
+
+impl Accumulator {
+    pub const EMPTY: Self = Self {
+        values: [[0; L1]; 2],
+        computed: [false; 2],
+    };
 }
 
-impl NnueAccumulator {
-    pub fn white_mut(&mut self) -> &mut [f64] {
-        &mut self.white
+impl Network {
+    /// Reads a network in the format described in the [module
+    /// documentation](self)
+    pub fn from_reader(mut reader: impl Read) -> io::Result<Self> {
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+
+        if &magic != MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Not a citron network file",
+            ));
+        }
+
+        let version = u32::from_le_bytes(read_array(&mut reader)?);
+
+        if version != VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Unsupported network version {}", version),
+            ));
+        }
+
+        Ok(Self {
+            feature_weights: read_values(&mut reader, FEATURES * L1, i16::from_le_bytes)?,
+            feature_biases: read_values(&mut reader, L1, i16::from_le_bytes)?,
+            hidden1_weights: read_values(&mut reader, L2 * L1_INPUTS, i8::from_le_bytes)?,
+            hidden1_biases: read_values(&mut reader, L2, i32::from_le_bytes)?,
+            hidden2_weights: read_values(&mut reader, L3 * L2, i8::from_le_bytes)?,
+            hidden2_biases: read_values(&mut reader, L3, i32::from_le_bytes)?,
+            output_weights: read_values(&mut reader, L3, i8::from_le_bytes)?,
+            output_bias: i32::from_le_bytes(read_array(&mut reader)?),
+        })
+    }
+    /// Writes the network in the format described in the [module
+    /// documentation](self)
+    pub fn write_to(&self, mut writer: impl Write) -> io::Result<()> {
+        writer.write_all(MAGIC)?;
+        writer.write_all(&VERSION.to_le_bytes())?;
+
+        for weight in &self.feature_weights {
+            writer.write_all(&weight.to_le_bytes())?;
+        }
+        for bias in &self.feature_biases {
+            writer.write_all(&bias.to_le_bytes())?;
+        }
+        for weight in &self.hidden1_weights {
+            writer.write_all(&weight.to_le_bytes())?;
+        }
+        for bias in &self.hidden1_biases {
+            writer.write_all(&bias.to_le_bytes())?;
+        }
+        for weight in &self.hidden2_weights {
+            writer.write_all(&weight.to_le_bytes())?;
+        }
+        for bias in &self.hidden2_biases {
+            writer.write_all(&bias.to_le_bytes())?;
+        }
+        for weight in &self.output_weights {
+            writer.write_all(&weight.to_le_bytes())?;
+        }
+
+        writer.write_all(&self.output_bias.to_le_bytes())
     }
-    pub fn black_mut(&mut self) -> &mut [f64] {
-        &mut self.black
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::from_reader(io::BufReader::new(std::fs::File::open(path)?))
     }
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut writer = io::BufWriter::new(std::fs::File::create(path)?);
 
-    pub fn refresh(&mut self, layer: Linear, active_features: Vec<i64>, to_play: PlayableTeam) {
-        if let Some(bias) = layer.bs {
-            match to_play {
-                PlayableTeam::White => {
-                    for (white, bias) in
-                        self.white_mut().iter_mut().zip(bias.iter::<f64>().unwrap())
-                    {
-                        *white = bias
-                    }
+        self.write_to(&mut writer)?;
+        writer.flush()
+    }
+    /// Makes this the network the engine evaluates with. Returns false if
+    /// one was already installed
+    pub fn install(self) -> bool {
+        NETWORK.set(self).is_ok()
+    }
+    /// Evaluates a position from its accumulator, returning the score in
+    /// centipawns from white's perspective
+    #[must_use]
+    pub fn evaluate(&self, accumulator: &Accumulator, to_play: PlayableTeam) -> i16 {
+        let mut input = [0; L1_INPUTS];
 
-                    for feature in active_features {
-                        for (index, white) in self.white_mut().iter_mut().enumerate() {
-                            *white += layer.ws.i(feature).double_value(&[index as i64]);
-                        }
-                    }
-                }
-                PlayableTeam::Black => {
-                    for (black, bias) in
-                        self.black_mut().iter_mut().zip(bias.iter::<f64>().unwrap())
-                    {
-                        *black = bias
-                    }
-
-                    for feature in active_features {
-                        for (index, black) in self.black_mut().iter_mut().enumerate() {
-                            *black += layer.ws.i(feature).double_value(&[index as i64]);
-                        }
-                    }
-                }
-            }
+        let (own, other) = input.split_at_mut(L1);
+        clipped_relu(&accumulator.values[to_play as usize], own);
+        clipped_relu(&accumulator.values[!to_play as usize], other);
+
+        let hidden1 = dense::<L1_INPUTS, L2>(&self.hidden1_weights, &self.hidden1_biases, &input);
+        let hidden2 = dense::<L2, L3>(&self.hidden2_weights, &self.hidden2_biases, &hidden1);
+
+        let output = self
+            .output_weights
+            .iter()
+            .zip(hidden2)
+            .map(|(weight, activation)| i32::from(*weight) * i32::from(activation))
+            .sum::<i32>()
+            + self.output_bias;
+
+        let score = (output / OUTPUT_SCALE).clamp(-i32::from(i16::MAX), i32::from(i16::MAX)) as i16;
+
+        match to_play {
+            PlayableTeam::White => score,
+            PlayableTeam::Black => -score,
         }
     }
-    pub fn update(
-        &mut self,
-        layer: Linear,
-        mut previous_accumulator: Self,
-        added_features: Vec<i64>,
-        removed_features: Vec<i64>,
-        to_play: PlayableTeam,
+    /// Evaluates `board` from white's perspective, computing any of its
+    /// accumulator that's out of date. Returns `None` if a king is missing
+    #[must_use]
+    pub fn evaluate_board(&self, board: &Board) -> Option<i16> {
+        if board.accumulator.computed == [true; 2] {
+            return Some(self.evaluate(&board.accumulator, board.to_play));
+        }
+
+        let mut accumulator = board.accumulator;
+        self.refresh_accumulator(&board.pieces, &mut accumulator);
+
+        if accumulator.computed == [true; 2] {
+            Some(self.evaluate(&accumulator, board.to_play))
+        } else {
+            None
+        }
+    }
+    /// Updates `accumulator` for a piece being added to or removed from
+    /// `position`, with the board's `pieces` as they are now. Moving a king invalidates its own side's
+    /// accumulator, which is refreshed by [`Network::refresh_accumulator`]
+    /// once the move has been made
+    fn update_accumulator(
+        &self,
+        pieces: &[[u64; 6]; 2],
+        accumulator: &mut Accumulator,
+        piece: Piece,
+        position: Position,
+        added: bool,
     ) {
-        match to_play {
-            PlayableTeam::White => {
-                for (new_white, old_white) in self
-                    .white_mut()
-                    .iter_mut()
-                    .zip(previous_accumulator.white_mut().iter())
-                {
-                    *new_white = *old_white
+        for perspective in PlayableTeam::teams() {
+            if piece.kind() == PieceKind::King {
+                if piece.team() == perspective.into() {
+                    accumulator.computed[perspective as usize] = false;
                 }
+                continue;
+            }
 
-                for feature in removed_features {
-                    for (index, white) in self.white_mut().iter_mut().enumerate() {
-                        *white -= layer.ws.i(feature).double_value(&[index as i64]);
-                    }
-                }
+            if !accumulator.computed[perspective as usize] {
+                continue;
+            }
 
-                for feature in added_features {
-                    for (index, white) in self.white_mut().iter_mut().enumerate() {
-                        *white += layer.ws.i(feature).double_value(&[index as i64]);
-                    }
-                }
+            let king =
+                Position::from_bitmap(pieces[perspective as usize][PieceKind::King as usize]);
+            let weights = self.feature_weights(feature_index(perspective, king, piece, position));
+            let values = &mut accumulator.values[perspective as usize];
+
+            if added {
+                add_weights(values, weights);
+            } else {
+                subtract_weights(values, weights);
+            }
+        }
+    }
+    /// Recomputes each side of `accumulator` that's out of date, as long as
+    /// that side still has a king among `pieces`
+    fn refresh_accumulator(&self, pieces: &[[u64; 6]; 2], accumulator: &mut Accumulator) {
+        for perspective in PlayableTeam::teams() {
+            if accumulator.computed[perspective as usize]
+                || pieces[perspective as usize][PieceKind::King as usize] == 0
+            {
+                continue;
             }
-            PlayableTeam::Black => {
-                for (new_black, old_black) in self
-                    .black_mut()
-                    .iter_mut()
-                    .zip(previous_accumulator.black_mut().iter())
-                {
-                    *new_black = *old_black
-                }
 
-                for feature in removed_features {
-                    for (index, black) in self.black_mut().iter_mut().enumerate() {
-                        *black -= layer.ws.i(feature).double_value(&[index as i64]);
-                    }
-                }
+            self.refresh(
+                pieces,
+                perspective,
+                &mut accumulator.values[perspective as usize],
+            );
+            accumulator.computed[perspective as usize] = true;
+        }
+    }
+    /// Computes the accumulator for `perspective` from scratch
+    fn refresh(&self, pieces: &[[u64; 6]; 2], perspective: PlayableTeam, values: &mut [i16; L1]) {
+        let king = Position::from_bitmap(pieces[perspective as usize][PieceKind::King as usize]);
 
-                for feature in added_features {
-                    for (index, black) in self.black_mut().iter_mut().enumerate() {
-                        *black += layer.ws.i(feature).double_value(&[index as i64]);
-                    }
+        values.copy_from_slice(&self.feature_biases);
+
+        for team in PlayableTeam::teams() {
+            for kind in PieceKind::kinds_no_king() {
+                let piece = Piece::new(team.into(), kind);
+                let mut bitmap = pieces[team as usize][kind as usize];
+
+                while bitmap != 0 {
+                    let position = Position::from_u8(pop_lsb(&mut bitmap) as u8);
+                    let index = feature_index(perspective, king, piece, position);
+
+                    add_weights(values, self.feature_weights(index));
                 }
             }
         }
     }
+    fn feature_weights(&self, index: usize) -> &[i16] {
+        &self.feature_weights[index * L1..(index + 1) * L1]
+    }
 }
 
-pub fn linear(layer: Linear, output: &mut [f64], input: &[f64]) -> usize {
-    if let Some(layer_bias) = layer.bs {
-        for (output, bias) in output.iter_mut().zip(layer_bias.iter::<f64>().unwrap()) {
-            *output = bias;
+/// Returns the installed network, if there is one
+#[must_use]
+pub fn network() -> Option<&'static Network> {
+    NETWORK.get()
+}
+
+impl Board {
+    /// Updates the accumulator with the installed network for a piece
+    /// being added to or removed from `position`
+    pub(crate) fn update_accumulator(&mut self, piece: Piece, position: Position, added: bool) {
+        if let Some(network) = network() {
+            network.update_accumulator(&self.pieces, &mut self.accumulator, piece, position, added);
         }
     }
-
-    for i in 0..layer.ws.numel() {
-        for j in 0..layer.ws.numel() {
-            output[j] += input[i] * layer.ws.i(i as i64).double_value(&[j as i64]);
+    /// Recomputes each side's accumulator that's out of date with the
+    /// installed network
+    pub(crate) fn refresh_accumulator(&mut self) {
+        if let Some(network) = network() {
+            network.refresh_accumulator(&self.pieces, &mut self.accumulator);
         }
     }
+    /// Evaluates the board with the installed network, from white's
+    /// perspective. Returns `None` if there's no network, or a king is
+    /// missing
+    #[must_use]
+    pub fn nnue_evaluation(&self) -> Option<i16> {
+        // Boards created before the network was installed won't have an
+        // accumulator yet, so it's computed if needed
+        network()?.evaluate_board(self)
+    }
+}
+
+/// Returns the index of `piece` on `position`, from `perspective`'s point
+/// of view with its king on `king`
+fn feature_index(
+    perspective: PlayableTeam,
+    king: Position,
+    piece: Piece,
+    position: Position,
+) -> usize {
+    let (king, square) = match perspective {
+        PlayableTeam::White => (king.index(), position.index()),
+        PlayableTeam::Black => (king.index() ^ 56, position.index() ^ 56),
+    };
+    let colour = if piece.team() == Team::from(perspective) {
+        0
+    } else {
+        1
+    };
+
+    king as usize * 640 + (piece.kind() as usize * 2 + colour) * 64 + square as usize
+}
+
+fn add_weights(values: &mut [i16; L1], weights: &[i16]) {
+    for (value, weight) in values.iter_mut().zip(weights) {
+        *value += weight;
+    }
+}
+
+fn subtract_weights(values: &mut [i16; L1], weights: &[i16]) {
+    for (value, weight) in values.iter_mut().zip(weights) {
+        *value -= weight;
+    }
+}
+
+fn clipped_relu(input: &[i16; L1], output: &mut [u8]) {
+    for (output, input) in output.iter_mut().zip(input) {
+        *output = (*input).clamp(0, ACTIVATION_MAX as i16) as u8;
+    }
+}
+
+/// A fully connected layer followed by a clipped ReLU. The weights are
+/// stored one output neuron at a time, so each row is a contiguous dot
+/// product the compiler can vectorise
+fn dense<const IN: usize, const OUT: usize>(
+    weights: &[i8],
+    biases: &[i32],
+    input: &[u8; IN],
+) -> [u8; OUT] {
+    let mut output = [0; OUT];
 
-    layer.ws.numel()
+    for ((output, row), bias) in output.iter_mut().zip(weights.chunks_exact(IN)).zip(biases) {
+        let sum = row
+            .iter()
+            .zip(input)
+            .map(|(weight, activation)| i32::from(*weight) * i32::from(*activation))
+            .sum::<i32>()
+            + bias;
+
+        *output = (sum >> WEIGHT_SHIFT).clamp(0, ACTIVATION_MAX) as u8;
+    }
+
+    output
+}
+
+fn read_array<const N: usize>(reader: &mut impl Read) -> io::Result<[u8; N]> {
+    let mut bytes = [0; N];
+    reader.read_exact(&mut bytes)?;
+
+    Ok(bytes)
 }
 
-pub fn crelu(output: &mut [f64], input: &[f64]) {
-    for (output, input) in output.iter_mut().zip(input.iter()) {
-        let max = if *input > 0. { *input } else { 0. };
-        *output = if max < 1. { max } else { 1. };
+fn read_values<T, const N: usize>(
+    reader: &mut impl Read,
+    count: usize,
+    from_le_bytes: fn([u8; N]) -> T,
+) -> io::Result<Vec<T>> {
+    let mut bytes = vec![0; count * N];
+    reader.read_exact(&mut bytes)?;
+
+    Ok(bytes
+        .chunks_exact(N)
+        .map(|chunk| {
+            let mut array = [0; N];
+            array.copy_from_slice(chunk);
+
+            from_le_bytes(array)
+        })
+        .collect())
+}
+
+#[cfg(test)]
+fn random_network(seed: u64) -> Network {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    let mut rng = StdRng::seed_from_u64(seed);
+
+    Network {
+        feature_weights: (0..FEATURES * L1).map(|_| rng.gen_range(-8..8)).collect(),
+        feature_biases: (0..L1).map(|_| rng.gen_range(0..64)).collect(),
+        hidden1_weights: (0..L2 * L1_INPUTS).map(|_| rng.gen_range(-4..4)).collect(),
+        hidden1_biases: (0..L2).map(|_| rng.gen_range(-64..64)).collect(),
+        hidden2_weights: (0..L3 * L2).map(|_| rng.gen_range(-16..16)).collect(),
+        hidden2_biases: (0..L3).map(|_| rng.gen_range(-64..64)).collect(),
+        output_weights: (0..L3).map(|_| rng.gen_range(-64..64)).collect(),
+        output_bias: rng.gen_range(-64..64),
     }
 }
+
+#[test]
+fn network_round_trip() {
+    let network = random_network(1);
+    let mut bytes = Vec::new();
+
+    network.write_to(&mut bytes).unwrap();
+
+    assert_eq!(
+        bytes.len(),
+        8 + FEATURES * L1 * 2 + L1 * 2 + L2 * L1_INPUTS + L2 * 4 + L3 * L2 + L3 * 4 + L3 + 4
+    );
+    assert_eq!(Network::from_reader(&bytes[..]).unwrap(), network);
+    assert!(Network::from_reader(&bytes[1..]).is_err());
+}
+
+#[test]
+fn accumulator_is_incremental() {
+    use crate::MoveGen;
+
+    let network = random_network(1);
+    let refreshed = |board: &Board| {
+        let mut accumulator = Accumulator::EMPTY;
+        network.refresh_accumulator(&board.pieces, &mut accumulator);
+
+        accumulator
+    };
+
+    for fen in [
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w - - 0 1",
+        "r2q1rk1/1p3p1p/1b4p1/pPp2b2/3pn1P1/P2Q4/B1P1NP1P/R1B2RK1 b - - 0 30",
+        "1n2k3/P7/8/8/8/8/7p/4K1N1 w - - 0 40",
+    ] {
+        let mut board = Board::from_fen(fen).unwrap();
+        let original = board.clone();
+        let before = refreshed(&original);
+
+        assert_eq!(before.computed, [true; 2]);
+
+        for possible_move in MoveGen::new(&original).into_inner() {
+            let undo = board.make_move_mut(&possible_move);
+
+            // Replay the move's changes square by square, removing pieces
+            // before adding them, as making the move does
+            let mut accumulator = before;
+            let changed: Vec<_> = Position::positions()
+                .filter(|&position| original.piece_at(position) != board.piece_at(position))
+                .collect();
+
+            for &position in &changed {
+                let piece = original.piece_at(position);
+
+                if piece.kind() != PieceKind::None {
+                    network.update_accumulator(
+                        &board.pieces,
+                        &mut accumulator,
+                        piece,
+                        position,
+                        false,
+                    );
+                }
+            }
+
+            for &position in &changed {
+                let piece = board.piece_at(position);
+
+                if piece.kind() != PieceKind::None {
+                    network.update_accumulator(
+                        &board.pieces,
+                        &mut accumulator,
+                        piece,
+                        position,
+                        true,
+                    );
+                }
+            }
+
+            network.refresh_accumulator(&board.pieces, &mut accumulator);
+
+            assert_eq!(accumulator, refreshed(&board));
+
+            board.unmake_move(&possible_move, undo);
+
+            assert_eq!(board, original);
+        }
+    }
+}
+
+#[test]
+fn evaluation_is_colour_symmetric() {
+    let network = random_network(1);
+
+    let white = Board::from_fen("4k3/pp3p1p/8/3N4/4P3/8/8/4K3 w - - 0 1").unwrap();
+    let black = Board::from_fen("4k3/8/8/4p3/3n4/8/PP3P1P/4K3 b - - 0 1").unwrap();
+
+    assert_eq!(
+        network.evaluate_board(&white),
+        network.evaluate_board(&black).map(|score| -score)
+    );
+
+    // Without a king there's nothing to index the pieces by
+    let kingless = Board::from_fen("8/pp3p1p/8/3N4/4P3/8/8/4K3 w - - 0 1").unwrap();
+
+    assert_eq!(network.evaluate_board(&kingless), None);
+}
@@ -1,79 +1,456 @@
-use crate::{move_gen::Move, piece::PieceKind, PlayableTeam};
+use core::fmt::{Display, Formatter};
 
+use crate::{
+    move_gen::{Move, MoveParseError},
+    piece::PieceKind,
+    Board, PlayableTeam, Position,
+};
+
+/// The line width movetext is wrapped to
+const LINE_WIDTH: usize = 80;
+
+/// The result of a game, as written in a PGN's `Result` tag and at the end
+/// of its movetext
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GameResult {
+    WhiteWins,
+    BlackWins,
+    Draw,
+    /// The game is unfinished, or its result is unknown
+    Unknown,
+}
+
+impl GameResult {
+    /// The result of a game won by `team`
+    #[must_use]
+    pub const fn win_for(team: PlayableTeam) -> Self {
+        match team {
+            PlayableTeam::White => Self::WhiteWins,
+            PlayableTeam::Black => Self::BlackWins,
+        }
+    }
+}
+
+impl Display for GameResult {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Self::WhiteWins => "1-0",
+                Self::BlackWins => "0-1",
+                Self::Draw => "1/2-1/2",
+                Self::Unknown => "*",
+            }
+        )
+    }
+}
+
+/// A single move in the movetext, with its annotations
+struct PgnMove {
+    index: usize,
+    to_play: PlayableTeam,
+    san: String,
+    nags: Vec<u8>,
+    comment: Option<String>,
+}
+
+/// A game writer, producing a PGN from moves added one at a time. Use
+/// [`Pgn::finish`] for just the movetext, or the [`Display`] implementation
+/// for the whole game, including its tag pairs and result
 pub struct Pgn {
     index: usize,
-    moves: String,
     to_play: PlayableTeam,
+    tags: Vec<(String, String)>,
+    moves: Vec<PgnMove>,
+    result: GameResult,
 }
 
 impl Pgn {
+    /// Creates a game starting from the initial position
     #[must_use]
-    pub const fn new() -> Self {
+    pub fn new() -> Self {
         Self {
             index: 1,
-            moves: String::new(),
             to_play: PlayableTeam::White,
+            tags: ["Event", "Site", "Date", "Round", "White", "Black"]
+                .into_iter()
+                .map(|name| {
+                    let value = if name == "Date" { "????.??.??" } else { "?" };
+                    (String::from(name), String::from(value))
+                })
+                .collect(),
+            moves: Vec::new(),
+            result: GameResult::Unknown,
         }
     }
-    pub fn add_move(&mut self, played_move: &Move) {
-        match self.to_play {
-            PlayableTeam::White => {
-                self.moves = format!("{} {}. {}", self.moves, self.index, played_move.write());
-                self.to_play = !self.to_play;
-            }
-            PlayableTeam::Black => {
-                self.moves = format!("{} {}", self.moves, played_move.write());
-                self.to_play = !self.to_play;
-                self.index += 1;
-            }
+    /// Creates a game starting from `board`. If it isn't the initial
+    /// position, the `SetUp` and `FEN` tags are added
+    #[must_use]
+    pub fn from_board(board: &Board) -> Self {
+        let mut pgn = Self::new();
+
+        let initial = Board::new();
+
+        if board.pieces != initial.pieces || board.to_play != initial.to_play {
+            pgn.set_tag("SetUp", "1");
+            pgn.set_tag("FEN", &board.to_fen());
+        }
+
+        pgn.index = usize::from(board.turn).max(1);
+        pgn.to_play = board.to_play;
+
+        pgn
+    }
+    /// Sets the value of a tag pair, replacing any previous value. The
+    /// `Result` tag is always taken from [`Pgn::set_result`]
+    pub fn set_tag(&mut self, name: &str, value: &str) {
+        if let Some((_, old)) = self.tags.iter_mut().find(|(tag, _)| tag == name) {
+            *old = String::from(value);
+        } else {
+            self.tags.push((String::from(name), String::from(value)));
         }
     }
+    /// Sets the result of the game
+    pub fn set_result(&mut self, result: GameResult) {
+        self.result = result;
+    }
+    /// Adds a move to the game, where `board` is the position the move
+    /// was played from
+    pub fn add_move(&mut self, board: &Board, played_move: &Move) {
+        self.moves.push(PgnMove {
+            index: self.index,
+            to_play: self.to_play,
+            san: board.san(played_move),
+            nags: Vec::new(),
+            comment: None,
+        });
+
+        if self.to_play == PlayableTeam::Black {
+            self.index += 1;
+        }
+        self.to_play = !self.to_play;
+    }
+    /// Adds a comment to the last move played
+    pub fn add_comment(&mut self, comment: &str) {
+        if let Some(last) = self.moves.last_mut() {
+            let comment = comment.replace('}', ")");
+
+            last.comment = Some(match last.comment.take() {
+                Some(old) => format!("{old} {comment}"),
+                None => comment,
+            });
+        }
+    }
+    /// Adds an engine evaluation, in centipawns, and the depth it was
+    /// searched to as a comment to the last move played
+    pub fn add_evaluation(&mut self, evaluation: i16, depth: u8) {
+        self.add_comment(&format!("{:+.2}/{depth}", f64::from(evaluation) / 100.));
+    }
+    /// Adds a numeric annotation glyph, such as `1` for "good move" or
+    /// `4` for "blunder", to the last move played
+    pub fn add_nag(&mut self, nag: u8) {
+        if let Some(last) = self.moves.last_mut() {
+            last.nags.push(nag);
+        }
+    }
+    /// Returns the movetext of the game, wrapped to 80 columns, without
+    /// the result terminator
     #[must_use]
     pub fn finish(self) -> String {
-        self.moves
+        wrap(self.movetext_tokens())
+    }
+    fn movetext_tokens(&self) -> Vec<String> {
+        let mut tokens = Vec::new();
+        let mut needs_index = true;
+
+        for played_move in &self.moves {
+            match played_move.to_play {
+                PlayableTeam::White => tokens.push(format!("{}.", played_move.index)),
+                PlayableTeam::Black if needs_index => {
+                    tokens.push(format!("{}...", played_move.index));
+                }
+                PlayableTeam::Black => {}
+            }
+
+            tokens.push(played_move.san.clone());
+            tokens.extend(played_move.nags.iter().map(|nag| format!("${nag}")));
+
+            needs_index = if let Some(comment) = &played_move.comment {
+                let comment = format!("{{{comment}}}");
+                tokens.extend(comment.split_whitespace().map(String::from));
+                true
+            } else {
+                false
+            };
+        }
+
+        tokens
     }
 }
 
-impl Move {
-    fn write(&self) -> String {
-        let (x, y) = self.to().to_uci();
-        let (from_x, _) = self.from().to_uci();
+impl Default for Pgn {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
-        let uci = match piece_to_uci(self.moved_piece_kind()) {
-            Ok(t) => t,
-            Err(_) => panic!(),
-        };
+impl Display for Pgn {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        let (roster, others) = self.tags.split_at(6);
+
+        for (name, value) in roster {
+            write_tag(f, name, value)?;
+        }
+        write_tag(f, "Result", &self.result.to_string())?;
+        for (name, value) in others.iter().filter(|(name, _)| name != "Result") {
+            write_tag(f, name, value)?;
+        }
+
+        let mut tokens = self.movetext_tokens();
+        tokens.push(self.result.to_string());
+
+        writeln!(f)?;
+        writeln!(f, "{}", wrap(tokens))
+    }
+}
+
+fn write_tag(f: &mut Formatter<'_>, name: &str, value: &str) -> core::fmt::Result {
+    writeln!(
+        f,
+        "[{name} \"{}\"]",
+        value.replace('\\', "\\\\").replace('"', "\\\"")
+    )
+}
+
+/// Joins `tokens` with spaces, starting a new line whenever the next token
+/// would go past [`LINE_WIDTH`]
+fn wrap(tokens: Vec<String>) -> String {
+    let mut text = String::new();
+    let mut line_length = 0;
+
+    for token in tokens {
+        if line_length != 0 && line_length + 1 + token.len() > LINE_WIDTH {
+            text.push('\n');
+            line_length = 0;
+        } else if line_length != 0 {
+            text.push(' ');
+            line_length += 1;
+        }
+
+        line_length += token.len();
+        text.push_str(&token);
+    }
+
+    text
+}
+
+impl Board {
+    /// Writes a [`Move`] in standard algebraic notation, using the
+    /// position it's played from to disambiguate it, and to mark checks
+    /// and mates
+    #[must_use]
+    pub fn san(&self, played_move: &Move) -> String {
+        let mut san = self.san_without_suffix(played_move);
+
+        if let Some(board) = self.make_move(played_move) {
+            if board.in_check(board.to_play) {
+                san.push(if board.legal_moves().is_empty() {
+                    '#'
+                } else {
+                    '+'
+                });
+            }
+        }
+
+        san
+    }
+    fn san_without_suffix(&self, played_move: &Move) -> String {
+        let (from, to) = played_move.from_to();
+        let (from_x, from_y) = from.to_uci();
+        let (to_x, to_y) = to.to_uci();
+
+        let kind = played_move.moved_piece_kind();
+        let is_capture = played_move.captured_piece_kind() != PieceKind::None;
+
+        if kind == PieceKind::King && from.x().max(to.x()) - from.x().min(to.x()) == 2 {
+            return if to.x() > from.x() {
+                String::from("O-O")
+            } else {
+                String::from("O-O-O")
+            };
+        }
+
+        let mut san = String::new();
+
+        if let Some(identifier) = piece_to_san(kind) {
+            san.push(identifier);
+
+            let others: Vec<Position> = self
+                .legal_moves()
+                .into_iter()
+                .filter(|other| {
+                    other.moved_piece_kind() == kind && other.to() == to && other.from() != from
+                })
+                .map(|other| other.from())
+                .collect();
 
-        if self.captured_piece_kind() == PieceKind::None {
-            uci.map_or_else(
-                || format!("{}{}", x, y),
-                |piece_identifier| format!("{}{}{}{}", piece_identifier, from_x, x, y),
-            )
+            if !others.is_empty() {
+                if others.iter().all(|other| other.x() != from.x()) {
+                    san.push(from_x);
+                } else if others.iter().all(|other| other.y() != from.y()) {
+                    san.push(from_y);
+                } else {
+                    san.push(from_x);
+                    san.push(from_y);
+                }
+            }
+        } else if is_capture {
+            san.push(from_x);
+        }
+
+        if is_capture {
+            san.push('x');
+        }
+
+        san.push(to_x);
+        san.push(to_y);
+
+        if played_move.flags().is_promotion() {
+            san.push_str("=Q");
+        }
+
+        san
+    }
+    /// Parses a move written in standard algebraic notation, matching it
+    /// against the legal moves in the position. Check, mate and
+    /// annotation suffixes are ignored. Pawns can only be promoted to
+    /// queens, so underpromotions are illegal
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`MoveParseError`] if the text isn't valid SAN, if no
+    /// legal move matches it, or if more than one legal move does
+    pub fn parse_san(&self, san: &str) -> Result<Move, MoveParseError> {
+        let san = san.trim().trim_end_matches(|c| matches!(c, '+' | '#' | '!' | '?'));
+
+        if matches!(san, "O-O" | "0-0" | "O-O-O" | "0-0-0") {
+            let kingside = san.len() == 3;
+
+            return self.single_match(|possible_move| {
+                let (from, to) = possible_move.from_to();
+
+                possible_move.moved_piece_kind() == PieceKind::King
+                    && from.y() == to.y()
+                    && if kingside {
+                        to.x() == from.x() + 2
+                    } else {
+                        to.x() + 2 == from.x()
+                    }
+            });
+        }
+
+        let mut chars: Vec<char> = san.chars().collect();
+
+        let promotion = if let Some(index) = chars.iter().position(|&c| c == '=') {
+            let promotion = chars
+                .get(index + 1)
+                .copied()
+                .and_then(san_to_piece)
+                .ok_or(MoveParseError::InvalidSyntax)?;
+
+            if chars.len() != index + 2 || promotion == PieceKind::Pawn {
+                return Err(MoveParseError::InvalidSyntax);
+            }
+
+            // Pawns are always promoted to queens
+            if promotion != PieceKind::Queen {
+                return Err(MoveParseError::IllegalMove);
+            }
+
+            chars.truncate(index);
+            true
         } else {
-            uci.map_or_else(
-                || format!("{}x{}{}", from_x, x, y),
-                |piece_identifier| format!("{}{}x{}{}", piece_identifier, from_x, x, y),
-            )
+            false
+        };
+
+        let kind = match chars.first().copied().and_then(san_to_piece) {
+            Some(kind) => {
+                chars.remove(0);
+                kind
+            }
+            None => PieceKind::Pawn,
+        };
+
+        if chars.len() < 2 {
+            return Err(MoveParseError::InvalidSyntax);
+        }
+
+        let to: String = chars.split_off(chars.len() - 2).into_iter().collect();
+        let to = Position::from_uci(&to).ok_or(MoveParseError::InvalidSyntax)?;
+
+        let capture = chars.last() == Some(&'x');
+        if capture {
+            chars.pop();
+        }
+
+        let mut from_x = None;
+        let mut from_y = None;
+
+        for c in chars {
+            match c {
+                'a'..='h' if from_x.is_none() && from_y.is_none() => {
+                    from_x = Some(c as u8 - b'a');
+                }
+                '1'..='8' if from_y.is_none() => from_y = Some(c as u8 - b'1'),
+                _ => return Err(MoveParseError::InvalidSyntax),
+            }
+        }
+
+        self.single_match(|possible_move| {
+            possible_move.moved_piece_kind() == kind
+                && possible_move.to() == to
+                && from_x.map_or(true, |x| possible_move.from().x() == x)
+                && from_y.map_or(true, |y| possible_move.from().y() == y)
+                && (!capture || possible_move.captured_piece_kind() != PieceKind::None)
+                && possible_move.flags().is_promotion() == promotion
+        })
+    }
+    fn single_match(&self, predicate: impl Fn(&Move) -> bool) -> Result<Move, MoveParseError> {
+        let mut matching = self.legal_moves().into_iter().filter(predicate);
+
+        match (matching.next(), matching.next()) {
+            (Some(found), None) => Ok(found),
+            (None, _) => Err(MoveParseError::IllegalMove),
+            (Some(_), Some(_)) => Err(MoveParseError::AmbiguousMove),
         }
     }
 }
 
-const fn piece_to_uci(kind: PieceKind) -> Result<Option<char>, ()> {
-    Ok(match kind {
-        PieceKind::None => return Err(()),
-        PieceKind::Pawn => None,
+const fn piece_to_san(kind: PieceKind) -> Option<char> {
+    match kind {
+        PieceKind::Pawn | PieceKind::None => None,
         PieceKind::Rook => Some('R'),
         PieceKind::Knight => Some('N'),
         PieceKind::Bishop => Some('B'),
         PieceKind::Queen => Some('Q'),
         PieceKind::King => Some('K'),
+    }
+}
+
+const fn san_to_piece(c: char) -> Option<PieceKind> {
+    Some(match c {
+        'R' => PieceKind::Rook,
+        'N' => PieceKind::Knight,
+        'B' => PieceKind::Bishop,
+        'Q' => PieceKind::Queen,
+        'K' => PieceKind::King,
+        _ => return None,
     })
 }
 
 #[test]
 fn pgn_gen() {
-    use crate::{Board, Position};
-
     let mut board = Board::new();
 
     let mut pgn = Pgn::new();
@@ -90,7 +467,7 @@ fn pgn_gen() {
         board.kind_at(board.to_play(), to),
     );
 
-    pgn.add_move(&played_move);
+    pgn.add_move(&board, &played_move);
 
     board = board.make_move(&played_move).unwrap();
 
@@ -106,7 +483,108 @@ fn pgn_gen() {
         board.kind_at(board.to_play(), to),
     );
 
-    pgn.add_move(&played_move);
+    pgn.add_move(&board, &played_move);
 
     assert_eq!("1. e4 e5", pgn.finish().trim());
 }
+
+#[test]
+fn pgn_export() {
+    let mut board =
+        Board::from_fen("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R b - - 0 3")
+            .unwrap();
+
+    let mut pgn = Pgn::from_board(&board);
+    pgn.set_tag("White", "citron");
+    pgn.set_tag("Black", "Human \"Tester\"");
+
+    for (san, evaluation) in [("Nf6", -15), ("Nc3", 20), ("Bc5", -10)] {
+        let played_move = board.parse_san(san).unwrap();
+        pgn.add_move(&board, &played_move);
+        pgn.add_evaluation(evaluation, 8);
+        board = board.make_move(&played_move).unwrap();
+    }
+    pgn.add_nag(1);
+    pgn.set_result(GameResult::Draw);
+
+    let expected = "[Event \"?\"]
+[Site \"?\"]
+[Date \"????.??.??\"]
+[Round \"?\"]
+[White \"citron\"]
+[Black \"Human \\\"Tester\\\"\"]
+[Result \"1/2-1/2\"]
+[SetUp \"1\"]
+[FEN \"r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R b - - 0 3\"]
+
+3... Nf6 {-0.15/8} 4. Nc3 {+0.20/8} 4... Bc5 $1 {-0.10/8} 1/2-1/2
+";
+
+    assert_eq!(pgn.to_string(), expected);
+}
+
+#[test]
+fn pgn_line_wrapping() {
+    let mut board = Board::new();
+    let mut pgn = Pgn::new();
+
+    for _ in 0..10 {
+        for san in ["Nf3", "Nf6", "Ng1", "Ng8"] {
+            let played_move = board.parse_san(san).unwrap();
+            pgn.add_move(&board, &played_move);
+            board = board.make_move(&played_move).unwrap();
+        }
+    }
+
+    let movetext = pgn.finish();
+
+    assert!(movetext.lines().count() > 1);
+    assert!(movetext.lines().all(|line| line.len() <= LINE_WIDTH));
+    assert!(movetext.starts_with("1. Nf3 Nf6 2. Ng1 Ng8 3. Nf3"));
+    assert!(movetext.ends_with("20. Ng1 Ng8"));
+}
+
+#[test]
+fn san_disambiguation() {
+    let board = Board::from_fen("4k3/8/8/8/8/8/8/R1N1K1NR w - - 0 1").unwrap();
+
+    let knight = board.parse_san("Ne2").unwrap_err();
+    assert_eq!(knight, MoveParseError::AmbiguousMove);
+
+    let knight = board.parse_san("Nge2").unwrap();
+    assert_eq!(knight.from(), Position::from_uci("g1").unwrap());
+    assert_eq!(board.san(&knight), "Nge2");
+
+    let knight = board.parse_san("Nf3").unwrap();
+    assert_eq!(board.san(&knight), "Nf3");
+
+    let board = Board::from_fen("4k3/8/8/R7/8/8/8/R3K3 w - - 0 1").unwrap();
+
+    let rook = board.parse_san("R1a3").unwrap();
+    assert_eq!(rook.from(), Position::from_uci("a1").unwrap());
+    assert_eq!(board.san(&rook), "R1a3");
+}
+
+#[test]
+fn san_check_mate_and_promotion() {
+    let board = Board::from_fen("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1").unwrap();
+
+    let mate = board.parse_san("Ra8#").unwrap();
+    assert_eq!(board.san(&mate), "Ra8#");
+
+    let board = Board::from_fen("4k3/8/8/8/8/8/8/R3K3 w - - 0 1").unwrap();
+
+    let check = board.parse_san("Ra8").unwrap();
+    assert_eq!(board.san(&check), "Ra8+");
+
+    let board = Board::from_fen("1n2k3/P7/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+
+    let promotion = board.parse_san("axb8=Q").unwrap();
+    assert_eq!(board.san(&promotion), "axb8=Q+");
+    assert_eq!(board.parse_san("a8=Q").map(|m| board.san(&m)).unwrap(), "a8=Q");
+    assert_eq!(board.parse_san("axb8=N"), Err(MoveParseError::IllegalMove));
+    assert_eq!(board.parse_san("a8=R"), Err(MoveParseError::IllegalMove));
+    assert_eq!(board.parse_san("a8=B+"), Err(MoveParseError::IllegalMove));
+    assert_eq!(board.parse_san("Nf3"), Err(MoveParseError::IllegalMove));
+    assert_eq!(board.parse_san("Zz9"), Err(MoveParseError::InvalidSyntax));
+}
@@ -1,11 +1,11 @@
 use core::fmt::Display;
 
-use crate::Team;
+use crate::{evaluation::eval_params, Team};
 
 pub const PAWN_VALUE: i16 = 100;
-const ROOK_VALUE: i16 = 5 * PAWN_VALUE;
-const KNIGHT_VALUE: i16 = 3 * PAWN_VALUE;
-const BISHOP_VALUE: i16 = 3 * PAWN_VALUE + 25;
+pub const ROOK_VALUE: i16 = 5 * PAWN_VALUE;
+pub const KNIGHT_VALUE: i16 = 3 * PAWN_VALUE;
+pub const BISHOP_VALUE: i16 = 3 * PAWN_VALUE + 25;
 pub const QUEEN_VALUE: i16 = 9 * PAWN_VALUE;
 pub const KING_VALUE: i16 = 50 * PAWN_VALUE;
 
@@ -89,6 +89,29 @@ impl Piece {
     }
 }
 
+impl Piece {
+    /// Returns the character used for the piece in a FEN string, with
+    /// white pieces in uppercase and black pieces in lowercase
+    #[must_use]
+    pub const fn fen_char(&self) -> char {
+        match self {
+            Self::WhitePawn => 'P',
+            Self::WhiteRook => 'R',
+            Self::WhiteKnight => 'N',
+            Self::WhiteBishop => 'B',
+            Self::WhiteQueen => 'Q',
+            Self::WhiteKing => 'K',
+            Self::BlackPawn => 'p',
+            Self::BlackRook => 'r',
+            Self::BlackKnight => 'n',
+            Self::BlackBishop => 'b',
+            Self::BlackQueen => 'q',
+            Self::BlackKing => 'k',
+            Self::Empty => ' ',
+        }
+    }
+}
+
 impl Default for Piece {
     fn default() -> Self {
         Self::Empty
@@ -101,18 +124,18 @@ impl Display for Piece {
             f,
             "{}",
             match self {
-                Self::WhitePawn => "♟",
-                Self::WhiteRook => "♜",
-                Self::WhiteKnight => "♞",
-                Self::WhiteBishop => "♝",
-                Self::WhiteQueen => "♛",
-                Self::WhiteKing => "♚",
-                Self::BlackPawn => "♙",
-                Self::BlackRook => "♖",
-                Self::BlackKnight => "♘",
-                Self::BlackBishop => "♗",
-                Self::BlackQueen => "♕",
-                Self::BlackKing => "♔",
+                Self::WhitePawn => "♙",
+                Self::WhiteRook => "♖",
+                Self::WhiteKnight => "♘",
+                Self::WhiteBishop => "♗",
+                Self::WhiteQueen => "♕",
+                Self::WhiteKing => "♔",
+                Self::BlackPawn => "♟",
+                Self::BlackRook => "♜",
+                Self::BlackKnight => "♞",
+                Self::BlackBishop => "♝",
+                Self::BlackQueen => "♛",
+                Self::BlackKing => "♚",
                 Self::Empty => " ",
             }
         )
@@ -167,17 +190,24 @@ impl PieceKind {
             Self::King,
         ]
     }
-    /// Returns the value of the the variant
+    /// Returns the value of the the variant, from the installed
+    /// [`EvalParams`](crate::EvalParams)
+    #[must_use]
+    pub fn value(&self) -> i16 {
+        eval_params().piece_value(*self)
+    }
+}
+
+impl PieceKind {
+    /// Returns how much the piece counts towards the game phase. Pawns and
+    /// kings don't count, so the phase only falls as pieces are traded
     #[must_use]
-    pub const fn value(&self) -> i16 {
+    pub const fn phase(&self) -> i16 {
         match self {
-            PieceKind::Pawn => PAWN_VALUE,
-            PieceKind::Rook => ROOK_VALUE,
-            PieceKind::Knight => KNIGHT_VALUE,
-            PieceKind::Bishop => BISHOP_VALUE,
-            PieceKind::Queen => QUEEN_VALUE,
-            PieceKind::King => KING_VALUE,
-            PieceKind::None => 0,
+            PieceKind::Knight | PieceKind::Bishop => 1,
+            PieceKind::Rook => 2,
+            PieceKind::Queen => 4,
+            PieceKind::Pawn | PieceKind::King | PieceKind::None => 0,
         }
     }
 }
@@ -87,17 +87,56 @@ impl Position {
     pub fn positions() -> impl Iterator<Item = Self> {
         (0..64).map(Self)
     }
+    /// Returns the lowest square set in `bitmap`
+    ///
+    /// # Panics
+    ///
+    /// Panics in debug builds if `bitmap` is empty
     #[must_use]
-    pub fn from_bitmap(bitmap: u64) -> Self {
-        if bitmap == 0 {
-            println!("Wtaf");
-        }
-        Self(crate::magic::bitscan_forward(bitmap) as u8)
+    pub const fn from_bitmap(bitmap: u64) -> Self {
+        debug_assert!(bitmap != 0, "No square in an empty bitmap");
+
+        Self(bitmap.trailing_zeros() as u8)
     }
     #[must_use]
     pub const fn to_bitmap(&self) -> u64 {
         1 << self.0
     }
+    /// Returns the number of king moves needed to get between two positions
+    #[must_use]
+    pub const fn distance(&self, other: &Self) -> u8 {
+        let x = difference(self.x(), other.x());
+        let y = difference(self.y(), other.y());
+
+        if x > y {
+            x
+        } else {
+            y
+        }
+    }
+    /// Returns the number of rook moves, one square at a time, needed to get
+    /// between two positions
+    #[must_use]
+    pub const fn manhattan_distance(&self, other: &Self) -> u8 {
+        difference(self.x(), other.x()) + difference(self.y(), other.y())
+    }
+    /// Returns the manhattan distance to the nearest of the four centre
+    /// squares
+    #[must_use]
+    pub const fn centre_distance(&self) -> u8 {
+        let x = if self.x() < 4 { 3 - self.x() } else { self.x() - 4 };
+        let y = if self.y() < 4 { 3 - self.y() } else { self.y() - 4 };
+
+        x + y
+    }
+}
+
+const fn difference(a: u8, b: u8) -> u8 {
+    if a > b {
+        a - b
+    } else {
+        b - a
+    }
 }
 
 impl Display for Position {
@@ -154,3 +193,19 @@ fn uci_test() {
     let position = Position::from_u8(9);
     assert_eq!(position.to_uci(), ('b', '2'));
 }
+
+#[test]
+fn distance_test() {
+    let a1 = Position::from_uci("a1").unwrap();
+    let h8 = Position::from_uci("h8").unwrap();
+    let c2 = Position::from_uci("c2").unwrap();
+
+    assert_eq!(a1.distance(&h8), 7);
+    assert_eq!(a1.manhattan_distance(&h8), 14);
+    assert_eq!(a1.distance(&c2), 2);
+    assert_eq!(c2.manhattan_distance(&a1), 3);
+
+    assert_eq!(a1.centre_distance(), 6);
+    assert_eq!(Position::from_uci("e4").unwrap().centre_distance(), 0);
+    assert_eq!(Position::from_uci("f6").unwrap().centre_distance(), 2);
+}
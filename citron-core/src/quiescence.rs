@@ -1,14 +1,13 @@
 use crate::{
+    evaluation::scoped_params,
     move_ordering::quiescence_move_ordering,
-    piece::{PieceKind, KING_VALUE, PAWN_VALUE},
+    piece::{PieceKind, KING_VALUE},
     Board, MoveGen, PlayableTeam,
 };
 
-const DELTA: i16 = 2 * PAWN_VALUE;
-
 impl Board {
     #[must_use]
-    pub fn quiesce(&self, mut alpha: i16, beta: i16) -> i16 {
+    pub fn quiesce(&mut self, mut alpha: i16, beta: i16) -> i16 {
         let stand_pat = if self.to_play == PlayableTeam::White {
             self.static_evaluation()
         } else {
@@ -35,12 +34,19 @@ impl Board {
                     return Err(KING_VALUE);
                 }
 
+                let params = scoped_params();
+
                 if self.in_endgame()
-                    || stand_pat + DELTA + possible_move.captured_piece_kind().value() > alpha
+                    || stand_pat
+                        + params.delta
+                        + params.piece_value(possible_move.captured_piece_kind())
+                        > alpha
                 {
-                    let possible_board = self.make_move(&possible_move).unwrap();
+                    let undo = self.make_move_mut(&possible_move);
+
+                    let score = -self.quiesce(-beta, -alpha);
 
-                    let score = -possible_board.quiesce(-beta, -alpha);
+                    self.unmake_move(&possible_move, undo);
 
                     if score > alpha {
                         if score >= beta {
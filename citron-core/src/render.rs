@@ -0,0 +1,304 @@
+//! Draws boards as text, for terminals, and as SVG diagrams.
+
+use core::fmt::Write;
+
+use crate::{piece::Piece, Board, Position, Team};
+
+/// The ANSI escape codes used to highlight squares, and to reset them
+const HIGHLIGHT: &str = "\x1b[30;43m";
+const RESET: &str = "\x1b[0m";
+
+/// The size of a square in SVG diagrams
+const SVG_SQUARE: u32 = 45;
+/// The space left around SVG diagrams for coordinates
+const SVG_MARGIN: u32 = 20;
+const SVG_LIGHT: &str = "#f0d9b5";
+const SVG_DARK: &str = "#b58863";
+const SVG_HIGHLIGHT: &str = "#cdd26a";
+
+/// The characters pieces are drawn with
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PieceSet {
+    /// FEN letters, uppercase for white and lowercase for black
+    Ascii,
+    /// Chess symbols, outlined for white and filled for black
+    Unicode,
+}
+
+impl PieceSet {
+    /// The character for `piece`, or `.` for an empty square
+    #[must_use]
+    pub fn symbol(self, piece: Piece) -> char {
+        match (self, piece) {
+            (_, Piece::Empty) => '.',
+            (Self::Ascii, piece) => piece.fen_char(),
+            (Self::Unicode, piece) => piece.to_string().chars().next().unwrap_or('.'),
+        }
+    }
+}
+
+/// How a board is drawn as text. The default is Unicode pieces, from
+/// white's side, with coordinates
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BoardRenderer {
+    pub piece_set: PieceSet,
+    /// Whether the ranks and files are labelled
+    pub coordinates: bool,
+    /// Whether the board is drawn from black's side
+    pub flipped: bool,
+    /// The squares of the last move, highlighted with ANSI colours
+    pub last_move: Option<(Position, Position)>,
+    /// Whether the board is drawn on a single line, one rank after another
+    /// separated by `/`
+    pub compact: bool,
+}
+
+impl Default for BoardRenderer {
+    fn default() -> Self {
+        Self {
+            piece_set: PieceSet::Unicode,
+            coordinates: true,
+            flipped: false,
+            last_move: None,
+            compact: false,
+        }
+    }
+}
+
+impl BoardRenderer {
+    /// The ranks from the top of the board down, and the files from left
+    /// to right
+    fn order(&self) -> ([u8; 8], [u8; 8]) {
+        let mut ranks = [7, 6, 5, 4, 3, 2, 1, 0];
+        let mut files = [0, 1, 2, 3, 4, 5, 6, 7];
+
+        if self.flipped {
+            ranks.reverse();
+            files.reverse();
+        }
+
+        (ranks, files)
+    }
+    fn is_highlighted(&self, position: Position) -> bool {
+        self.last_move
+            .map_or(false, |(from, to)| position == from || position == to)
+    }
+    /// Draws `board` as text
+    #[must_use]
+    pub fn render(&self, board: &Board) -> String {
+        let (ranks, files) = self.order();
+        let mut text = String::new();
+
+        for (row, &y) in ranks.iter().enumerate() {
+            if self.compact {
+                if row > 0 {
+                    text.push('/');
+                }
+            } else if self.coordinates {
+                let _ = write!(text, "{} ", y + 1);
+            }
+
+            for (column, &x) in files.iter().enumerate() {
+                let position = Position::new(x, y);
+                let symbol = self.piece_set.symbol(board.piece_at(position));
+
+                if !self.compact && column > 0 {
+                    text.push(' ');
+                }
+
+                if self.is_highlighted(position) {
+                    let _ = write!(text, "{}{}{}", HIGHLIGHT, symbol, RESET);
+                } else {
+                    text.push(symbol);
+                }
+            }
+
+            if !self.compact {
+                text.push('\n');
+            }
+        }
+
+        if !self.compact && self.coordinates {
+            text.push_str("  ");
+
+            for (column, &x) in files.iter().enumerate() {
+                if column > 0 {
+                    text.push(' ');
+                }
+
+                text.push(char::from(b'a' + x));
+            }
+
+            text.push('\n');
+        }
+
+        text
+    }
+    /// Draws `board` as an SVG diagram. Only the orientation, coordinates
+    /// and last move are used, as the pieces are always drawn as symbols
+    #[must_use]
+    pub fn svg(&self, board: &Board) -> String {
+        let (ranks, files) = self.order();
+        let margin = if self.coordinates { SVG_MARGIN } else { 0 };
+        let size = 8 * SVG_SQUARE + 2 * margin;
+        let mut svg = String::new();
+
+        let _ = writeln!(
+            svg,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{0}" height="{0}" viewBox="0 0 {0} {0}">"#,
+            size
+        );
+
+        for (row, &y) in ranks.iter().enumerate() {
+            for (column, &x) in files.iter().enumerate() {
+                let position = Position::new(x, y);
+                let left = margin + column as u32 * SVG_SQUARE;
+                let top = margin + row as u32 * SVG_SQUARE;
+
+                let colour = if self.is_highlighted(position) {
+                    SVG_HIGHLIGHT
+                } else if (x + y) % 2 == 0 {
+                    SVG_DARK
+                } else {
+                    SVG_LIGHT
+                };
+
+                let _ = writeln!(
+                    svg,
+                    r#"  <rect x="{}" y="{}" width="{2}" height="{2}" fill="{3}"/>"#,
+                    left, top, SVG_SQUARE, colour
+                );
+
+                let piece = board.piece_at(position);
+
+                if piece.is_piece() {
+                    // Both sides use the filled symbols, coloured in, so
+                    // they stand out from the squares
+                    let (fill, stroke) = match piece.team() {
+                        Team::White => ("#ffffff", "#000000"),
+                        _ => ("#000000", "#ffffff"),
+                    };
+
+                    let _ = writeln!(
+                        svg,
+                        r#"  <text x="{}" y="{}" font-size="{}" text-anchor="middle" dominant-baseline="central" fill="{}" stroke="{}" stroke-width="0.8">{}</text>"#,
+                        left + SVG_SQUARE / 2,
+                        top + SVG_SQUARE / 2,
+                        SVG_SQUARE * 4 / 5,
+                        fill,
+                        stroke,
+                        Piece::new(Team::Black, piece.kind())
+                    );
+                }
+            }
+        }
+
+        if self.coordinates {
+            for (i, (&y, &x)) in ranks.iter().zip(files.iter()).enumerate() {
+                let centre = margin + i as u32 * SVG_SQUARE + SVG_SQUARE / 2;
+
+                let _ = writeln!(
+                    svg,
+                    r#"  <text x="{}" y="{}" font-size="12" text-anchor="middle" dominant-baseline="central">{}</text>"#,
+                    margin / 2,
+                    centre,
+                    y + 1
+                );
+                let _ = writeln!(
+                    svg,
+                    r#"  <text x="{}" y="{}" font-size="12" text-anchor="middle" dominant-baseline="central">{}</text>"#,
+                    centre,
+                    size - margin / 2,
+                    char::from(b'a' + x)
+                );
+            }
+        }
+
+        svg.push_str("</svg>\n");
+
+        svg
+    }
+}
+
+impl Board {
+    /// Draws the board as an SVG diagram from white's side, with
+    /// coordinates
+    #[must_use]
+    pub fn to_svg(&self) -> String {
+        BoardRenderer::default().svg(self)
+    }
+}
+
+#[test]
+fn render_text() {
+    let board = Board::new();
+
+    let ascii = BoardRenderer {
+        piece_set: PieceSet::Ascii,
+        ..BoardRenderer::default()
+    };
+
+    assert_eq!(
+        ascii.render(&board),
+        "8 r n b q k b n r\n\
+         7 p p p p p p p p\n\
+         6 . . . . . . . .\n\
+         5 . . . . . . . .\n\
+         4 . . . . . . . .\n\
+         3 . . . . . . . .\n\
+         2 P P P P P P P P\n\
+         1 R N B Q K B N R\n  \
+         a b c d e f g h\n"
+    );
+
+    let flipped = BoardRenderer {
+        flipped: true,
+        coordinates: false,
+        ..ascii
+    };
+
+    assert_eq!(
+        flipped.render(&board).lines().next(),
+        Some("R N B K Q B N R")
+    );
+
+    let compact = BoardRenderer {
+        compact: true,
+        ..BoardRenderer::default()
+    };
+
+    assert_eq!(
+        compact.render(&board),
+        "♜♞♝♛♚♝♞♜/♟♟♟♟♟♟♟♟/......../......../......../......../♙♙♙♙♙♙♙♙/♖♘♗♕♔♗♘♖"
+    );
+}
+
+#[test]
+fn render_last_move() {
+    let board = Board::new();
+    let played_move = board.parse_uci_move("e2e4").unwrap();
+    let board = board.make_move(&played_move).unwrap();
+
+    let renderer = BoardRenderer {
+        piece_set: PieceSet::Ascii,
+        last_move: Some(played_move.from_to()),
+        ..BoardRenderer::default()
+    };
+    let text = renderer.render(&board);
+
+    assert_eq!(text.matches(HIGHLIGHT).count(), 2);
+    assert!(text.contains(&format!("{}P{}", HIGHLIGHT, RESET)));
+    assert!(text.contains(&format!("{}.{}", HIGHLIGHT, RESET)));
+}
+
+#[test]
+fn render_svg() {
+    let svg = Board::new().to_svg();
+
+    assert!(svg.starts_with("<svg"));
+    assert!(svg.ends_with("</svg>\n"));
+    assert_eq!(svg.matches("<rect").count(), 64);
+    // 32 pieces, and 16 coordinates
+    assert_eq!(svg.matches("<text").count(), 48);
+    assert_eq!(svg.matches(r##"fill="#ffffff""##).count(), 16);
+}
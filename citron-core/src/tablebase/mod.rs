@@ -0,0 +1,550 @@
+//! Probes Syzygy endgame tablebases.
+//!
+//! Tables are read from `.rtbw` files, which store whether a position is
+//! won, drawn or lost, and `.rtbz` files, which store the distance to the
+//! next capture or pawn move (DTZ) needed to keep a win under the 50-move
+//! rule. Tables are only read into memory the first time they're probed.
+//!
+//! The board doesn't track castling, en passant or the 50-move counter,
+//! so positions are always probed as if none apply, and the counter is
+//! taken to be zero at the root. Pawns are only ever promoted to queens.
+
+use std::{
+    collections::HashMap,
+    io,
+    lazy::SyncOnceCell,
+    ops::Neg,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    move_gen::Move,
+    piece::{PieceKind, KING_VALUE},
+    Board, PlayableTeam,
+};
+
+mod table;
+
+use table::{Table, TableKind, TableProbe};
+
+static TABLEBASES: SyncOnceCell<Tablebases> = SyncOnceCell::new();
+
+/// The score given to a tablebase win, less the ply it's found at. It's
+/// below [`KING_VALUE`] so capturing the king is still preferred
+pub const TABLEBASE_WIN: i16 = KING_VALUE / 2;
+
+/// The result of a position with best play, from the perspective of the
+/// side to move
+#[repr(i8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Wdl {
+    Loss = -2,
+    /// A loss that can be held to a draw by the 50-move rule
+    BlessedLoss = -1,
+    Draw = 0,
+    /// A win that can't be forced before the 50-move rule draws it
+    CursedWin = 1,
+    Win = 2,
+}
+
+impl Wdl {
+    const fn from_i32(value: i32) -> Option<Self> {
+        Some(match value {
+            -2 => Self::Loss,
+            -1 => Self::BlessedLoss,
+            0 => Self::Draw,
+            1 => Self::CursedWin,
+            2 => Self::Win,
+            _ => return None,
+        })
+    }
+    const fn signum(self) -> i32 {
+        (self as i32).signum()
+    }
+    /// The DTZ of the move before a capture or pawn move reaching a
+    /// position with this result
+    const fn dtz_before_zeroing(self) -> i32 {
+        match self {
+            Self::Loss => -1,
+            Self::BlessedLoss => -101,
+            Self::Draw => 0,
+            Self::CursedWin => 101,
+            Self::Win => 1,
+        }
+    }
+    /// The search score of this result, found `ply` plies from the root.
+    /// Wins found sooner score higher, and results decided by the 50-move
+    /// rule score barely off a draw
+    #[must_use]
+    pub const fn score(self, ply: u8) -> i16 {
+        match self {
+            Self::Loss => -TABLEBASE_WIN + ply as i16,
+            Self::BlessedLoss => -1,
+            Self::Draw => 0,
+            Self::CursedWin => 1,
+            Self::Win => TABLEBASE_WIN - ply as i16,
+        }
+    }
+}
+
+impl Neg for Wdl {
+    type Output = Self;
+
+    fn neg(self) -> Self::Output {
+        match self {
+            Self::Loss => Self::Win,
+            Self::BlessedLoss => Self::CursedWin,
+            Self::Draw => Self::Draw,
+            Self::CursedWin => Self::BlessedLoss,
+            Self::Win => Self::Loss,
+        }
+    }
+}
+
+/// A directory of Syzygy tables
+pub struct Tablebases {
+    directory: PathBuf,
+    /// Every table in the directory, by name and kind, loaded when first
+    /// probed. Tables that fail to load are left as `None`
+    tables: HashMap<(String, TableKind), SyncOnceCell<Option<Table>>>,
+    max_pieces: usize,
+    probe_limit: usize,
+}
+
+impl Tablebases {
+    /// Finds every table in `directory`. The tables themselves aren't read
+    /// until they're needed
+    pub fn open(directory: impl AsRef<Path>) -> io::Result<Self> {
+        let directory = directory.as_ref().to_path_buf();
+        let mut tables = HashMap::new();
+        let mut max_pieces = 0;
+
+        for entry in std::fs::read_dir(&directory)? {
+            let path = entry?.path();
+
+            let kind = match path.extension().and_then(|extension| extension.to_str()) {
+                Some("rtbw") => TableKind::Wdl,
+                Some("rtbz") => TableKind::Dtz,
+                _ => continue,
+            };
+
+            if let Some(name) = path.file_stem().and_then(|name| name.to_str()) {
+                if !is_table_name(name) {
+                    continue;
+                }
+
+                if kind == TableKind::Wdl {
+                    max_pieces = max_pieces.max(name.len() - 1);
+                }
+
+                tables.insert((name.to_string(), kind), SyncOnceCell::new());
+            }
+        }
+
+        Ok(Self {
+            directory,
+            tables,
+            max_pieces,
+            probe_limit: max_pieces,
+        })
+    }
+    /// The most pieces of any win/draw/loss table found
+    #[must_use]
+    pub const fn max_pieces(&self) -> usize {
+        self.max_pieces
+    }
+    /// The most pieces a position can have to be probed during the search
+    #[must_use]
+    pub const fn probe_limit(&self) -> usize {
+        self.probe_limit
+    }
+    /// Sets the most pieces a position can have to be probed during the
+    /// search. Positions at the root are probed whenever there's a table
+    pub fn set_probe_limit(&mut self, pieces: usize) {
+        self.probe_limit = pieces.min(self.max_pieces);
+    }
+    /// Makes these the tablebases the search probes. Returns false if some
+    /// were already installed
+    pub fn install(self) -> bool {
+        TABLEBASES.set(self).is_ok()
+    }
+    /// Finds the table for the material on `board`, and whether black has
+    /// the pieces the table lists first
+    fn table(&self, board: &Board, kind: TableKind) -> Option<(&Table, bool)> {
+        let white = material(board, PlayableTeam::White);
+        let black = material(board, PlayableTeam::Black);
+
+        for (name, black_stronger) in [
+            (format!("{}v{}", white, black), false),
+            (format!("{}v{}", black, white), true),
+        ] {
+            if let Some(table) = self.tables.get(&(name.clone(), kind)) {
+                let extension = match kind {
+                    TableKind::Wdl => "rtbw",
+                    TableKind::Dtz => "rtbz",
+                };
+
+                return table
+                    .get_or_init(|| {
+                        let path = self.directory.join(format!("{}.{}", name, extension));
+
+                        Table::new(kind, &name, std::fs::read(path).ok()?)
+                    })
+                    .as_ref()
+                    .map(|table| (table, black_stronger));
+            }
+        }
+
+        None
+    }
+    fn probe_table(&self, board: &Board, kind: TableKind, wdl: Wdl) -> Option<TableProbe> {
+        // Two kings are always a draw, so have no table
+        if piece_count(board) == 2 {
+            return Some(TableProbe::Value(0));
+        }
+
+        let (table, black_stronger) = self.table(board, kind)?;
+
+        table.probe(board, black_stronger, wdl as i32)
+    }
+    /// Whether the position could be in the tables: each side needs exactly
+    /// one king, and there can't be more pieces than the largest table has
+    fn can_probe(&self, board: &Board) -> bool {
+        let kings = |team: PlayableTeam| {
+            board.pieces[team as usize][PieceKind::King as usize].count_ones()
+        };
+
+        kings(PlayableTeam::White) == 1
+            && kings(PlayableTeam::Black) == 1
+            && piece_count(board) <= self.max_pieces
+    }
+    /// Probes the position, checking captures, and pawn moves if
+    /// `check_zeroing` is set, by hand. Also returns whether the best move
+    /// is one of those, in which case the DTZ tables can't be relied on
+    fn search(&self, board: &Board, check_zeroing: bool) -> Option<(Wdl, bool)> {
+        let moves = board.legal_moves();
+        let mut best = Wdl::Loss;
+        let mut searched = 0;
+
+        for possible_move in &moves {
+            if !is_capture(possible_move)
+                && (!check_zeroing || possible_move.moved_piece_kind() != PieceKind::Pawn)
+            {
+                continue;
+            }
+
+            searched += 1;
+
+            let value = -self.search(&board.make_move(possible_move)?, false)?.0;
+
+            if value > best {
+                best = value;
+
+                if value >= Wdl::Win {
+                    return Some((value, true));
+                }
+            }
+        }
+
+        // When every move has been searched, the table's value isn't needed
+        let no_more_moves = searched > 0 && searched == moves.len();
+        let value = if no_more_moves {
+            best
+        } else {
+            match self.probe_table(board, TableKind::Wdl, Wdl::Draw)? {
+                TableProbe::Value(value) => Wdl::from_i32(value)?,
+                TableProbe::ChangeStm => return None,
+            }
+        };
+
+        if best >= value {
+            Some((best, best > Wdl::Draw || no_more_moves))
+        } else {
+            Some((value, false))
+        }
+    }
+    /// The result of the position on `board` with best play, or `None` if
+    /// it isn't in the tablebases
+    #[must_use]
+    pub fn probe_wdl(&self, board: &Board) -> Option<Wdl> {
+        if !self.can_probe(board) {
+            return None;
+        }
+
+        Some(self.search(board, false)?.0)
+    }
+    /// The number of plies until the next capture or pawn move with best
+    /// play, positive if the side to move wins, negative if it loses and
+    /// zero for draws. Results decided by the 50-move rule are 100 further
+    /// from zero. `None` if the position isn't in the tablebases
+    #[must_use]
+    pub fn probe_dtz(&self, board: &Board) -> Option<i32> {
+        if !self.can_probe(board) {
+            return None;
+        }
+
+        let (wdl, zeroing) = self.search(board, true)?;
+
+        if wdl == Wdl::Draw {
+            return Some(0);
+        }
+
+        if zeroing {
+            return Some(wdl.dtz_before_zeroing());
+        }
+
+        match self.probe_table(board, TableKind::Dtz, wdl)? {
+            TableProbe::Value(dtz) => {
+                let cursed = matches!(wdl, Wdl::BlessedLoss | Wdl::CursedWin);
+
+                Some((dtz + if cursed { 100 } else { 0 }) * wdl.signum())
+            }
+            // The table only stores the other side to move, so search one
+            // ply to find the best DTZ
+            TableProbe::ChangeStm => {
+                let mut best = 0xffff;
+
+                for possible_move in board.legal_moves() {
+                    let zeroing = is_capture(&possible_move)
+                        || possible_move.moved_piece_kind() == PieceKind::Pawn;
+                    let board = board.make_move(&possible_move)?;
+
+                    let mut dtz = if zeroing {
+                        -self.search(&board, false)?.0.dtz_before_zeroing()
+                    } else {
+                        -self.probe_dtz(&board)?
+                    };
+
+                    if dtz == 1 && is_checkmate(&board) {
+                        best = 1;
+                    }
+
+                    if !zeroing {
+                        dtz += dtz.signum();
+                    }
+
+                    if dtz < best && dtz.signum() == wdl.signum() {
+                        best = dtz;
+                    }
+                }
+
+                // Without any legal moves it's checkmate
+                Some(if best == 0xffff { -1 } else { best })
+            }
+        }
+    }
+    /// Picks the move that best keeps the result of the position under the
+    /// 50-move rule. Winning moves that reach the next capture or pawn move
+    /// soonest are preferred, as are losing moves that put it off longest
+    #[must_use]
+    pub fn root_move(&self, board: &Board) -> Option<(Move, Wdl)> {
+        if !self.can_probe(board) {
+            return None;
+        }
+
+        let mut best: Option<(Move, i32, i32)> = None;
+
+        for possible_move in board.legal_moves() {
+            let after = board.make_move(&possible_move)?;
+
+            let mut dtz = if is_capture(&possible_move)
+                || possible_move.moved_piece_kind() == PieceKind::Pawn
+            {
+                (-self.probe_wdl(&after)?).dtz_before_zeroing()
+            } else {
+                let dtz = -self.probe_dtz(&after)?;
+
+                dtz + dtz.signum()
+            };
+
+            if dtz == 2 && is_checkmate(&after) {
+                dtz = 1;
+            }
+
+            // Wins within the 50-move rule rank equally, as do losses that
+            // can't reach it
+            let rank = match dtz {
+                1..=99 => 1000,
+                100.. => 1000 - dtz,
+                -49..=-1 => -1000,
+                _ if dtz < 0 => -1000 - dtz,
+                _ => 0,
+            };
+
+            if best.as_ref().map_or(true, |&(_, best_rank, best_dtz)| {
+                (rank, -dtz) > (best_rank, -best_dtz)
+            }) {
+                best = Some((possible_move, rank, dtz));
+            }
+        }
+
+        let (best_move, _, dtz) = best?;
+
+        let wdl = match dtz {
+            1..=100 => Wdl::Win,
+            101.. => Wdl::CursedWin,
+            0 => Wdl::Draw,
+            -100..=-1 => Wdl::Loss,
+            _ => Wdl::BlessedLoss,
+        };
+
+        Some((best_move, wdl))
+    }
+}
+
+/// The installed tablebases, if any
+#[must_use]
+pub fn tablebases() -> Option<&'static Tablebases> {
+    TABLEBASES.get()
+}
+
+pub(crate) fn piece_count(board: &Board) -> usize {
+    (board.all_pieces[0] | board.all_pieces[1]).count_ones() as usize
+}
+
+fn is_capture(possible_move: &Move) -> bool {
+    possible_move.captured_piece_kind() != PieceKind::None
+}
+
+fn is_checkmate(board: &Board) -> bool {
+    board.in_check(board.to_play()) && board.legal_moves().is_empty()
+}
+
+/// Names one side's material the way table files do, such as `KRP`
+fn material(board: &Board, team: PlayableTeam) -> String {
+    let pieces = &board.pieces[team as usize];
+    let mut name = String::from("K");
+
+    for (kind, letter) in [
+        (PieceKind::Queen, 'Q'),
+        (PieceKind::Rook, 'R'),
+        (PieceKind::Bishop, 'B'),
+        (PieceKind::Knight, 'N'),
+        (PieceKind::Pawn, 'P'),
+    ] {
+        for _ in 0..pieces[kind as usize].count_ones() {
+            name.push(letter);
+        }
+    }
+
+    name
+}
+
+fn is_table_name(name: &str) -> bool {
+    name.split_once('v').map_or(false, |(white, black)| {
+        [white, black].iter().all(|side| {
+            side.starts_with('K') && side[1..].chars().all(|piece| "QRBNP".contains(piece))
+        }) && name.len() - 1 <= table::MAX_PIECES
+    })
+}
+
+#[cfg(test)]
+fn fixture_tablebases(tables: &[&str]) -> Tablebases {
+    let directory = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/syzygy");
+
+    for table in tables {
+        for extension in ["rtbw", "rtbz"] {
+            let path = Path::new(directory).join(format!("{}.{}", table, extension));
+
+            assert!(path.exists(), "{} is missing", path.display());
+        }
+    }
+
+    Tablebases::open(directory).unwrap()
+}
+
+#[test]
+fn material_names() {
+    let board = Board::from_fen("4k3/8/8/3n4/8/8/3PP3/R3K3 w - - 0 1").unwrap();
+
+    assert_eq!(material(&board, PlayableTeam::White), "KRPP");
+    assert_eq!(material(&board, PlayableTeam::Black), "KN");
+    assert_eq!(piece_count(&board), 6);
+
+    assert!(is_table_name("KRPPvKN"));
+    assert!(!is_table_name("KRvKN.rtbw"));
+    assert!(!is_table_name("notes"));
+}
+
+#[test]
+#[ignore = "needs the KQvK, KRvK and KPvK Syzygy tables in tests/fixtures/syzygy"]
+fn wdl_probes() {
+    let tablebases = fixture_tablebases(&["KQvK", "KRvK", "KPvK"]);
+
+    let probe = |fen: &str| tablebases.probe_wdl(&Board::from_fen(fen).unwrap());
+
+    assert_eq!(probe("4k3/8/8/8/8/8/8/3QK3 w - - 0 1"), Some(Wdl::Win));
+    assert_eq!(probe("4k3/8/8/8/8/8/8/3QK3 b - - 0 1"), Some(Wdl::Loss));
+    // Black is the stronger side, so the board is flipped to probe it
+    assert_eq!(probe("3qk3/8/8/8/8/8/8/4K3 w - - 0 1"), Some(Wdl::Loss));
+    assert_eq!(probe("4k3/8/8/8/8/8/8/4K3 w - - 0 1"), Some(Wdl::Draw));
+    // The rook can be taken
+    assert_eq!(probe("8/8/8/8/8/8/8/Kr2k3 w - - 0 1"), Some(Wdl::Draw));
+    // Stalemate
+    assert_eq!(probe("k7/2Q5/1K6/8/8/8/8/8 b - - 0 1"), Some(Wdl::Draw));
+    assert_eq!(probe("8/8/8/8/8/8/4P3/k3K3 w - - 0 1"), Some(Wdl::Win));
+    // The defending king holds the corner against a rook's pawn
+    assert_eq!(probe("k7/8/8/8/8/8/P7/K7 w - - 0 1"), Some(Wdl::Draw));
+    // There are too many pieces for any table
+    assert_eq!(probe("4k3/pppp4/8/8/8/8/PPPP4/4K3 w - - 0 1"), None);
+    // Positions without one king each aren't in the tables
+    assert_eq!(probe("8/8/8/8/8/8/8/3QK3 w - - 0 1"), None);
+    assert_eq!(probe("4k3/8/8/8/8/8/8/K3K3 w - - 0 1"), None);
+}
+
+#[test]
+#[ignore = "needs the KQvK, KRvK and KPvK Syzygy tables in tests/fixtures/syzygy"]
+fn dtz_probes() {
+    let tablebases = fixture_tablebases(&["KQvK", "KRvK"]);
+
+    let probe = |fen: &str| tablebases.probe_dtz(&Board::from_fen(fen).unwrap());
+
+    // Mate in one
+    assert_eq!(probe("k7/8/1K6/8/8/8/7Q/8 w - - 0 1"), Some(1));
+    // Checkmated
+    assert_eq!(probe("k7/1Q6/1K6/8/8/8/8/8 b - - 0 1"), Some(-1));
+    assert_eq!(probe("4k3/8/8/8/8/8/8/4K3 w - - 0 1"), Some(0));
+
+    let winning = probe("8/8/8/4k3/8/8/8/R3K3 w - - 0 1").unwrap();
+    let losing = probe("8/8/8/4k3/8/8/8/R3K3 b - - 0 1").unwrap();
+
+    assert!((1..=100).contains(&winning));
+    assert!((-100..=-1).contains(&losing));
+
+    assert_eq!(probe("8/8/8/8/8/8/8/R3K3 w - - 0 1"), None);
+}
+
+#[test]
+#[ignore = "needs the KQvK, KRvK and KPvK Syzygy tables in tests/fixtures/syzygy"]
+fn root_move_keeps_the_win() {
+    let tablebases = fixture_tablebases(&["KQvK", "KRvK"]);
+
+    let board = Board::from_fen("k7/8/1K6/8/8/8/7Q/8 w - - 0 1").unwrap();
+    let (best_move, wdl) = tablebases.root_move(&board).unwrap();
+
+    // Qh8 is the only mate
+    assert_eq!(best_move.to_uci(), "h2h8");
+    assert_eq!(wdl, Wdl::Win);
+
+    let kingless = Board::from_fen("8/8/1K6/8/8/8/7Q/8 w - - 0 1").unwrap();
+    assert!(tablebases.root_move(&kingless).is_none());
+
+    // Every move but giving up the rook keeps the win
+    let mut board = Board::from_fen("8/8/8/4k3/8/8/8/R3K3 w - - 0 1").unwrap();
+
+    for _ in 0..20 {
+        let (best_move, wdl) = tablebases.root_move(&board).unwrap();
+
+        assert_eq!(wdl, Wdl::Win);
+
+        board = board.make_move(&best_move).unwrap();
+
+        match tablebases.root_move(&board) {
+            Some((reply, _)) => board = board.make_move(&reply).unwrap(),
+            // Black has been checkmated
+            None => break,
+        }
+
+        assert_eq!(tablebases.probe_wdl(&board), Some(Wdl::Win));
+    }
+}
@@ -0,0 +1,1037 @@
+//! Decoding of a single Syzygy table file. Tables are split into up to
+//! four parts, one for each file the leading pawn can be on, and each part
+//! is compressed separately with recursive pairing and a canonical Huffman
+//! code. Positions are turned into an index into the decompressed values
+//! by grouping identical pieces together and using the board's symmetry.
+
+use std::lazy::SyncLazy;
+
+use crate::{
+    magic::{king_attacks, pop_lsb},
+    piece::PieceKind,
+    Board, PlayableTeam, Position,
+};
+
+/// The most pieces a table can have
+pub const MAX_PIECES: usize = 7;
+
+pub const WDL_MAGIC: [u8; 4] = [0x71, 0xe8, 0x23, 0x5d];
+pub const DTZ_MAGIC: [u8; 4] = [0xd7, 0x66, 0x0c, 0xa5];
+
+// Flags stored for each part of a table
+/// The side to move a DTZ table stores values for
+const STM: u8 = 1;
+/// DTZ values are stored through a map, rather than directly
+const MAPPED: u8 = 2;
+/// DTZ values of wins are stored in plies rather than moves
+const WIN_PLIES: u8 = 4;
+/// DTZ values of losses are stored in plies rather than moves
+const LOSS_PLIES: u8 = 8;
+/// The DTZ map is made of 16 bit values
+const WIDE: u8 = 16;
+/// Every position stores the same value
+const SINGLE_VALUE: u8 = 128;
+
+/// The lookup tables used to index positions, which only need computing
+/// once
+static ENCODING: SyncLazy<Encoding> = SyncLazy::new(Encoding::new);
+
+struct Encoding {
+    /// Numbers the squares a2 to h7 so that the pawn nearest the edge, and
+    /// then on the lowest rank, has the highest number
+    map_pawns: [usize; 64],
+    /// Numbers the squares below the a1-h8 diagonal from 0 to 27
+    map_b1h1h7: [usize; 64],
+    /// Numbers the squares in the a1-d1-d4 triangle from 0 to 9, with those
+    /// on the diagonal last
+    map_a1d1d4: [usize; 64],
+    /// Numbers every legal placement of two kings, where the first is in
+    /// the a1-d1-d4 triangle, from 0 to 461
+    map_kk: [[usize; 64]; 10],
+    /// The binomial coefficients, indexed by `[k][n]`
+    binomial: [[u64; 64]; 6],
+    /// The index of the leading pawn, by the number of leading pawns and
+    /// the leading pawn's square
+    lead_pawn_index: [[u64; 64]; 6],
+    /// The number of placements of leading pawns, by their number and the
+    /// leading pawn's file
+    lead_pawns_size: [[u64; 4]; 6],
+}
+
+impl Encoding {
+    fn new() -> Self {
+        let mut encoding = Self {
+            map_pawns: [0; 64],
+            map_b1h1h7: [0; 64],
+            map_a1d1d4: [0; 64],
+            map_kk: [[0; 64]; 10],
+            binomial: [[0; 64]; 6],
+            lead_pawn_index: [[0; 64]; 6],
+            lead_pawns_size: [[0; 4]; 6],
+        };
+
+        let mut code = 0;
+
+        for square in 0..64 {
+            if off_diagonal(square) < 0 {
+                encoding.map_b1h1h7[square] = code;
+                code += 1;
+            }
+        }
+
+        let mut diagonal = Vec::new();
+        code = 0;
+
+        // Every square up to d4
+        for square in 0..28 {
+            if square % 8 > 3 {
+                continue;
+            }
+
+            if off_diagonal(square) < 0 {
+                encoding.map_a1d1d4[square] = code;
+                code += 1;
+            } else if off_diagonal(square) == 0 {
+                diagonal.push(square);
+            }
+        }
+
+        for square in diagonal {
+            encoding.map_a1d1d4[square] = code;
+            code += 1;
+        }
+
+        let mut both_on_diagonal = Vec::new();
+        code = 0;
+
+        for index in 0..10 {
+            for first in 0..28 {
+                // b1 is numbered 0, so a1 isn't mistaken for it
+                if square_file(first) > 3
+                    || encoding.map_a1d1d4[first] != index
+                    || (index == 0 && first != 1)
+                {
+                    continue;
+                }
+
+                let attacked = king_attacks(Position::from_u8(first as u8)) | 1 << first;
+
+                for second in 0..64 {
+                    if attacked & 1 << second != 0 {
+                        continue;
+                    }
+
+                    if off_diagonal(first) == 0 && off_diagonal(second) > 0 {
+                        continue;
+                    }
+
+                    if off_diagonal(first) == 0 && off_diagonal(second) == 0 {
+                        both_on_diagonal.push((index, second));
+                    } else {
+                        encoding.map_kk[index][second] = code;
+                        code += 1;
+                    }
+                }
+            }
+        }
+
+        for (index, second) in both_on_diagonal {
+            encoding.map_kk[index][second] = code;
+            code += 1;
+        }
+
+        encoding.binomial[0][0] = 1;
+
+        for n in 1..64 {
+            for k in 0..6.min(n + 1) {
+                encoding.binomial[k][n] = if k > 0 {
+                    encoding.binomial[k - 1][n - 1]
+                } else {
+                    0
+                } + if k < n {
+                    encoding.binomial[k][n - 1]
+                } else {
+                    0
+                };
+            }
+        }
+
+        for lead_pawns in 1..6 {
+            for file in 0..4 {
+                let mut index = 0;
+
+                for rank in 1..7 {
+                    let square = rank * 8 + file;
+
+                    if lead_pawns == 1 {
+                        // Squares are numbered down from 47, a file at a
+                        // time, in pairs mirrored across the board
+                        let available = 47 - 2 * (file * 6 + rank - 1);
+
+                        encoding.map_pawns[square] = available;
+                        encoding.map_pawns[flip_file(square)] = available - 1;
+                    }
+
+                    encoding.lead_pawn_index[lead_pawns][square] = index;
+                    index += encoding.binomial[lead_pawns - 1][encoding.map_pawns[square]];
+                }
+
+                encoding.lead_pawns_size[lead_pawns][file] = index;
+            }
+        }
+
+        encoding
+    }
+}
+
+const fn square_file(square: usize) -> usize {
+    square % 8
+}
+
+const fn square_rank(square: usize) -> usize {
+    square / 8
+}
+
+const fn flip_file(square: usize) -> usize {
+    square ^ 7
+}
+
+const fn flip_rank(square: usize) -> usize {
+    square ^ 0x38
+}
+
+/// How far above the a1-h8 diagonal a square is, negative if it's below
+const fn off_diagonal(square: usize) -> isize {
+    square_rank(square) as isize - square_file(square) as isize
+}
+
+/// Whether a table stores win/draw/loss values or distances to zeroing
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TableKind {
+    Wdl,
+    Dtz,
+}
+
+/// The result of probing a single table
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TableProbe {
+    Value(i32),
+    /// DTZ tables only store one side to move, and this isn't it
+    ChangeStm,
+}
+
+/// The decoding information for one part of a table. Every position is
+/// given as an offset into the table's bytes
+#[derive(Clone, Debug, Default)]
+struct PairsData {
+    flags: u8,
+    min_symbol_length: u8,
+    block_size: u64,
+    span: u64,
+    lowest_symbol: usize,
+    tree: usize,
+    block_lengths: usize,
+    block_lengths_size: u64,
+    sparse_index: usize,
+    sparse_index_size: u64,
+    data: usize,
+    blocks: u64,
+    /// The lowest symbol of each length, left aligned in 64 bits
+    base: Vec<u64>,
+    /// The number of values each symbol expands to, minus one
+    symbol_lengths: Vec<u8>,
+    /// The table's pieces, in the order they're indexed
+    pieces: [u8; MAX_PIECES],
+    group_index: [u64; MAX_PIECES + 1],
+    group_length: [usize; MAX_PIECES + 1],
+    /// Where each result's DTZ map starts, for wins, losses, cursed wins
+    /// and blessed losses
+    map_index: [u16; 4],
+}
+
+/// A table file, read into memory
+pub struct Table {
+    kind: TableKind,
+    bytes: Vec<u8>,
+    piece_count: usize,
+    has_pawns: bool,
+    has_unique_pieces: bool,
+    /// The pawns of the leading side, then the other side
+    pawn_count: [usize; 2],
+    /// Whether both sides have the same pieces, in which case only white
+    /// to move is stored
+    symmetric: bool,
+    /// Indexed by side to move, then the leading pawn's file
+    parts: [[PairsData; 4]; 2],
+    map: usize,
+}
+
+impl Table {
+    /// Reads a table named after its pieces, such as `KRvKP`, returning
+    /// `None` if it's malformed
+    pub fn new(kind: TableKind, name: &str, bytes: Vec<u8>) -> Option<Self> {
+        let magic = match kind {
+            TableKind::Wdl => WDL_MAGIC,
+            TableKind::Dtz => DTZ_MAGIC,
+        };
+
+        if bytes.get(..4)? != magic {
+            return None;
+        }
+
+        let (white, black) = name.split_once('v')?;
+        let count = |side: &str, piece: char| side.chars().filter(|&c| c == piece).count();
+
+        let white_pawns = count(white, 'P');
+        let black_pawns = count(black, 'P');
+        // The leading side is the one with fewer pawns, or white when they
+        // have the same number
+        let white_leads = black_pawns == 0 || (white_pawns > 0 && black_pawns >= white_pawns);
+
+        let mut table = Self {
+            kind,
+            bytes,
+            piece_count: white.len() + black.len(),
+            has_pawns: white_pawns + black_pawns > 0,
+            has_unique_pieces: "QRBNP"
+                .chars()
+                .any(|piece| count(white, piece) == 1 || count(black, piece) == 1),
+            pawn_count: if white_leads {
+                [white_pawns, black_pawns]
+            } else {
+                [black_pawns, white_pawns]
+            },
+            symmetric: white == black,
+            parts: Default::default(),
+            map: 0,
+        };
+
+        if table.piece_count > MAX_PIECES {
+            return None;
+        }
+
+        table.set_up()?;
+
+        Some(table)
+    }
+    const fn sides(&self) -> usize {
+        match self.kind {
+            TableKind::Wdl => 2,
+            TableKind::Dtz => 1,
+        }
+    }
+    fn part(&self, stm: usize, file: usize) -> &PairsData {
+        &self.parts[stm % self.sides()][if self.has_pawns { file } else { 0 }]
+    }
+    fn part_mut(&mut self, stm: usize, file: usize) -> &mut PairsData {
+        let sides = self.sides();
+        let has_pawns = self.has_pawns;
+
+        &mut self.parts[stm % sides][if has_pawns { file } else { 0 }]
+    }
+    fn byte(&self, offset: usize) -> Option<u8> {
+        self.bytes.get(offset).copied()
+    }
+    fn u16_le(&self, offset: usize) -> Option<u16> {
+        Some(u16::from_le_bytes(
+            self.bytes.get(offset..offset + 2)?.try_into().ok()?,
+        ))
+    }
+    fn u32_le(&self, offset: usize) -> Option<u32> {
+        Some(u32::from_le_bytes(
+            self.bytes.get(offset..offset + 4)?.try_into().ok()?,
+        ))
+    }
+    fn u32_be(&self, offset: usize) -> Option<u32> {
+        Some(u32::from_be_bytes(
+            self.bytes.get(offset..offset + 4)?.try_into().ok()?,
+        ))
+    }
+    fn u64_be(&self, offset: usize) -> Option<u64> {
+        Some(u64::from_be_bytes(
+            self.bytes.get(offset..offset + 8)?.try_into().ok()?,
+        ))
+    }
+    /// Reads the header of each part of the table, and finds where each
+    /// part's data starts
+    fn set_up(&mut self) -> Option<()> {
+        let mut offset = 4;
+        let flags = self.byte(offset)?;
+
+        if (flags & 2 != 0) != self.has_pawns || (flags & 1 != 0) == self.symmetric {
+            return None;
+        }
+
+        offset += 1;
+
+        let sides = if self.kind == TableKind::Wdl && !self.symmetric {
+            2
+        } else {
+            1
+        };
+        let files = if self.has_pawns { 4 } else { 1 };
+        let both_have_pawns = self.has_pawns && self.pawn_count[1] > 0;
+
+        for file in 0..files {
+            let order_byte = self.byte(offset)?;
+            let pawn_order_byte = if both_have_pawns {
+                self.byte(offset + 1)?
+            } else {
+                0xff
+            };
+            let order = [
+                [order_byte & 0xf, pawn_order_byte & 0xf],
+                [order_byte >> 4, pawn_order_byte >> 4],
+            ];
+
+            offset += 1 + usize::from(both_have_pawns);
+
+            for piece in 0..self.piece_count {
+                let byte = self.byte(offset)?;
+
+                for side in 0..sides {
+                    self.part_mut(side, file).pieces[piece] =
+                        if side == 0 { byte & 0xf } else { byte >> 4 };
+                }
+
+                offset += 1;
+            }
+
+            for (side, order) in order.iter().enumerate().take(sides) {
+                self.set_groups(side, file, *order);
+            }
+        }
+
+        offset += offset & 1;
+
+        for file in 0..files {
+            for side in 0..sides {
+                offset = self.set_sizes(side, file, offset)?;
+            }
+        }
+
+        if self.kind == TableKind::Dtz {
+            offset = self.set_dtz_map(offset, files)?;
+        }
+
+        for file in 0..files {
+            for side in 0..sides {
+                let part = self.part_mut(side, file);
+
+                part.sparse_index = offset;
+                offset += part.sparse_index_size as usize * 6;
+            }
+        }
+
+        for file in 0..files {
+            for side in 0..sides {
+                let part = self.part_mut(side, file);
+
+                part.block_lengths = offset;
+                offset += part.block_lengths_size as usize * 2;
+            }
+        }
+
+        for file in 0..files {
+            for side in 0..sides {
+                let part = self.part_mut(side, file);
+
+                offset = (offset + 0x3f) & !0x3f;
+                part.data = offset;
+                offset += (part.blocks * part.block_size) as usize;
+            }
+        }
+
+        if offset > self.bytes.len() {
+            return None;
+        }
+
+        Some(())
+    }
+    /// Splits the pieces into groups that are indexed together, and works
+    /// out the index each group starts at
+    fn set_groups(&mut self, side: usize, file: usize, order: [u8; 2]) {
+        let encoding = &*ENCODING;
+        let has_pawns = self.has_pawns;
+        let has_unique_pieces = self.has_unique_pieces;
+        let piece_count = self.piece_count;
+        let both_have_pawns = has_pawns && self.pawn_count[1] > 0;
+        let part = self.part_mut(side, file);
+
+        // Without pawns, the first group is either three unique pieces or
+        // the two kings
+        let mut first_length: isize = if has_pawns {
+            0
+        } else if has_unique_pieces {
+            3
+        } else {
+            2
+        };
+        let mut groups = 0;
+
+        part.group_length[0] = 1;
+
+        for i in 1..piece_count {
+            first_length -= 1;
+
+            if first_length > 0 || part.pieces[i] == part.pieces[i - 1] {
+                part.group_length[groups] += 1;
+            } else {
+                groups += 1;
+                part.group_length[groups] = 1;
+            }
+        }
+
+        groups += 1;
+        part.group_length[groups] = 0;
+
+        let mut next = if both_have_pawns { 2 } else { 1 };
+        let mut free_squares = 64
+            - part.group_length[0]
+            - if both_have_pawns {
+                part.group_length[1]
+            } else {
+                0
+            };
+        let mut index = 1;
+        let mut k = 0;
+
+        while next < groups || k == usize::from(order[0]) || k == usize::from(order[1]) {
+            if k == usize::from(order[0]) {
+                part.group_index[0] = index;
+                index *= if has_pawns {
+                    encoding.lead_pawns_size[part.group_length[0]][file]
+                } else if has_unique_pieces {
+                    31332
+                } else {
+                    462
+                };
+            } else if k == usize::from(order[1]) {
+                part.group_index[1] = index;
+                index *= encoding.binomial[part.group_length[1]][48 - part.group_length[0]];
+            } else {
+                part.group_index[next] = index;
+                index *= encoding.binomial[part.group_length[next]][free_squares];
+                free_squares -= part.group_length[next];
+                next += 1;
+            }
+
+            k += 1;
+        }
+
+        part.group_index[groups] = index;
+    }
+    /// Reads the sizes of a part's compressed data and its Huffman code,
+    /// returning the offset after them
+    fn set_sizes(&mut self, side: usize, file: usize, mut offset: usize) -> Option<usize> {
+        let flags = self.byte(offset)?;
+        offset += 1;
+
+        if flags & SINGLE_VALUE != 0 {
+            let value = self.byte(offset)?;
+            let part = self.part_mut(side, file);
+
+            part.flags = flags;
+            part.min_symbol_length = value;
+
+            return Some(offset + 1);
+        }
+
+        let block_size = 1 << self.byte(offset)?;
+        let span = 1 << self.byte(offset + 1)?;
+        let padding = u64::from(self.byte(offset + 2)?);
+        let blocks = u64::from(self.u32_le(offset + 3)?);
+        let max_symbol_length = self.byte(offset + 7)?;
+        let min_symbol_length = self.byte(offset + 8)?;
+        let lowest_symbol = offset + 9;
+
+        if min_symbol_length == 0 || max_symbol_length < min_symbol_length {
+            return None;
+        }
+
+        let lengths = usize::from(max_symbol_length - min_symbol_length) + 1;
+        let mut base = vec![0_u64; lengths];
+
+        for i in (0..lengths - 1).rev() {
+            base[i] = base[i + 1]
+                .wrapping_add(u64::from(self.u16_le(lowest_symbol + 2 * i)?))
+                .wrapping_sub(u64::from(self.u16_le(lowest_symbol + 2 * i + 2)?))
+                / 2;
+        }
+
+        for (i, base) in base.iter_mut().enumerate() {
+            *base = base
+                .checked_shl(64 - i as u32 - u32::from(min_symbol_length))
+                .unwrap_or(0);
+        }
+
+        offset = lowest_symbol + 2 * lengths;
+
+        let symbols = usize::from(self.u16_le(offset)?);
+        let tree = offset + 2;
+
+        if self.bytes.len() < tree + 3 * symbols {
+            return None;
+        }
+
+        let mut symbol_lengths = vec![0; symbols];
+        let mut visited = vec![false; symbols];
+
+        for symbol in 0..symbols {
+            if !visited[symbol] {
+                symbol_lengths[symbol] =
+                    self.symbol_length(tree, symbol, &mut symbol_lengths, &mut visited)?;
+            }
+        }
+
+        let part = self.part_mut(side, file);
+        let table_size =
+            part.group_index[part.group_length.iter().position(|&length| length == 0)?];
+
+        part.flags = flags;
+        part.min_symbol_length = min_symbol_length;
+        part.block_size = block_size;
+        part.span = span;
+        part.sparse_index_size = (table_size + span - 1) / span;
+        part.blocks = blocks;
+        part.block_lengths_size = blocks + padding;
+        part.lowest_symbol = lowest_symbol;
+        part.base = base;
+        part.tree = tree;
+        part.symbol_lengths = symbol_lengths;
+
+        Some(tree + 3 * symbols + (symbols & 1))
+    }
+    /// Works out how many values a symbol expands to, minus one, by
+    /// following the pairs it's made of
+    fn symbol_length(
+        &self,
+        tree: usize,
+        symbol: usize,
+        lengths: &mut [u8],
+        visited: &mut [bool],
+    ) -> Option<u8> {
+        visited[symbol] = true;
+
+        let (left, right) = self.pair(tree, symbol)?;
+
+        if right == 0xfff {
+            return Some(0);
+        }
+
+        for child in [left, right] {
+            if !*visited.get(child)? {
+                lengths[child] = self.symbol_length(tree, child, lengths, visited)?;
+            }
+        }
+
+        Some(lengths[left].wrapping_add(lengths[right]).wrapping_add(1))
+    }
+    /// The two symbols a symbol expands to. Symbols of a single value store
+    /// it as the left symbol
+    fn pair(&self, tree: usize, symbol: usize) -> Option<(usize, usize)> {
+        let bytes = self.bytes.get(tree + 3 * symbol..tree + 3 * symbol + 3)?;
+
+        Some((
+            usize::from(bytes[1] & 0xf) << 8 | usize::from(bytes[0]),
+            usize::from(bytes[2]) << 4 | usize::from(bytes[1] >> 4),
+        ))
+    }
+    /// Finds where each part's map from stored values to DTZ values starts
+    fn set_dtz_map(&mut self, mut offset: usize, files: usize) -> Option<usize> {
+        self.map = offset;
+
+        for file in 0..files {
+            let flags = self.part(0, file).flags;
+
+            if flags & MAPPED == 0 {
+                continue;
+            }
+
+            let mut map_index = [0; 4];
+
+            if flags & WIDE != 0 {
+                offset += offset & 1;
+
+                for index in &mut map_index {
+                    *index = ((offset - self.map) / 2 + 1) as u16;
+                    offset += 2 * usize::from(self.u16_le(offset)?) + 2;
+                }
+            } else {
+                for index in &mut map_index {
+                    *index = (offset - self.map + 1) as u16;
+                    offset += usize::from(self.byte(offset)?) + 1;
+                }
+            }
+
+            self.part_mut(0, file).map_index = map_index;
+        }
+
+        Some(offset + (offset & 1))
+    }
+    /// Decompresses the value at `index` of a part
+    fn decompress(&self, part: &PairsData, index: u64) -> Option<i32> {
+        if part.flags & SINGLE_VALUE != 0 {
+            return Some(i32::from(part.min_symbol_length));
+        }
+
+        // The sparse index gives the block, and the offset within it, of
+        // every `span`th value, starting half a span in
+        let k = (index / part.span) as usize;
+        let mut block = self.u32_le(part.sparse_index + 6 * k)? as usize;
+        let mut offset = i64::from(self.u16_le(part.sparse_index + 6 * k + 4)?)
+            + (index % part.span) as i64
+            - (part.span / 2) as i64;
+
+        let block_length = |block: usize| -> Option<i64> {
+            Some(i64::from(self.u16_le(part.block_lengths + 2 * block)?))
+        };
+
+        while offset < 0 {
+            block = block.checked_sub(1)?;
+            offset += block_length(block)? + 1;
+        }
+
+        while offset > block_length(block)? {
+            offset -= block_length(block)? + 1;
+            block += 1;
+        }
+
+        let mut pointer = part.data + block * part.block_size as usize;
+        let mut buffer = self.u64_be(pointer)?;
+        let mut buffer_size = 64;
+        let min_length = u32::from(part.min_symbol_length);
+
+        pointer += 8;
+
+        let mut symbol = loop {
+            let mut length = 0;
+
+            while buffer < *part.base.get(length)? {
+                length += 1;
+            }
+
+            let mut symbol = ((buffer - part.base[length]) >> (64 - length as u32 - min_length))
+                as usize
+                + usize::from(self.u16_le(part.lowest_symbol + 2 * length)?);
+            symbol &= 0xffff;
+
+            let values = i64::from(*part.symbol_lengths.get(symbol)?) + 1;
+
+            if offset < values {
+                break symbol;
+            }
+
+            offset -= values;
+
+            let consumed = length as u32 + min_length;
+
+            buffer = buffer.checked_shl(consumed).unwrap_or(0);
+            buffer_size -= consumed as i32;
+
+            if buffer_size <= 32 {
+                buffer_size += 32;
+                buffer |= u64::from(self.u32_be(pointer)?) << (64 - buffer_size);
+                pointer += 4;
+            }
+        };
+
+        // Each symbol is a pair of adjacent symbols, so walk down the pairs
+        // until reaching the single value at the offset
+        while part.symbol_lengths[symbol] != 0 {
+            let (left, right) = self.pair(part.tree, symbol)?;
+            let left_values = i64::from(*part.symbol_lengths.get(left)?) + 1;
+
+            if offset < left_values {
+                symbol = left;
+            } else {
+                offset -= left_values;
+                symbol = right;
+            }
+
+            part.symbol_lengths.get(symbol)?;
+        }
+
+        Some(self.pair(part.tree, symbol)?.0 as i32)
+    }
+    /// Turns a stored value into a win/draw/loss value, or a DTZ value in
+    /// plies
+    fn map_score(&self, file: usize, value: i32, wdl: i32) -> Option<i32> {
+        if self.kind == TableKind::Wdl {
+            return Some(value - 2);
+        }
+
+        let part = self.part(0, file);
+        let flags = part.flags;
+        let mut value = value;
+
+        if flags & MAPPED != 0 {
+            let index =
+                usize::from(part.map_index[[1, 3, 0, 2, 0][(wdl + 2) as usize]]) + value as usize;
+
+            value = if flags & WIDE != 0 {
+                i32::from(self.u16_le(self.map + 2 * index)?)
+            } else {
+                i32::from(self.byte(self.map + index)?)
+            };
+        }
+
+        if (wdl == 2 && flags & WIN_PLIES == 0)
+            || (wdl == -2 && flags & LOSS_PLIES == 0)
+            || wdl == 1
+            || wdl == -1
+        {
+            value *= 2;
+        }
+
+        Some(value + 1)
+    }
+    /// Looks up a position. `black_stronger` is set when black has the
+    /// pieces this table lists first, and `wdl` is the position's result,
+    /// which DTZ tables need to decode their values
+    pub fn probe(&self, board: &Board, black_stronger: bool, wdl: i32) -> Option<TableProbe> {
+        let encoding = &*ENCODING;
+        let black_to_move = board.to_play() == PlayableTeam::Black;
+
+        // Symmetric tables only store white to move, and other tables have
+        // the stronger side as white, so the board is flipped to match
+        let flip = (self.symmetric && black_to_move) || black_stronger;
+        let flip_colour = if flip { 8 } else { 0 };
+        let flip_squares = if flip { 56 } else { 0 };
+        let stm = usize::from(flip ^ black_to_move);
+
+        let mut squares = [0; MAX_PIECES];
+        let mut pieces = [0; MAX_PIECES];
+        let mut size = 0;
+        let mut lead_pawns = 0;
+        let mut lead_pawn_count = 0;
+        let mut file = 0;
+
+        if self.has_pawns {
+            let lead_colour = self.part(0, 0).pieces[0] ^ flip_colour;
+            let team = if lead_colour & 8 == 0 {
+                PlayableTeam::White
+            } else {
+                PlayableTeam::Black
+            };
+
+            lead_pawns = board.pieces[team as usize][PieceKind::Pawn as usize];
+
+            let mut pawns = lead_pawns;
+
+            while pawns != 0 {
+                squares[size] = pop_lsb(&mut pawns) as usize ^ flip_squares;
+                size += 1;
+            }
+
+            lead_pawn_count = size;
+
+            // The leading pawn is the one nearest the edge, then on the
+            // lowest rank
+            let mut leading = 0;
+
+            for i in 1..lead_pawn_count {
+                if encoding.map_pawns[squares[i]] > encoding.map_pawns[squares[leading]] {
+                    leading = i;
+                }
+            }
+
+            squares.swap(0, leading);
+
+            file = square_file(squares[0]).min(7 - square_file(squares[0]));
+        }
+
+        if self.kind == TableKind::Dtz
+            && usize::from(self.part(stm, file).flags & STM) != stm
+            && !(self.symmetric && !self.has_pawns)
+        {
+            return Some(TableProbe::ChangeStm);
+        }
+
+        let mut remaining = (board.all_pieces[0] | board.all_pieces[1]) ^ lead_pawns;
+
+        while remaining != 0 {
+            if size == MAX_PIECES {
+                return None;
+            }
+
+            let square = pop_lsb(&mut remaining) as usize;
+
+            squares[size] = square ^ flip_squares;
+            pieces[size] = piece_code(board, square) ^ flip_colour;
+            size += 1;
+        }
+
+        if size != self.piece_count {
+            return None;
+        }
+
+        let part = self.part(stm, file);
+
+        // Put the pieces in the order the table indexes them
+        for i in lead_pawn_count..size.saturating_sub(1) {
+            for j in i + 1..size {
+                if part.pieces[i] == pieces[j] {
+                    pieces.swap(i, j);
+                    squares.swap(i, j);
+                    break;
+                }
+            }
+        }
+
+        // The leading piece is always on the queen side
+        if square_file(squares[0]) > 3 {
+            for square in &mut squares[..size] {
+                *square = flip_file(*square);
+            }
+        }
+
+        let mut index;
+
+        if self.has_pawns {
+            index = encoding.lead_pawn_index[lead_pawn_count][squares[0]];
+
+            squares[1..lead_pawn_count].sort_by_key(|&square| encoding.map_pawns[square]);
+
+            for (i, &square) in squares.iter().enumerate().take(lead_pawn_count).skip(1) {
+                index += encoding.binomial[i][encoding.map_pawns[square]];
+            }
+        } else {
+            // Without pawns the leading piece is also below the 5th rank,
+            // and on or below the a1-h8 diagonal
+            if square_rank(squares[0]) > 3 {
+                for square in &mut squares[..size] {
+                    *square = flip_rank(*square);
+                }
+            }
+
+            for i in 0..part.group_length[0] {
+                if off_diagonal(squares[i]) == 0 {
+                    continue;
+                }
+
+                if off_diagonal(squares[i]) > 0 {
+                    for square in &mut squares[i..size] {
+                        *square = ((*square >> 3) | (*square << 3)) & 63;
+                    }
+                }
+
+                break;
+            }
+
+            index = if self.has_unique_pieces {
+                let adjust_1 = usize::from(squares[1] > squares[0]);
+                let adjust_2 =
+                    usize::from(squares[2] > squares[0]) + usize::from(squares[2] > squares[1]);
+
+                (if off_diagonal(squares[0]) != 0 {
+                    (encoding.map_a1d1d4[squares[0]] * 63 + (squares[1] - adjust_1)) * 62
+                        + squares[2]
+                        - adjust_2
+                } else if off_diagonal(squares[1]) != 0 {
+                    (6 * 63 + square_rank(squares[0]) * 28 + encoding.map_b1h1h7[squares[1]]) * 62
+                        + squares[2]
+                        - adjust_2
+                } else if off_diagonal(squares[2]) != 0 {
+                    6 * 63 * 62
+                        + 4 * 28 * 62
+                        + square_rank(squares[0]) * 7 * 28
+                        + (square_rank(squares[1]) - adjust_1) * 28
+                        + encoding.map_b1h1h7[squares[2]]
+                } else {
+                    6 * 63 * 62
+                        + 4 * 28 * 62
+                        + 4 * 7 * 28
+                        + square_rank(squares[0]) * 7 * 6
+                        + (square_rank(squares[1]) - adjust_1) * 6
+                        + (square_rank(squares[2]) - adjust_2)
+                }) as u64
+            } else {
+                encoding.map_kk[encoding.map_a1d1d4[squares[0]]][squares[1]] as u64
+            };
+        }
+
+        index *= part.group_index[0];
+
+        let mut group_start = part.group_length[0];
+        let mut remaining_pawns = self.has_pawns && self.pawn_count[1] > 0;
+        let mut next = 1;
+
+        while part.group_length[next] != 0 {
+            let group_end = group_start + part.group_length[next];
+
+            squares[group_start..group_end].sort_unstable();
+
+            let mut n = 0;
+
+            for i in 0..part.group_length[next] {
+                let square = squares[group_start + i];
+                // Squares taken by earlier groups aren't counted
+                let adjust = squares[..group_start]
+                    .iter()
+                    .filter(|&&earlier| square > earlier)
+                    .count();
+
+                n +=
+                    encoding.binomial[i + 1][square - adjust - if remaining_pawns { 8 } else { 0 }];
+            }
+
+            remaining_pawns = false;
+            index += n * part.group_index[next];
+            group_start = group_end;
+            next += 1;
+        }
+
+        let value = self.decompress(part, index)?;
+
+        Some(TableProbe::Value(self.map_score(file, value, wdl)?))
+    }
+}
+
+/// The number a table uses for the piece on `square`, with the piece's
+/// kind from 1 for a pawn to 6 for a king, plus 8 for black pieces
+fn piece_code(board: &Board, square: usize) -> u8 {
+    let piece = board.piece_at(Position::from_u8(square as u8));
+    let kind = match piece.kind() {
+        PieceKind::Pawn => 1,
+        PieceKind::Knight => 2,
+        PieceKind::Bishop => 3,
+        PieceKind::Rook => 4,
+        PieceKind::Queen => 5,
+        _ => 6,
+    };
+
+    if piece.team() == crate::Team::Black {
+        kind + 8
+    } else {
+        kind
+    }
+}
+
+#[test]
+fn encoding_tables() {
+    let encoding = &*ENCODING;
+
+    // There are 462 ways to place two kings, once symmetry is removed
+    let king_pairs = encoding
+        .map_kk
+        .iter()
+        .flat_map(|squares| squares.iter())
+        .max()
+        .unwrap();
+    assert_eq!(*king_pairs, 461);
+
+    assert_eq!(encoding.map_b1h1h7.iter().max(), Some(&27));
+    assert_eq!(encoding.map_a1d1d4.iter().max(), Some(&9));
+    assert_eq!(encoding.binomial[2][6], 15);
+    assert_eq!(encoding.binomial[5][63], 7_028_847);
+
+    // The pawn nearest the edge on the lowest rank leads
+    assert_eq!(encoding.map_pawns[8], 47);
+    assert_eq!(encoding.map_pawns[15], 46);
+    // The last pair, on d7 and e7, takes the final two
+    assert_eq!(encoding.map_pawns[51], 1);
+    assert_eq!(encoding.map_pawns[52], 0);
+    assert_eq!(encoding.lead_pawns_size[1][0], 6);
+    assert_eq!(encoding.lead_pawns_size[1][3], 6);
+}
@@ -0,0 +1,662 @@
+//! Plays matches between two configurations of the engine, to tell whether
+//! a change makes it stronger.
+//!
+//! Each opening is played twice in a row, with the engines swapping
+//! colours, so neither gains from a lopsided opening. The score is kept
+//! from the first engine's side, and is turned into an Elo difference and
+//! tested with a sequential probability ratio test (SPRT). The SPRT decides
+//! between the first engine being no more than `elo0` stronger, and being
+//! at least `elo1` stronger, as soon as the games played allow.
+
+use core::fmt::{self, Display};
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc, Arc,
+    },
+    thread,
+};
+
+use crate::{
+    analysis::SearchLimit,
+    move_gen::Move,
+    pgn::{GameResult, Pgn},
+    piece::PieceKind,
+    Board, EvalParams, PlayableTeam,
+};
+
+/// A configuration of the engine
+#[derive(Clone, Debug)]
+pub struct EngineConfig {
+    /// The name the engine is given in PGNs
+    pub name: String,
+    pub limit: SearchLimit,
+    /// The parameters the engine evaluates with, if not the installed
+    /// ones. See [`EvalParams::scope`]
+    pub params: Option<&'static EvalParams>,
+}
+
+impl EngineConfig {
+    /// Searches `board`, returning the best move and its score from the
+    /// side to play's perspective
+    fn search(&self, board: &Board) -> Option<(Move, i16)> {
+        let search = || {
+            board
+                .search(self.limit)
+                .get(&board.hash())
+                .map(|best| (best.best_move.clone(), best.evaluation.into_inner()))
+        };
+
+        match self.params {
+            Some(params) => params.scope(search),
+            None => search(),
+        }
+    }
+}
+
+/// When games are ended before checkmate or stalemate
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Adjudication {
+    /// Games reaching this many plies are drawn
+    pub max_plies: usize,
+    /// Games are won once both engines have scored them at least this far
+    /// in one side's favour for `score_plies` plies in a row
+    pub score: i16,
+    pub score_plies: usize,
+}
+
+impl Default for Adjudication {
+    fn default() -> Self {
+        Self {
+            max_plies: 400,
+            score: 1000,
+            score_plies: 6,
+        }
+    }
+}
+
+/// Why a game ended
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Termination {
+    Checkmate,
+    Stalemate,
+    /// A king was left where it could be taken, and was
+    KingCaptured,
+    Repetition,
+    /// Neither side can win, see [`Board::is_known_draw`]
+    KnownDraw,
+    MoveLimit,
+    /// Both engines agreed one side was winning
+    Score,
+    /// The engine to play found no move
+    NoMove,
+}
+
+impl Display for Termination {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Self::Checkmate => "checkmate",
+                Self::Stalemate => "stalemate",
+                Self::KingCaptured => "king captured",
+                Self::Repetition => "threefold repetition",
+                Self::KnownDraw => "known draw",
+                Self::MoveLimit => "move limit",
+                Self::Score => "adjudicated by score",
+                Self::NoMove => "no move found",
+            }
+        )
+    }
+}
+
+/// A game played in a match
+#[derive(Clone, Debug)]
+pub struct MatchGame {
+    /// The game's number, counting from 1, in the order games are started
+    pub round: usize,
+    pub opening: Board,
+    /// Whether the first engine played white
+    pub first_is_white: bool,
+    /// Each move played, with its score from the side to play's perspective
+    pub moves: Vec<(Move, i16)>,
+    pub result: GameResult,
+    pub termination: Termination,
+}
+
+impl MatchGame {
+    /// Writes the game as PGN, with the engines' names and a comment with
+    /// the score of each move
+    #[must_use]
+    pub fn to_pgn(&self, engines: &[EngineConfig; 2]) -> Pgn {
+        let (white, black) = if self.first_is_white {
+            (&engines[0], &engines[1])
+        } else {
+            (&engines[1], &engines[0])
+        };
+
+        let mut pgn = Pgn::from_board(&self.opening);
+        pgn.set_tag("Event", "citron match");
+        pgn.set_tag("Round", &self.round.to_string());
+        pgn.set_tag("White", &white.name);
+        pgn.set_tag("Black", &black.name);
+        pgn.set_tag("Termination", &self.termination.to_string());
+        pgn.set_result(self.result);
+
+        let mut board = self.opening.clone();
+
+        for (played_move, score) in &self.moves {
+            let engine = if (board.to_play() == PlayableTeam::White) == self.first_is_white {
+                &engines[0]
+            } else {
+                &engines[1]
+            };
+
+            pgn.add_move(&board, played_move);
+
+            match engine.limit {
+                SearchLimit::Depth(depth) => pgn.add_evaluation(*score, depth),
+                SearchLimit::Nodes(_) | SearchLimit::Time(_) => {
+                    pgn.add_comment(&format!("{:+.2}", f64::from(*score) / 100.));
+                }
+            }
+
+            match board.make_move(played_move) {
+                Some(next) => board = next,
+                None => break,
+            }
+        }
+
+        pgn
+    }
+}
+
+/// Plays game `round` of a match. Each opening is played in two rounds in
+/// a row, with the first engine white in the first of them
+fn play_game(
+    engines: &[EngineConfig; 2],
+    openings: &[Board],
+    adjudication: &Adjudication,
+    round: usize,
+) -> MatchGame {
+    let opening = openings[(round - 1) / 2 % openings.len()].clone();
+    let first_is_white = (round - 1) % 2 == 0;
+
+    let mut board = opening.clone();
+    let mut history = vec![board.hash()];
+    let mut moves = Vec::new();
+    // The side both engines have scored as winning, and for how many
+    // plies in a row
+    let mut leader = None;
+    let mut leading_plies = 0;
+
+    let (result, termination) = loop {
+        let to_play = board.to_play();
+
+        if board.legal_moves().is_empty() {
+            break if board.in_check(to_play) {
+                (GameResult::win_for(!to_play), Termination::Checkmate)
+            } else {
+                (GameResult::Draw, Termination::Stalemate)
+            };
+        }
+
+        if moves.len() >= adjudication.max_plies {
+            break (GameResult::Draw, Termination::MoveLimit);
+        }
+
+        if board.is_known_draw() {
+            break (GameResult::Draw, Termination::KnownDraw);
+        }
+
+        let engine = if (to_play == PlayableTeam::White) == first_is_white {
+            &engines[0]
+        } else {
+            &engines[1]
+        };
+
+        let (best_move, score) = match engine.search(&board) {
+            Some(best) => best,
+            None => break (GameResult::Draw, Termination::NoMove),
+        };
+
+        if best_move.captured_piece_kind() == PieceKind::King {
+            moves.push((best_move, score));
+            break (GameResult::win_for(to_play), Termination::KingCaptured);
+        }
+
+        let ahead = if score >= adjudication.score {
+            Some(to_play)
+        } else if score <= -adjudication.score {
+            Some(!to_play)
+        } else {
+            None
+        };
+
+        if ahead.is_some() && ahead == leader {
+            leading_plies += 1;
+        } else {
+            leader = ahead;
+            leading_plies = usize::from(ahead.is_some());
+        }
+
+        board = match board.make_move(&best_move) {
+            Some(next) => next,
+            None => break (GameResult::Draw, Termination::NoMove),
+        };
+        moves.push((best_move, score));
+
+        if let Some(leader) = leader {
+            if leading_plies >= adjudication.score_plies {
+                break (GameResult::win_for(leader), Termination::Score);
+            }
+        }
+
+        // Threefold repetition
+        if history.iter().filter(|&&hash| hash == board.hash()).count() >= 2 {
+            break (GameResult::Draw, Termination::Repetition);
+        }
+
+        history.push(board.hash());
+    };
+
+    MatchGame {
+        round,
+        opening,
+        first_is_white,
+        moves,
+        result,
+        termination,
+    }
+}
+
+/// A match between two engine configurations
+pub struct Match {
+    engines: Arc<[EngineConfig; 2]>,
+    openings: Arc<Vec<Board>>,
+    adjudication: Adjudication,
+    threads: usize,
+}
+
+impl Match {
+    /// Creates a match where games start from each of `openings` in turn,
+    /// or from the initial position if there are none, and are played on
+    /// `threads` threads at once
+    #[must_use]
+    pub fn new(
+        engines: [EngineConfig; 2],
+        mut openings: Vec<Board>,
+        adjudication: Adjudication,
+        threads: usize,
+    ) -> Self {
+        if openings.is_empty() {
+            openings.push(Board::new());
+        }
+
+        Self {
+            engines: Arc::new(engines),
+            openings: Arc::new(openings),
+            adjudication,
+            threads: threads.max(1),
+        }
+    }
+    #[must_use]
+    pub fn engines(&self) -> &[EngineConfig; 2] {
+        &self.engines
+    }
+    /// Plays game `round` of the match, counting from 1, on this thread
+    #[must_use]
+    pub fn play_game(&self, round: usize) -> MatchGame {
+        play_game(&self.engines, &self.openings, &self.adjudication, round)
+    }
+    /// Plays `games` games, calling `on_game` with each game as it
+    /// finishes and the score so far. The match stops early if `on_game`
+    /// returns false, without waiting for the games still being played
+    pub fn play(
+        &self,
+        games: usize,
+        mut on_game: impl FnMut(&MatchGame, &MatchScore) -> bool,
+    ) -> MatchScore {
+        let next = Arc::new(AtomicUsize::new(1));
+        let stop = Arc::new(AtomicBool::new(false));
+        let (sender, receiver) = mpsc::channel();
+
+        for _ in 0..self.threads.min(games) {
+            let engines = Arc::clone(&self.engines);
+            let openings = Arc::clone(&self.openings);
+            let adjudication = self.adjudication;
+            let next = Arc::clone(&next);
+            let stop = Arc::clone(&stop);
+            let sender = sender.clone();
+
+            thread::spawn(move || {
+                while !stop.load(Ordering::Relaxed) {
+                    let round = next.fetch_add(1, Ordering::Relaxed);
+
+                    if round > games {
+                        break;
+                    }
+
+                    let game = play_game(&engines, &openings, &adjudication, round);
+
+                    if sender.send(game).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+
+        // The results end once every thread has finished
+        drop(sender);
+
+        let mut score = MatchScore::default();
+
+        for game in receiver {
+            score.add(&game);
+
+            if !on_game(&game, &score) {
+                stop.store(true, Ordering::Relaxed);
+                break;
+            }
+        }
+
+        score
+    }
+}
+
+/// The expected score of a player `elo` stronger than their opponent
+fn expected_score(elo: f64) -> f64 {
+    1. / (1. + 10_f64.powf(-elo / 400.))
+}
+
+/// The Elo difference expected to give a `score`
+fn elo_difference(score: f64) -> f64 {
+    -400. * (1. / score - 1.).log10()
+}
+
+/// The wins, draws and losses of the first engine in a match
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MatchScore {
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32,
+}
+
+impl MatchScore {
+    pub fn add(&mut self, game: &MatchGame) {
+        match (game.result, game.first_is_white) {
+            (GameResult::WhiteWins, true) | (GameResult::BlackWins, false) => self.wins += 1,
+            (GameResult::WhiteWins, false) | (GameResult::BlackWins, true) => self.losses += 1,
+            (GameResult::Draw | GameResult::Unknown, _) => self.draws += 1,
+        }
+    }
+    #[must_use]
+    pub const fn games(&self) -> u32 {
+        self.wins + self.draws + self.losses
+    }
+    /// The fraction of the points the first engine has won
+    #[must_use]
+    pub fn score(&self) -> f64 {
+        (f64::from(self.wins) + f64::from(self.draws) / 2.) / f64::from(self.games())
+    }
+    /// The variance of a single game's score. Each result is counted half
+    /// a game more than it was played, so that a run of the same result
+    /// doesn't give a variance of 0, and with it certainty
+    fn variance(&self) -> f64 {
+        let [wins, draws, losses] =
+            [self.wins, self.draws, self.losses].map(|count| f64::from(count) + 0.5);
+        let games = wins + draws + losses;
+        let score = (wins + draws / 2.) / games;
+
+        (wins * (1. - score).powi(2) + draws * (0.5 - score).powi(2) + losses * score.powi(2))
+            / games
+    }
+    /// The Elo difference between the engines, positive if the first is
+    /// stronger, and the margin of its 95% confidence interval. The
+    /// difference is infinite if every game was won or lost
+    #[must_use]
+    pub fn elo(&self) -> Option<(f64, f64)> {
+        if self.games() == 0 {
+            return None;
+        }
+
+        let score = self.score();
+        let margin = 1.96 * (self.variance() / f64::from(self.games())).sqrt();
+
+        let lower = elo_difference((score - margin).max(0.));
+        let upper = elo_difference((score + margin).min(1.));
+
+        Some((elo_difference(score), (upper - lower) / 2.))
+    }
+}
+
+impl Display for MatchScore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "+{} ={} -{}", self.wins, self.draws, self.losses)
+    }
+}
+
+/// A sequential probability ratio test of whether the first engine is at
+/// least `elo1` stronger (H1), or no more than `elo0` stronger (H0), with
+/// a false positive rate of `alpha` and a false negative rate of `beta`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sprt {
+    pub elo0: f64,
+    pub elo1: f64,
+    pub alpha: f64,
+    pub beta: f64,
+}
+
+impl Default for Sprt {
+    fn default() -> Self {
+        Self {
+            elo0: 0.,
+            elo1: 5.,
+            alpha: 0.05,
+            beta: 0.05,
+        }
+    }
+}
+
+/// What a [`Sprt`] has decided so far
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SprtVerdict {
+    /// H1 is accepted, the first engine is at least `elo1` stronger
+    Stronger,
+    /// H0 is accepted, the first engine is no more than `elo0` stronger
+    NotStronger,
+    /// More games are needed to decide
+    Continue,
+}
+
+impl Sprt {
+    /// The log-likelihood ratio of H1 against H0, using a normal
+    /// approximation of the distribution of game scores
+    #[must_use]
+    pub fn llr(&self, score: &MatchScore) -> f64 {
+        if score.games() == 0 {
+            return 0.;
+        }
+
+        let variance = score.variance();
+        let score0 = expected_score(self.elo0);
+        let score1 = expected_score(self.elo1);
+
+        (score1 - score0) * (2. * score.score() - score0 - score1) * f64::from(score.games())
+            / (2. * variance)
+    }
+    /// The log-likelihood ratios below which H0 is accepted, and above
+    /// which H1 is
+    #[must_use]
+    pub fn bounds(&self) -> (f64, f64) {
+        (
+            (self.beta / (1. - self.alpha)).ln(),
+            ((1. - self.beta) / self.alpha).ln(),
+        )
+    }
+    #[must_use]
+    pub fn verdict(&self, score: &MatchScore) -> SprtVerdict {
+        let llr = self.llr(score);
+        let (lower, upper) = self.bounds();
+
+        if llr >= upper {
+            SprtVerdict::Stronger
+        } else if llr <= lower {
+            SprtVerdict::NotStronger
+        } else {
+            SprtVerdict::Continue
+        }
+    }
+}
+
+#[cfg(test)]
+fn depth_engine(depth: u8) -> EngineConfig {
+    EngineConfig {
+        name: format!("citron depth {}", depth),
+        limit: SearchLimit::Depth(depth),
+        params: None,
+    }
+}
+
+#[test]
+fn elo_difference_test() {
+    let score = MatchScore {
+        wins: 60,
+        draws: 20,
+        losses: 20,
+    };
+    let (elo, margin) = score.elo().unwrap();
+
+    assert!((elo - 147.2).abs() < 0.1, "{}", elo);
+    assert!(margin > 50. && margin < 100., "{}", margin);
+
+    let even = MatchScore {
+        wins: 10,
+        draws: 0,
+        losses: 10,
+    };
+
+    assert_eq!(even.elo().unwrap().0, 0.);
+    assert_eq!(MatchScore::default().elo(), None);
+    assert!((elo_difference(expected_score(35.)) - 35.).abs() < 1e-9);
+}
+
+#[test]
+fn sprt_test() {
+    let sprt = Sprt::default();
+    let (lower, upper) = sprt.bounds();
+
+    assert!((lower + 2.944).abs() < 0.001);
+    assert!((upper - 2.944).abs() < 0.001);
+
+    let winning = MatchScore {
+        wins: 600,
+        draws: 800,
+        losses: 400,
+    };
+    let losing = MatchScore {
+        wins: 400,
+        draws: 800,
+        losses: 600,
+    };
+    let early = MatchScore {
+        wins: 6,
+        draws: 8,
+        losses: 5,
+    };
+
+    assert_eq!(sprt.verdict(&winning), SprtVerdict::Stronger);
+    assert_eq!(sprt.verdict(&losing), SprtVerdict::NotStronger);
+    assert_eq!(sprt.verdict(&early), SprtVerdict::Continue);
+    assert_eq!(sprt.llr(&MatchScore::default()), 0.);
+
+    // Winning every game is enough, given enough games
+    let all_wins = |wins| MatchScore {
+        wins,
+        draws: 0,
+        losses: 0,
+    };
+
+    assert_eq!(sprt.verdict(&all_wins(8)), SprtVerdict::Continue);
+    assert_eq!(sprt.verdict(&all_wins(100)), SprtVerdict::Stronger);
+    assert_eq!(all_wins(8).elo().unwrap(), (f64::INFINITY, f64::INFINITY));
+}
+
+#[test]
+fn adjudicated_games() {
+    let engines = [depth_engine(2), depth_engine(2)];
+    let adjudication = Adjudication {
+        score: 500,
+        score_plies: 2,
+        ..Adjudication::default()
+    };
+
+    // White can take the king straight away
+    let game = play_game(
+        &engines,
+        &[Board::from_fen("4k3/8/8/8/8/8/8/4RK2 w - - 0 1").unwrap()],
+        &adjudication,
+        1,
+    );
+
+    assert_eq!(game.termination, Termination::KingCaptured);
+    assert_eq!(game.result, GameResult::WhiteWins);
+
+    // A queen up is enough to be adjudicated
+    let game = play_game(
+        &engines,
+        &[Board::from_fen("4k3/8/8/8/8/8/8/Q3K3 b - - 0 1").unwrap()],
+        &adjudication,
+        2,
+    );
+
+    assert!(!game.first_is_white);
+    assert_eq!(game.termination, Termination::Score);
+    assert_eq!(game.result, GameResult::WhiteWins);
+    assert_eq!(game.moves.len(), 2);
+
+    let pgn = game.to_pgn(&engines).to_string();
+
+    assert!(pgn.contains("[Termination \"adjudicated by score\"]"));
+    assert!(pgn.contains("[Result \"1-0\"]"));
+
+    let game = play_game(
+        &engines,
+        &[Board::new()],
+        &Adjudication {
+            max_plies: 4,
+            ..adjudication
+        },
+        1,
+    );
+
+    assert_eq!(game.termination, Termination::MoveLimit);
+    assert_eq!(game.moves.len(), 4);
+}
+
+#[test]
+fn play_match() {
+    let engines = [depth_engine(1), depth_engine(2)];
+    let adjudication = Adjudication {
+        max_plies: 10,
+        ..Adjudication::default()
+    };
+    let chess_match = Match::new(engines, Vec::new(), adjudication, 2);
+
+    let mut rounds = Vec::new();
+    let score = chess_match.play(4, |game, _| {
+        rounds.push((game.round, game.first_is_white));
+        true
+    });
+
+    rounds.sort_unstable();
+
+    assert_eq!(score.games(), 4);
+    assert_eq!(rounds, vec![(1, true), (2, false), (3, true), (4, false)]);
+
+    // Stopping after the first game
+    let score = chess_match.play(4, |_, _| false);
+
+    assert_eq!(score.games(), 1);
+}
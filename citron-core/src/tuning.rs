@@ -0,0 +1,266 @@
+use std::{io, path::Path, sync::Arc, thread};
+
+use crate::{Board, EvalParams};
+
+/// Parameters which have no effect on the static evaluation, so tuning
+/// them against labelled positions would only waste time
+const UNTUNED_PARAMETERS: [&str; 1] = ["delta"];
+
+/// A position labelled with the result of the game it was taken from
+#[derive(Clone, Debug)]
+pub struct LabelledPosition {
+    pub board: Board,
+    /// The result from white's perspective, 1 for a win, 0.5 for a draw
+    /// and 0 for a loss
+    pub result: f64,
+}
+
+impl LabelledPosition {
+    /// Parses a line with a FEN followed by the game result, such as
+    /// `<fen> [1.0]`, `<fen> "1/2-1/2";` or `<fen> 0-1`. The move counters
+    /// may be left off the FEN, as they are in EPD
+    #[must_use]
+    pub fn parse(line: &str) -> Option<Self> {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+
+        if tokens.len() < 5 {
+            return None;
+        }
+
+        let result = match tokens[tokens.len() - 1].trim_matches(&['[', ']', '"', ';'][..]) {
+            "1-0" | "1.0" => 1.,
+            "0-1" | "0.0" => 0.,
+            "1/2-1/2" | "0.5" => 0.5,
+            _ => return None,
+        };
+
+        let counters = match tokens.get(4..6) {
+            Some([half_moves, turn])
+                if half_moves.parse::<u32>().is_ok() && turn.parse::<u32>().is_ok() =>
+            {
+                format!("{} {}", half_moves, turn)
+            }
+            _ => "0 1".to_string(),
+        };
+
+        let fen = format!("{} {}", tokens[..4].join(" "), counters);
+
+        Some(Self {
+            board: Board::from_fen(&fen)?,
+            result,
+        })
+    }
+}
+
+/// Reads every labelled position from a file, one per line, skipping any
+/// lines that can't be parsed
+pub fn load_positions(path: impl AsRef<Path>) -> io::Result<Vec<LabelledPosition>> {
+    Ok(std::fs::read_to_string(path)?
+        .lines()
+        .filter_map(LabelledPosition::parse)
+        .collect())
+}
+
+/// Maps an evaluation in centipawns to an expected score between 0 and 1,
+/// where `k` scales how quickly an advantage turns into a win
+#[must_use]
+pub fn sigmoid(evaluation: f64, k: f64) -> f64 {
+    1. / (1. + 10_f64.powf(-k * evaluation / 400.))
+}
+
+/// Tunes [`EvalParams`] with Texel's method, minimising the mean squared
+/// error between each position's game result and the result predicted
+/// from its static evaluation
+pub struct Tuner {
+    positions: Arc<Vec<LabelledPosition>>,
+    threads: usize,
+    k: f64,
+}
+
+impl Tuner {
+    #[must_use]
+    pub fn new(positions: Vec<LabelledPosition>, threads: usize) -> Self {
+        Self {
+            positions: Arc::new(positions),
+            threads: threads.max(1),
+            k: 1.,
+        }
+    }
+    #[must_use]
+    pub const fn k(&self) -> f64 {
+        self.k
+    }
+    /// The mean squared error of the positions evaluated with `params`
+    #[must_use]
+    pub fn error(&self, params: &EvalParams) -> f64 {
+        Self::error_with_k(&self.positions, self.threads, params, self.k)
+    }
+    fn error_with_k(
+        positions: &Arc<Vec<LabelledPosition>>,
+        threads: usize,
+        params: &EvalParams,
+        k: f64,
+    ) -> f64 {
+        if positions.is_empty() {
+            return 0.;
+        }
+
+        let chunk_size = (positions.len() + threads - 1) / threads;
+
+        let handles: Vec<_> = (0..threads)
+            .map(|thread| {
+                let positions = Arc::clone(positions);
+                let params = params.clone();
+
+                thread::spawn(move || {
+                    positions
+                        .iter()
+                        .skip(thread * chunk_size)
+                        .take(chunk_size)
+                        .map(|position| {
+                            let predicted =
+                                sigmoid(f64::from(position.board.evaluate_with(&params)), k);
+
+                            (position.result - predicted).powi(2)
+                        })
+                        .sum::<f64>()
+                })
+            })
+            .collect();
+
+        let total: f64 = handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .sum();
+
+        total / positions.len() as f64
+    }
+    /// Finds the scaling constant which best fits the evaluations of
+    /// `params` to the game results, and uses it for the rest of tuning
+    pub fn optimise_k(&mut self, params: &EvalParams) -> f64 {
+        let mut best = self.k;
+        let mut best_error = Self::error_with_k(&self.positions, self.threads, params, best);
+
+        // Each pass searches either side of the best value so far, with a
+        // step ten times finer than the last
+        for step in [0.1, 0.01, 0.001] {
+            let centre = best;
+
+            for i in -10..=10 {
+                let k = centre + f64::from(i) * step;
+
+                if k <= 0. {
+                    continue;
+                }
+
+                let error = Self::error_with_k(&self.positions, self.threads, params, k);
+
+                if error < best_error {
+                    best = k;
+                    best_error = error;
+                }
+            }
+        }
+
+        self.k = best;
+        best
+    }
+    /// Runs up to `iterations` passes of local search over every parameter,
+    /// nudging each up or down by one while it lowers the error. Stops early
+    /// once a pass makes no improvement. `progress` is called after each
+    /// pass with its number, the error, and the parameters so far
+    pub fn tune(
+        &self,
+        mut params: EvalParams,
+        iterations: usize,
+        mut progress: impl FnMut(usize, f64, &EvalParams),
+    ) -> EvalParams {
+        let mut best_error = self.error(&params);
+        let count = Self::tunable_values(&mut params).len();
+
+        for iteration in 1..=iterations {
+            let mut improved = false;
+
+            for index in 0..count {
+                for delta in [1, -2] {
+                    *Self::tunable_values(&mut params)[index] += delta;
+
+                    let error = self.error(&params);
+
+                    if error < best_error {
+                        best_error = error;
+                        improved = true;
+                        break;
+                    }
+
+                    if delta == -2 {
+                        // Neither direction helped, so restore the original
+                        *Self::tunable_values(&mut params)[index] += 1;
+                    }
+                }
+            }
+
+            progress(iteration, best_error, &params);
+
+            if !improved {
+                break;
+            }
+        }
+
+        params
+    }
+    fn tunable_values(params: &mut EvalParams) -> Vec<&mut i16> {
+        params
+            .fields_mut()
+            .into_iter()
+            .filter(|(name, _)| !UNTUNED_PARAMETERS.contains(name))
+            .flat_map(|(_, values)| values)
+            .collect()
+    }
+}
+
+#[test]
+fn labelled_position_parsing() {
+    let position =
+        LabelledPosition::parse("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1 [0.5]")
+            .unwrap();
+    assert_eq!(position.result, 0.5);
+    assert_eq!(position.board.to_play(), crate::PlayableTeam::Black);
+
+    // EPD positions don't have move counters
+    let position = LabelledPosition::parse("4k3/8/8/8/8/8/4P3/4K3 w - - c9 \"1-0\";").unwrap();
+    assert_eq!(position.result, 1.);
+
+    assert_eq!(
+        LabelledPosition::parse("4k3/8/8/8/8/8/4p3/4K3 w - - 0 1 0-1")
+            .unwrap()
+            .result,
+        0.
+    );
+    assert!(LabelledPosition::parse("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1").is_none());
+}
+
+#[test]
+fn tuning_lowers_error() {
+    let positions = [
+        "4k3/8/8/8/8/8/PPPP4/4K3 w - - 0 1 1-0",
+        "4k3/pppp4/8/8/8/8/8/4K3 w - - 0 1 0-1",
+        "4k3/pp6/8/8/8/8/PP6/4K3 w - - 0 1 1/2-1/2",
+        "4k3/8/8/8/8/8/P7/4K3 w - - 0 1 1/2-1/2",
+    ]
+    .iter()
+    .filter_map(|line| LabelledPosition::parse(line))
+    .collect();
+
+    let mut tuner = Tuner::new(positions, 2);
+    let params = EvalParams::default();
+
+    tuner.optimise_k(&params);
+
+    let error = tuner.error(&params);
+    let mut passes = 0;
+    let tuned = tuner.tune(params, 2, |_, _, _| passes += 1);
+
+    assert!(passes > 0);
+    assert!(tuner.error(&tuned) < error);
+}